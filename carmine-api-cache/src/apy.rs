@@ -1,41 +1,163 @@
-use carmine_api_core::types::PoolStateWithTimestamp;
+use std::env;
+
+use carmine_api_core::{
+    types::{ApyResult, PoolStateWithTimestamp},
+    utils::felt_to_f64,
+};
 
-const WEEK_SECS: i64 = 604800;
 const DAY_SECS: i64 = 86400;
+const YEAR_SECS: f64 = 365.0 * 86400.0;
+// LP token values within this time from the ends of a window are averaged (median)
+const SAMPLE_SECS: i64 = 6 * 3600;
+// shorter history would give unreasonable annualized numbers
+const MIN_INCEPTION_SECS: i64 = DAY_SECS;
+// windows in days used without APY_WINDOWS
+const DEFAULT_WINDOWS: [i64; 3] = [7, 30, 90];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApyWindow {
+    pub name: String,
+    /// None since the first stored state
+    pub seconds: Option<i64>,
+}
 
-fn median(numbers: &mut Vec<i64>) -> f64 {
-    numbers.sort();
-    let mid = numbers.len() / 2;
-    numbers[mid] as f64
+/// Windows from APY_WINDOWS (days separated by commas, eg. "7,30,90"), since inception is always included
+pub fn apy_windows() -> Vec<ApyWindow> {
+    let days: Vec<i64> = match env::var("APY_WINDOWS") {
+        Ok(v) => v
+            .split(',')
+            .filter_map(|d| d.trim().parse::<i64>().ok())
+            .filter(|d| *d > 0)
+            .collect(),
+        Err(_) => DEFAULT_WINDOWS.to_vec(),
+    };
+    days.into_iter()
+        .map(|d| ApyWindow {
+            name: format!("{}d", d),
+            seconds: Some(d * DAY_SECS),
+        })
+        .chain(std::iter::once(ApyWindow {
+            name: "inception".to_string(),
+            seconds: None,
+        }))
+        .collect()
 }
 
-pub fn calculate_apy(state: &Vec<PoolStateWithTimestamp>) -> f64 {
-    if state.len() < 10000 {
-        // cannot calculate for less than a week of data
-        return 0.0;
+/// Median LP token value and timestamp of the samples
+fn median_sample(samples: &mut [(i64, f64)]) -> Option<(i64, f64)> {
+    if samples.is_empty() {
+        return None;
     }
-    let now = state
+    samples.sort_by(|a, b| a.1.total_cmp(&b.1));
+    Some(samples[samples.len() / 2])
+}
+
+fn samples_between(values: &[(i64, f64)], from: i64, to: i64) -> Vec<(i64, f64)> {
+    values
         .iter()
-        .max_by_key(|state| state.timestamp)
-        .unwrap()
-        .timestamp;
-    let mut last_day: Vec<i64> = state
-        .into_iter()
-        .filter(|v| v.timestamp > now - DAY_SECS)
-        .filter_map(|v| v.lp_token_value.as_ref())
-        .filter_map(|v| i64::from_str_radix(v.trim_start_matches("0x"), 16).ok())
-        .collect();
-    let mut week_ago: Vec<i64> = state
-        .into_iter()
-        .filter(|v| v.timestamp < now - WEEK_SECS && v.timestamp > now - WEEK_SECS - DAY_SECS)
-        .filter_map(|v| v.lp_token_value.as_ref())
-        .filter_map(|v| i64::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+        .filter(|(timestamp, _)| *timestamp >= from && *timestamp <= to)
+        .cloned()
+        .collect()
+}
+
+fn insufficient_data(window: &ApyWindow) -> ApyResult {
+    ApyResult {
+        window: window.name.to_owned(),
+        status: "insufficient_data".to_string(),
+        apy: None,
+        period_return: None,
+        from_timestamp: None,
+        to_timestamp: None,
+    }
+}
+
+/// Time weighted return of the LP token, LP token value already excludes
+/// deposits and withdrawals, so only its growth is compared
+fn window_apy(values: &[(i64, f64)], window: &ApyWindow) -> ApyResult {
+    let (first, last) = match (values.first(), values.last()) {
+        (Some(first), Some(last)) => (first.0, last.0),
+        _ => return insufficient_data(window),
+    };
+    let start = match window.seconds {
+        Some(seconds) if last - seconds < first => return insufficient_data(window),
+        Some(seconds) => last - seconds,
+        None => first,
+    };
+
+    let end_sample = median_sample(&mut samples_between(values, last - SAMPLE_SECS, last));
+    let start_sample = median_sample(&mut samples_between(values, start, start + SAMPLE_SECS));
+    let ((from, start_value), (to, end_value)) = match (start_sample, end_sample) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return insufficient_data(window),
+    };
+    let elapsed = to - from;
+    if elapsed <= 0 || (window.seconds.is_none() && elapsed < MIN_INCEPTION_SECS) {
+        return insufficient_data(window);
+    }
+
+    let growth = end_value / start_value;
+    let apy = growth.powf(YEAR_SECS / elapsed as f64) - 1.0;
+
+    ApyResult {
+        window: window.name.to_owned(),
+        status: "ok".to_string(),
+        apy: Some(apy * 100.0),
+        period_return: Some((growth - 1.0) * 100.0),
+        from_timestamp: Some(from),
+        to_timestamp: Some(to),
+    }
+}
+
+pub fn calculate_apy(state: &[PoolStateWithTimestamp], windows: &[ApyWindow]) -> Vec<ApyResult> {
+    let mut values: Vec<(i64, f64)> = state
+        .iter()
+        .filter_map(|v| Some((v.timestamp, felt_to_f64(v.lp_token_value.as_ref()?)?)))
+        .filter(|(_, value)| *value > 0.0)
         .collect();
+    values.sort_by_key(|(timestamp, _)| *timestamp);
+
+    windows.iter().map(|w| window_apy(&values, w)).collect()
+}
 
-    let last_day_median = median(&mut last_day);
-    let week_ago_median = median(&mut week_ago);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carmine_api_core::test_utils::PoolStateBuilder;
 
-    let wpy = last_day_median / week_ago_median as f64;
-    let apy = wpy.powi(52);
-    (apy - 1.0) * 100 as f64 // actual gain in percentage
+    #[test]
+    fn annualizes_by_timestamps() {
+        let start = 1_700_000_000;
+        // 10 days of hourly states, values over i64 with 18 decimals
+        let history: Vec<PoolStateWithTimestamp> = (0..=240)
+            .map(|hour| {
+                let value = 20_000_000_000_000_000_000u128 + hour as u128 * 10u128.pow(16);
+                PoolStateBuilder::new(start + hour * 3600)
+                    .lp_token_value(&format!("{:#x}", value))
+                    .build()
+            })
+            .collect();
+        let windows = vec![
+            ApyWindow {
+                name: "7d".to_string(),
+                seconds: Some(7 * DAY_SECS),
+            },
+            ApyWindow {
+                name: "30d".to_string(),
+                seconds: Some(30 * DAY_SECS),
+            },
+            ApyWindow {
+                name: "inception".to_string(),
+                seconds: None,
+            },
+        ];
+
+        let res = calculate_apy(&history, &windows);
+        assert_eq!(res[0].status, "ok");
+        assert!(res[0].apy.unwrap() > 0.0);
+        assert_eq!(res[1], insufficient_data(&windows[1]));
+        assert_eq!(res[2].from_timestamp, Some(start + 3 * 3600));
+        assert_eq!(res[2].to_timestamp, Some(start + 237 * 3600));
+
+        assert_eq!(calculate_apy(&[], &windows)[2].status, "insufficient_data");
+    }
 }
@@ -1,36 +1,52 @@
 use carmine_api_core::{
-    network::{Network, Protocol},
+    amm_events::{AmmEvent, EventDecodeError},
+    network::{Network, Protocol, REORG_WINDOW},
     pool::{get_all_pools, Pool},
     telegram_bot,
     types::{
-        AppData, IOption, OraclePrice, OraclePriceConcise, PoolStateWithTimestamp,
-        StarkScanEventSettled, TokenPair, TradeHistory,
+        AppData, ApyResult, IOption, OptionWithVolatility, OraclePrice, OraclePriceConcise,
+        PoolStateBucket, PoolStateWithTimestamp, StarkScanEventSettled, TokenPair, TradeHistory,
+        Volatility,
     },
     utils::token_pair_id,
 };
 use carmine_api_db::{
-    get_options, get_options_volatility, get_oracle_prices, get_pool_state, get_protocol_events,
-    get_protocol_events_from_block,
+    get_options, get_options_volatility, get_options_volatility_in_range, get_oracle_prices,
+    get_oracle_prices_in_range, get_pool_state, get_pool_state_in_range, get_protocol_events,
+    get_protocol_events_from_block, DataChange,
 };
-use carmine_api_starknet::carmine::Carmine;
-use std::{collections::HashMap, vec};
+use carmine_api_starknet::{carmine::Carmine, pool_registry::load_pools_from_db};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    vec,
+};
+use tracing::{instrument, warn};
 
 mod apy;
+pub mod state_history;
 
-// Only store Events we know and not ExpireOptionTokenForPool and Upgrade
-const ALLOWED_METHODS: &'static [&'static str; 10] = &[
-    "TradeOpen",
-    "TradeClose",
-    "TradeSettle",
-    "DepositLiquidity",
-    "WithdrawLiquidity",
-    // Cairo1 contracts have this prefix
-    "carmine_protocol::amm_core::amm::AMM::TradeOpen",
-    "carmine_protocol::amm_core::amm::AMM::TradeClose",
-    "carmine_protocol::amm_core::amm::AMM::TradeSettle",
-    "carmine_protocol::amm_core::amm::AMM::DepositLiquidity",
-    "carmine_protocol::amm_core::amm::AMM::WithdrawLiquidity",
-];
+/// Block from which data is read again, everything after the last
+/// known block minus the reorg window, everything if nothing is known
+fn reorg_safe_block(last_block: Option<i64>) -> i64 {
+    match last_block {
+        Some(block) => block - REORG_WINDOW,
+        None => -1,
+    }
+}
+
+/// Replaces items after `from_block` up to `to_block` with items read
+/// again from the DB, items removed by reorg disappear
+fn replace_block_range<T>(
+    known: &mut Vec<T>,
+    new_items: Vec<T>,
+    from_block: i64,
+    to_block: i64,
+    block_number: impl Fn(&T) -> i64,
+) {
+    known.retain(|v| block_number(v) <= from_block || block_number(v) > to_block);
+    known.extend(new_items);
+}
 
 pub struct Cache {
     network: Network,
@@ -40,14 +56,26 @@ pub struct Cache {
     all_non_expired: Vec<String>,
     trade_history: Vec<TradeHistory>,
     pools: Vec<Pool>,
+    option_volatility: Vec<OptionWithVolatility>,
+    state: HashMap<String, Vec<PoolStateWithTimestamp>>,
+    apy: HashMap<String, Vec<ApyResult>>,
+    state_buckets: HashMap<String, HashMap<i64, Vec<PoolStateBucket>>>,
+    oracle_prices: HashMap<String, Vec<OraclePriceConcise>>,
 }
 
 impl Cache {
     pub async fn new(network: Network) -> Self {
         let network = network;
         let carmine = Carmine::new(network);
-        let events = get_protocol_events(&network, &Protocol::CarmineOptions);
-        let options_vec = get_options(&network);
+        // start with empty data if DB fails, it is fetched again on update
+        let events = get_protocol_events(&network, &Protocol::CarmineOptions).unwrap_or_else(|e| {
+            warn!(network = %network, error = %e, "Failed getting events");
+            vec![]
+        });
+        let options_vec = get_options(&network).unwrap_or_else(|e| {
+            warn!(network = %network, error = %e, "Failed getting options");
+            vec![]
+        });
         let options = Cache::options_vec_to_hashmap(options_vec);
         let all_non_expired = vec![];
         let pools = get_all_pools(&network);
@@ -60,29 +88,31 @@ impl Cache {
             all_non_expired,
             trade_history: Vec::new(),
             pools,
+            option_volatility: vec![],
+            state: HashMap::new(),
+            apy: HashMap::new(),
+            state_buckets: HashMap::new(),
+            oracle_prices: HashMap::new(),
         };
 
         cache.trade_history = Cache::generate_trade_history(&mut cache);
         cache.update_all_non_expired().await;
+        cache.update_option_volatility();
+        cache.update_state();
+        cache.update_oracle_prices();
 
         cache
     }
 
     pub fn get_app_data(&self) -> AppData {
-        let all_non_expired = self.get_all_non_expired();
-        let trade_history = self.get_trade_history();
-        let option_volatility = get_options_volatility(&self.network);
-        let state = self.generate_state_hashmap();
-        let apy = self.generate_apy_hashmap();
-        let oracle_prices = self.generate_oracle_prices_hash_map();
-
         AppData {
-            all_non_expired,
-            trade_history,
-            option_volatility,
-            state,
-            apy,
-            oracle_prices,
+            all_non_expired: self.get_all_non_expired(),
+            trade_history: self.get_trade_history(),
+            option_volatility: self.option_volatility.clone(),
+            state: self.state.clone(),
+            apy: self.apy.clone(),
+            state_buckets: self.state_buckets.clone(),
+            oracle_prices: self.oracle_prices.clone(),
         }
     }
 
@@ -101,24 +131,12 @@ impl Cache {
         })
     }
 
-    fn generate_state_hashmap(&self) -> HashMap<String, Vec<PoolStateWithTimestamp>> {
-        self.pools.iter().fold(HashMap::new(), |mut acc, pool| {
-            acc.insert(
-                pool.id.to_string(),
-                get_pool_state(&pool.address, &self.network),
-            );
-            acc
-        })
-    }
-
-    fn generate_apy_hashmap(&self) -> HashMap<String, f64> {
-        self.pools.iter().fold(HashMap::new(), |mut acc, pool| {
-            acc.insert(
-                pool.id.to_string(),
-                self.calculate_apy_for_pool(&pool.address),
-            );
-            acc
-        })
+    fn generate_apy_hashmap(&self) -> HashMap<String, Vec<ApyResult>> {
+        let windows = apy::apy_windows();
+        self.state
+            .iter()
+            .map(|(pool_id, state)| (pool_id.to_owned(), apy::calculate_apy(state, &windows)))
+            .collect()
     }
 
     fn set_oracle_prices_pair(
@@ -141,15 +159,13 @@ impl Cache {
         prices_map.insert(pair_id, data);
     }
 
-    fn generate_oracle_prices_hash_map(&self) -> HashMap<String, Vec<OraclePriceConcise>> {
+    fn generate_oracle_prices_hash_map(
+        &self,
+        oracle_prices: Vec<OraclePrice>,
+    ) -> HashMap<String, Vec<OraclePriceConcise>> {
         let mut map: HashMap<String, Vec<OraclePriceConcise>> = HashMap::new();
-        let oracle_prices = get_oracle_prices(&self.network);
 
-        self.set_oracle_prices_pair(
-            &mut map,
-            token_pair_id(&TokenPair::EthUsdc),
-            oracle_prices.clone(),
-        );
+        self.set_oracle_prices_pair(&mut map, token_pair_id(&TokenPair::EthUsdc), oracle_prices);
 
         map
     }
@@ -158,65 +174,249 @@ impl Cache {
         let mut trade_history: Vec<TradeHistory> = self
             .events
             .iter()
-            .filter(|event| ALLOWED_METHODS.contains(&event.key_name.as_str()))
-            .map(|e| {
-                let token_address = e.data[1].to_owned();
-                let action = String::from(
-                    &e.key_name
-                        // remove prefix in C1 contracts
-                        .replace("carmine_protocol::amm_core::amm::AMM::", ""),
-                );
-                let option = match self.options.get(&token_address) {
-                    Some(v) => Some(v.clone()),
-                    None => None,
-                };
-                let liquidity_pool: Option<String> = if action.as_str() == "DepositLiquidity"
-                    || action.as_str() == "WithdrawLiquidity"
-                {
-                    let matched_pool = self
-                        .pools
-                        .iter()
-                        .find(|&pool| pool.address == token_address.as_str());
-
-                    match matched_pool {
-                        Some(pool) => {
-                            let pool_description = format!(
-                                "{}/{} {}",
-                                pool.base.symbol, pool.quote.symbol, pool.type_
-                            );
-                            Some(pool_description)
-                        }
-                        None => None,
+            .filter_map(|e| {
+                let event = match AmmEvent::decode(&e.key_name, &e.keys, &e.data) {
+                    Ok(v) => v,
+                    // only store Events we know, eg. not Upgrade
+                    Err(EventDecodeError::UnknownEvent(_)) => return None,
+                    Err(err) => {
+                        warn!(event = %e.id, error = %err, "Skipping event");
+                        return None;
                     }
-                } else {
-                    None
                 };
+                let action = event.name().to_owned();
 
-                TradeHistory {
+                let (caller, capital_transfered, tokens_minted, option, liquidity_pool) =
+                    match event {
+                        AmmEvent::TradeOpen(trade)
+                        | AmmEvent::TradeClose(trade)
+                        | AmmEvent::TradeSettle(trade) => (
+                            trade.caller,
+                            trade.capital_transfered.to_hex(),
+                            trade.option_tokens.to_hex(),
+                            self.options.get(&trade.option_token).cloned(),
+                            None,
+                        ),
+                        AmmEvent::DepositLiquidity(liquidity)
+                        | AmmEvent::WithdrawLiquidity(liquidity) => {
+                            let liquidity_pool = self
+                                .pools
+                                .iter()
+                                .find(|&pool| pool.address == liquidity.lp_token.as_str())
+                                .map(|pool| pool.description());
+                            (
+                                liquidity.caller,
+                                liquidity.capital_transfered.to_hex(),
+                                liquidity.lp_tokens.to_hex(),
+                                None,
+                                liquidity_pool,
+                            )
+                        }
+                        // not a user action
+                        AmmEvent::ExpireOptionTokenForPool(_) => return None,
+                    };
+
+                Some(TradeHistory {
                     timestamp: e.timestamp,
+                    transaction_hash: e.transaction_hash.to_owned(),
                     action,
-                    caller: e.data[0].to_owned(),
-                    capital_transfered: e.data[2].to_owned(),
-                    tokens_minted: e.data[4].to_owned(),
+                    caller,
+                    capital_transfered,
+                    tokens_minted,
                     option,
                     liquidity_pool,
-                }
+                })
             })
             .collect::<Vec<TradeHistory>>();
 
-        trade_history.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        // sort by timestamp and transaction hash to have stable order for pagination
+        trade_history.sort_by(|a, b| {
+            (a.timestamp, &a.transaction_hash).cmp(&(b.timestamp, &b.transaction_hash))
+        });
         trade_history
     }
 
-    fn calculate_apy_for_pool(&self, pool_address: &str) -> f64 {
-        let state = get_pool_state(pool_address, &self.network);
-        apy::calculate_apy(&state)
+    /// Only volatilities after the last one in memory are read, like events
+    pub fn update_option_volatility(&mut self) {
+        let last_block = self
+            .option_volatility
+            .iter()
+            .flat_map(|o| o.volatilities.iter().map(|v| v.block_number))
+            .max();
+        self.read_option_volatility(reorg_safe_block(last_block), i64::MAX);
+    }
+
+    /// Replaces volatilities after `from_block` up to `to_block`
+    fn read_option_volatility(&mut self, from_block: i64, to_block: i64) {
+        // keep old volatility if DB fails
+        let mut live = match get_options_volatility_in_range(&self.network, from_block, to_block) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(error = %e, "Failed updating options volatility");
+                return;
+            }
+        };
+
+        // new option could have volatilities before the block, get everything
+        let new_option = live.iter().any(|option| {
+            !self
+                .option_volatility
+                .iter()
+                .any(|o| o.option_address == option.option_address)
+        });
+        if from_block >= 0 && new_option {
+            self.reload_option_volatility();
+            return;
+        }
+
+        let mut known: HashMap<String, Vec<Volatility>> = self
+            .option_volatility
+            .drain(..)
+            .map(|o| (o.option_address, o.volatilities))
+            .collect();
+        // expired options are not live anymore and are dropped
+        for option in live.iter_mut() {
+            let mut volatilities = known.remove(&option.option_address).unwrap_or_default();
+            replace_block_range(
+                &mut volatilities,
+                std::mem::take(&mut option.volatilities),
+                from_block,
+                to_block,
+                |v| v.block_number,
+            );
+            volatilities.sort_by_key(|v| Reverse(v.block_number));
+            option.volatilities = volatilities;
+        }
+        self.option_volatility = live;
+    }
+
+    /// All volatilities, including those stored for older blocks
+    pub fn reload_option_volatility(&mut self) {
+        match get_options_volatility(&self.network) {
+            Ok(v) => self.option_volatility = v,
+            Err(e) => warn!(error = %e, "Failed getting options volatility"),
+        }
+    }
+
+    /// Pool state with APY and buckets calculated from it, only states
+    /// after the last one in memory are read
+    pub fn update_state(&mut self) {
+        self.read_state(reorg_safe_block, i64::MAX);
+    }
+
+    /// Replaces states of each pool after the block returned by `from_block`
+    /// for its last known block, up to `to_block`
+    fn read_state(&mut self, from_block: impl Fn(Option<i64>) -> i64, to_block: i64) {
+        let mut state = HashMap::new();
+        for pool in &self.pools {
+            let known = self.state.remove(&pool.id);
+            let last_block = known
+                .as_ref()
+                .and_then(|s| s.iter().map(|v| v.block_number).max());
+            let from_block = from_block(last_block);
+            match get_pool_state_in_range(&pool.address, from_block, to_block, &self.network) {
+                Ok(new_states) => {
+                    let mut pool_state = known.unwrap_or_default();
+                    replace_block_range(&mut pool_state, new_states, from_block, to_block, |v| {
+                        v.block_number
+                    });
+                    pool_state.sort_by_key(|s| Reverse(s.block_number));
+                    state.insert(pool.id.to_string(), pool_state);
+                }
+                // keep old state of the pool if DB fails
+                Err(e) => {
+                    warn!(pool = %pool.id, error = %e, "Failed getting pool state");
+                    if let Some(v) = known {
+                        state.insert(pool.id.to_string(), v);
+                    }
+                }
+            }
+        }
+        self.set_state(state);
+    }
+
+    /// Whole pool state history, states stored for older blocks
+    /// (eg. holes plugged by the fetcher) are not read by `update_state`
+    pub fn reload_state(&mut self) {
+        let mut state = HashMap::new();
+        for pool in &self.pools {
+            match get_pool_state(&pool.address, &self.network) {
+                Ok(pool_state) => {
+                    state.insert(pool.id.to_string(), pool_state);
+                }
+                // keep old state of the pool if DB fails
+                Err(e) => {
+                    warn!(pool = %pool.id, error = %e, "Failed getting pool state");
+                    if let Some(v) = self.state.remove(&pool.id) {
+                        state.insert(pool.id.to_string(), v);
+                    }
+                }
+            }
+        }
+        self.set_state(state);
+    }
+
+    fn set_state(&mut self, state: HashMap<String, Vec<PoolStateWithTimestamp>>) {
+        self.state = state;
+        self.apy = self.generate_apy_hashmap();
+        self.state_buckets = self
+            .state
+            .iter()
+            .map(|(pool_id, state)| (pool_id.to_owned(), state_history::precompute_buckets(state)))
+            .collect();
+    }
+
+    /// Only prices after the last one in memory are read
+    pub fn update_oracle_prices(&mut self) {
+        let last_block = self
+            .oracle_prices
+            .values()
+            .flatten()
+            .map(|p| p.block_number)
+            .max();
+        self.read_oracle_prices(reorg_safe_block(last_block), i64::MAX);
+    }
+
+    /// Replaces prices after `from_block` up to `to_block`
+    fn read_oracle_prices(&mut self, from_block: i64, to_block: i64) {
+        // keep old prices if DB fails
+        let new_prices = match get_oracle_prices_in_range(&self.network, from_block, to_block) {
+            Ok(v) => self.generate_oracle_prices_hash_map(v),
+            Err(e) => {
+                warn!(error = %e, "Failed getting oracle prices");
+                return;
+            }
+        };
+        for (pair_id, prices) in new_prices {
+            let known = self.oracle_prices.entry(pair_id).or_default();
+            replace_block_range(known, prices, from_block, to_block, |p| p.block_number);
+        }
+    }
+
+    /// All prices, including those stored for older blocks
+    pub fn reload_oracle_prices(&mut self) {
+        match get_oracle_prices(&self.network) {
+            Ok(v) => self.oracle_prices = self.generate_oracle_prices_hash_map(v),
+            Err(e) => warn!(error = %e, "Failed getting oracle prices"),
+        }
+    }
+
+    /// Volatility, pool state and prices of the blocks (inclusive) stored
+    /// after newer ones, eg. holes plugged by the fetcher
+    pub fn backfill(&mut self, from_block: i64, to_block: i64) {
+        // ranges are read after their start, the first block is included
+        let after = from_block - 1;
+        self.read_option_volatility(after, to_block);
+        self.read_state(|_| after, to_block);
+        self.read_oracle_prices(after, to_block);
     }
 
     pub fn update_options(&mut self) {
-        let options_vec = get_options(&self.network);
-        let options = Cache::options_vec_to_hashmap(options_vec);
-        self.options = options;
+        // keep old options if DB fails
+        match get_options(&self.network) {
+            Ok(options_vec) => self.options = Cache::options_vec_to_hashmap(options_vec),
+            Err(e) => warn!(error = %e, "Failed updating options"),
+        }
     }
 
     pub fn update_events(&mut self) {
@@ -225,16 +425,22 @@ impl Cache {
             Some(event) => event.block_number,
             // did not find max block number, get all events
             None => {
-                self.events = get_protocol_events(&self.network, &Protocol::CarmineOptions);
+                match get_protocol_events(&self.network, &Protocol::CarmineOptions) {
+                    Ok(events) => self.events = events,
+                    Err(e) => warn!(error = %e, "Failed getting events"),
+                }
                 return;
             }
         };
-        let new_events = get_protocol_events_from_block(
-            &self.network,
-            &Protocol::CarmineOptions,
-            max_block_number,
-        );
-        self.events.extend(new_events)
+        // recent events are read again, reorg could have replaced them in the DB
+        let from_block = max_block_number - REORG_WINDOW;
+        match get_protocol_events_from_block(&self.network, &Protocol::CarmineOptions, from_block) {
+            Ok(new_events) => {
+                self.events.retain(|event| event.block_number <= from_block);
+                self.events.extend(new_events);
+            }
+            Err(e) => warn!(error = %e, "Failed updating events"),
+        }
     }
 
     pub async fn update_all_non_expired(&mut self) {
@@ -243,10 +449,7 @@ impl Cache {
         match new_non_expired_result {
             Ok(new_non_expired) => self.all_non_expired = new_non_expired,
             Err(e) => {
-                println!(
-                    "Failed getting non expired options: {:?}, \nNetwork {}",
-                    e, &self.network
-                );
+                warn!(network = %self.network, error = ?e, "Failed getting non expired options");
                 match &self.network {
                     Network::Mainnet => {
                         telegram_bot::send_message("Failed getting non expired options MAINNET")
@@ -266,9 +469,95 @@ impl Cache {
     }
 
     pub async fn update(&mut self) {
-        self.update_options();
-        self.update_events();
-        self.update_all_non_expired().await;
-        self.update_trade_history();
+        self.update_sections(&HashSet::from([DataChange::All]))
+            .await;
+    }
+
+    /// Refreshes only data affected by the changes committed to the DB,
+    /// `DataChange::All` reads state, volatility and prices again from the start
+    /// and `DataChange::Backfill` reads only its blocks
+    #[instrument(skip_all, fields(network = %self.network, changes = ?changes))]
+    pub async fn update_sections(&mut self, changes: &HashSet<DataChange>) {
+        let full = changes.contains(&DataChange::All);
+        let changed = |change: DataChange| full || changes.contains(&change);
+        let pools = changed(DataChange::Pools);
+        let options = changed(DataChange::Options);
+        let events = changed(DataChange::Events);
+
+        if pools {
+            // pick up pools registered since the last update, including pools discovered by the fetcher
+            load_pools_from_db(&self.network);
+            self.pools = get_all_pools(&self.network);
+        }
+        if options {
+            self.update_options();
+        }
+        if events {
+            self.update_events();
+        }
+        // trades change premia, new options are traded right away
+        if options || events {
+            self.update_all_non_expired().await;
+        }
+        if options || events || pools {
+            self.update_trade_history();
+        }
+        if full {
+            self.reload_option_volatility();
+            self.reload_state();
+            self.reload_oracle_prices();
+            return;
+        }
+        if options || changed(DataChange::Volatility) {
+            self.update_option_volatility();
+        }
+        if pools || changed(DataChange::PoolState) {
+            self.update_state();
+        }
+        if changed(DataChange::OraclePrices) {
+            self.update_oracle_prices();
+        }
+        let backfill = changes
+            .iter()
+            .filter_map(|change| match change {
+                DataChange::Backfill {
+                    from_block,
+                    to_block,
+                } => Some((*from_block, *to_block)),
+                _ => None,
+            })
+            .reduce(|(from_a, to_a), (from_b, to_b)| (from_a.min(from_b), to_a.max(to_b)));
+        if let Some((from_block, to_block)) = backfill {
+            self.backfill(from_block, to_block);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_reorg_window() {
+        // (block number, value)
+        let mut known = vec![(1, "a"), (150, "b"), (200, "c")];
+        let from_block = reorg_safe_block(Some(200));
+        assert_eq!(from_block, 100);
+
+        // block 200 was removed by reorg, 201 is new
+        replace_block_range(
+            &mut known,
+            vec![(150, "b"), (201, "d")],
+            from_block,
+            i64::MAX,
+            |v| v.0,
+        );
+        assert_eq!(known, vec![(1, "a"), (150, "b"), (201, "d")]);
+
+        // hole at block 2 plugged, newer blocks are kept
+        replace_block_range(&mut known, vec![(2, "e")], 1, 2, |v| v.0);
+        assert_eq!(known, vec![(1, "a"), (150, "b"), (201, "d"), (2, "e")]);
+
+        assert_eq!(reorg_safe_block(None), -1);
     }
 }
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use carmine_api_core::{
+    types::{PoolStateBucket, PoolStateWithTimestamp, ValueRange},
+    utils::felt_to_f64,
+};
+
+// smallest bucket, one block is a few minutes
+const MIN_INTERVAL_SECS: i64 = 60;
+
+/// Intervals bucketed when the cache updates ("1h", "1d" and "1w")
+pub const PRECOMPUTED_INTERVALS: [i64; 3] = [3600, 86400, 604800];
+
+/// Parses interval like "15m", "1h", "1d" or "1w" into seconds
+pub fn parse_interval(interval: &str) -> Option<i64> {
+    let unit_index = interval.find(|c: char| !c.is_ascii_digit())?;
+    let (count, unit) = interval.split_at(unit_index);
+    let unit_secs = match unit {
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 604800,
+        _ => return None,
+    };
+    let secs = count.parse::<i64>().ok()?.checked_mul(unit_secs)?;
+    match secs >= MIN_INTERVAL_SECS {
+        true => Some(secs),
+        false => None,
+    }
+}
+
+/// States with timestamp between `from` and `to` (inclusive)
+pub fn filter_pool_state(
+    state: &[PoolStateWithTimestamp],
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Vec<&PoolStateWithTimestamp> {
+    let from = from.unwrap_or(i64::MIN);
+    let to = to.unwrap_or(i64::MAX);
+    state
+        .iter()
+        .filter(|s| s.timestamp >= from && s.timestamp <= to)
+        .collect()
+}
+
+fn value_range<'a>(values: impl Iterator<Item = &'a String>) -> Option<ValueRange> {
+    let values: Vec<(&String, f64)> = values.filter_map(|v| Some((v, felt_to_f64(v)?))).collect();
+    let first = values.first()?;
+    let last = values.last()?;
+    let min = values.iter().min_by(|a, b| a.1.total_cmp(&b.1))?;
+    let max = values.iter().max_by(|a, b| a.1.total_cmp(&b.1))?;
+    Some(ValueRange {
+        first: first.0.to_owned(),
+        last: last.0.to_owned(),
+        min: min.0.to_owned(),
+        max: max.0.to_owned(),
+    })
+}
+
+fn bucket(timestamp: i64, states: &[&PoolStateWithTimestamp]) -> PoolStateBucket {
+    PoolStateBucket {
+        timestamp,
+        first_block_number: states[0].block_number,
+        last_block_number: states[states.len() - 1].block_number,
+        count: states.len(),
+        lp_token_value: value_range(states.iter().filter_map(|s| s.lp_token_value.as_ref())),
+        locked_cap: value_range(states.iter().map(|s| &s.locked_cap)),
+        unlocked_cap: value_range(states.iter().map(|s| &s.unlocked_cap)),
+        pool_position: value_range(states.iter().filter_map(|s| s.pool_position.as_ref())),
+    }
+}
+
+/// Groups states between `from` and `to` into buckets of `interval` seconds aligned
+/// to the unix epoch, empty buckets are left out
+pub fn bucket_pool_state(
+    state: &[PoolStateWithTimestamp],
+    from: Option<i64>,
+    to: Option<i64>,
+    interval: i64,
+) -> Vec<PoolStateBucket> {
+    let mut states = filter_pool_state(state, from, to);
+    states.sort_by_key(|s| s.block_number);
+
+    let mut buckets: Vec<PoolStateBucket> = vec![];
+    let mut start = 0;
+    for i in 1..=states.len() {
+        let bucket_start = states[start].timestamp - states[start].timestamp.rem_euclid(interval);
+        if i == states.len() || states[i].timestamp >= bucket_start + interval {
+            buckets.push(bucket(bucket_start, &states[start..i]));
+            start = i;
+        }
+    }
+    buckets
+}
+
+/// Buckets of the whole history for each of `PRECOMPUTED_INTERVALS`
+pub fn precompute_buckets(state: &[PoolStateWithTimestamp]) -> HashMap<i64, Vec<PoolStateBucket>> {
+    PRECOMPUTED_INTERVALS
+        .iter()
+        .map(|interval| (*interval, bucket_pool_state(state, None, None, *interval)))
+        .collect()
+}
+
+/// Same result as `bucket_pool_state`, buckets lying whole within `from` and `to`
+/// are taken from `precomputed`, only buckets cut by the range are built from `state`
+pub fn bucket_precomputed(
+    state: &[PoolStateWithTimestamp],
+    precomputed: &[PoolStateBucket],
+    from: Option<i64>,
+    to: Option<i64>,
+    interval: i64,
+) -> Vec<PoolStateBucket> {
+    // start of the first and end of the last bucket inside the range
+    let first = match from {
+        Some(from) => from.saturating_add((interval - from.rem_euclid(interval)) % interval),
+        None => i64::MIN,
+    };
+    let last_end = match to {
+        Some(to) => to - to.saturating_add(1).rem_euclid(interval),
+        None => i64::MAX,
+    };
+    if first > last_end {
+        return bucket_pool_state(state, from, to, interval);
+    }
+
+    let mut buckets = vec![];
+    if let Some(from) = from.filter(|from| *from < first) {
+        buckets.extend(bucket_pool_state(
+            state,
+            Some(from),
+            Some(first - 1),
+            interval,
+        ));
+    }
+    buckets.extend(
+        precomputed
+            .iter()
+            .filter(|b| b.timestamp >= first && b.timestamp <= last_end)
+            .cloned(),
+    );
+    if let Some(to) = to.filter(|to| *to > last_end) {
+        buckets.extend(bucket_pool_state(
+            state,
+            Some(last_end + 1),
+            Some(to),
+            interval,
+        ));
+    }
+    buckets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carmine_api_core::test_utils::PoolStateBuilder;
+
+    fn state(block_number: i64, timestamp: i64, lp_token_value: &str) -> PoolStateWithTimestamp {
+        PoolStateBuilder::new(block_number)
+            .timestamp(timestamp)
+            .lp_token_value(lp_token_value)
+            .build()
+    }
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(parse_interval("1h"), Some(3600));
+        assert_eq!(parse_interval("15m"), Some(900));
+        assert_eq!(parse_interval("2d"), Some(172800));
+        assert_eq!(parse_interval("0m"), None);
+        assert_eq!(parse_interval("h"), None);
+        assert_eq!(parse_interval("1y"), None);
+    }
+
+    #[test]
+    fn buckets_by_interval() {
+        let history = vec![
+            state(4, 7300, "0x5"),
+            state(1, 3600, "0x3"),
+            state(2, 4000, "0x9"),
+            state(3, 7100, "0x4"),
+        ];
+
+        let buckets = bucket_pool_state(&history, None, None, 3600);
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].timestamp, 3600);
+        assert_eq!(buckets[0].count, 3);
+        assert_eq!(
+            buckets[0].lp_token_value,
+            Some(ValueRange {
+                first: "0x3".to_string(),
+                last: "0x4".to_string(),
+                min: "0x3".to_string(),
+                max: "0x9".to_string(),
+            })
+        );
+        assert_eq!(buckets[0].pool_position, None);
+        assert_eq!(buckets[1].timestamp, 7200);
+        assert_eq!(buckets[1].first_block_number, 4);
+
+        let filtered = bucket_pool_state(&history, Some(4000), Some(7200), 3600);
+        assert_eq!(filtered[0].count, 2);
+        assert_eq!(filtered.len(), 1);
+    }
+
+    #[test]
+    fn precomputed_buckets_match() {
+        let history: Vec<PoolStateWithTimestamp> = (0..50)
+            .map(|i| state(i, i * 1000, &format!("{:#x}", i % 7)))
+            .collect();
+        let precomputed = &precompute_buckets(&history)[&3600];
+
+        let ranges = [
+            (None, None),
+            (Some(4000), Some(7200)),
+            (Some(3600), Some(10799)),
+            (Some(100), None),
+            (None, Some(30000)),
+            (Some(5000), Some(5500)),
+        ];
+        for (from, to) in ranges {
+            assert_eq!(
+                bucket_precomputed(&history, precomputed, from, to, 3600),
+                bucket_pool_state(&history, from, to, 3600)
+            );
+        }
+    }
+}
@@ -0,0 +1,4 @@
+ALTER TABLE
+  blocks
+ADD
+  COLUMN block_hash Text;
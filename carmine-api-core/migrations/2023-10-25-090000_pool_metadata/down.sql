@@ -0,0 +1,9 @@
+ALTER TABLE
+  pools DROP COLUMN pool_id,
+  DROP COLUMN option_type,
+  DROP COLUMN base_address,
+  DROP COLUMN base_symbol,
+  DROP COLUMN base_decimals,
+  DROP COLUMN quote_address,
+  DROP COLUMN quote_symbol,
+  DROP COLUMN quote_decimals;
@@ -0,0 +1,18 @@
+ALTER TABLE
+  pools
+ADD
+  COLUMN pool_id Text,
+ADD
+  COLUMN option_type SmallInt,
+ADD
+  COLUMN base_address Text,
+ADD
+  COLUMN base_symbol Text,
+ADD
+  COLUMN base_decimals SmallInt,
+ADD
+  COLUMN quote_address Text,
+ADD
+  COLUMN quote_symbol Text,
+ADD
+  COLUMN quote_decimals SmallInt;
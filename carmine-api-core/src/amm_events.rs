@@ -0,0 +1,350 @@
+use std::fmt;
+
+use crate::utils::felt_to_u128;
+
+// Cairo1 contracts have this prefix
+pub const CAIRO_1_EVENT_PREFIX: &str = "carmine_protocol::amm_core::amm::AMM::";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uint256 {
+    pub low: u128,
+    pub high: u128,
+}
+
+impl Uint256 {
+    pub fn from_felts(low: &str, high: &str) -> Option<Self> {
+        Some(Uint256 {
+            low: felt_to_u128(low)?,
+            high: felt_to_u128(high)?,
+        })
+    }
+
+    pub fn to_hex(&self) -> String {
+        match self.high {
+            0 => format!("{:#x}", self.low),
+            high => format!("{:#x}{:032x}", high, self.low),
+        }
+    }
+}
+
+/// TradeOpen, TradeClose and TradeSettle
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeEvent {
+    pub caller: String,
+    pub option_token: String,
+    pub capital_transfered: Uint256,
+    /// minted for TradeOpen, burned for TradeClose and TradeSettle
+    pub option_tokens: Uint256,
+}
+
+/// DepositLiquidity and WithdrawLiquidity
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiquidityEvent {
+    pub caller: String,
+    pub lp_token: String,
+    pub capital_transfered: Uint256,
+    /// minted for DepositLiquidity, burned for WithdrawLiquidity
+    pub lp_tokens: Uint256,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpireOptionTokenForPoolEvent {
+    pub lptoken_address: String,
+    pub option_side: u8,
+    pub strike_price: String,
+    pub maturity: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AmmEvent {
+    TradeOpen(TradeEvent),
+    TradeClose(TradeEvent),
+    TradeSettle(TradeEvent),
+    DepositLiquidity(LiquidityEvent),
+    WithdrawLiquidity(LiquidityEvent),
+    ExpireOptionTokenForPool(ExpireOptionTokenForPoolEvent),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventDecodeError {
+    /// event is not emitted by the AMM or we do not handle it (eg. "Upgraded")
+    UnknownEvent(String),
+    WrongLength {
+        event: String,
+        keys: usize,
+        data: usize,
+    },
+    InvalidFelt {
+        event: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for EventDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventDecodeError::UnknownEvent(name) => write!(f, "Unknown event {}", name),
+            EventDecodeError::WrongLength { event, keys, data } => write!(
+                f,
+                "Unexpected layout of {}: {} keys, {} data fields",
+                event, keys, data
+            ),
+            EventDecodeError::InvalidFelt { event, value } => {
+                write!(f, "Invalid value \"{}\" in {}", value, event)
+            }
+        }
+    }
+}
+
+fn parse_felt<T: TryFrom<u128>>(event: &str, value: &str) -> Result<T, EventDecodeError> {
+    match felt_to_u128(value).and_then(|v| T::try_from(v).ok()) {
+        Some(v) => Ok(v),
+        None => Err(EventDecodeError::InvalidFelt {
+            event: event.to_owned(),
+            value: value.to_owned(),
+        }),
+    }
+}
+
+fn parse_u256(event: &str, low: &str, high: &str) -> Result<Uint256, EventDecodeError> {
+    match Uint256::from_felts(low, high) {
+        Some(v) => Ok(v),
+        None => Err(EventDecodeError::InvalidFelt {
+            event: event.to_owned(),
+            value: format!("{}, {}", low, high),
+        }),
+    }
+}
+
+/// caller, token address, capital transfered and amount of tokens
+type TransferFields = (String, String, Uint256, Uint256);
+
+/// Cairo 0: keys [selector], data [caller, token, capital.low, capital.high, tokens.low, tokens.high]
+fn decode_transfer_cairo_0(
+    name: &str,
+    keys: &[String],
+    data: &[String],
+) -> Result<TransferFields, EventDecodeError> {
+    if keys.len() > 1 || data.len() != 6 {
+        return Err(EventDecodeError::WrongLength {
+            event: name.to_owned(),
+            keys: keys.len(),
+            data: data.len(),
+        });
+    }
+    Ok((
+        data[0].to_owned(),
+        data[1].to_owned(),
+        parse_u256(name, &data[2], &data[3])?,
+        parse_u256(name, &data[4], &data[5])?,
+    ))
+}
+
+/// Cairo 1: caller and token are either keys [selector, caller, token] with data
+/// [capital.low, capital.high, tokens.low, tokens.high] or part of data like in Cairo 0
+fn decode_transfer_cairo_1(
+    name: &str,
+    keys: &[String],
+    data: &[String],
+) -> Result<TransferFields, EventDecodeError> {
+    if keys.len() == 3 && data.len() == 4 {
+        return Ok((
+            keys[1].to_owned(),
+            keys[2].to_owned(),
+            parse_u256(name, &data[0], &data[1])?,
+            parse_u256(name, &data[2], &data[3])?,
+        ));
+    }
+    decode_transfer_cairo_0(name, keys, data)
+}
+
+/// Cairo 0: [lptoken_address, option_side, strike_price, maturity]
+/// Cairo 1: [lptoken_address, option_side, strike_price.mag, strike_price.sign, maturity]
+fn decode_expire(
+    name: &str,
+    data: &[String],
+    is_cairo_1: bool,
+) -> Result<ExpireOptionTokenForPoolEvent, EventDecodeError> {
+    let expected_length = if is_cairo_1 { 5 } else { 4 };
+    if data.len() != expected_length {
+        return Err(EventDecodeError::WrongLength {
+            event: name.to_owned(),
+            keys: 0,
+            data: data.len(),
+        });
+    }
+    if is_cairo_1 {
+        // strike price is never negative
+        let sign: u8 = parse_felt(name, &data[3])?;
+        if sign != 0 {
+            return Err(EventDecodeError::InvalidFelt {
+                event: name.to_owned(),
+                value: data[3].to_owned(),
+            });
+        }
+    }
+    Ok(ExpireOptionTokenForPoolEvent {
+        lptoken_address: data[0].to_owned(),
+        option_side: parse_felt(name, &data[1])?,
+        strike_price: data[2].to_owned(),
+        maturity: parse_felt(name, &data[expected_length - 1])?,
+    })
+}
+
+impl AmmEvent {
+    /// Decodes event by its name, handles both Cairo 0 and Cairo 1 (prefixed) events
+    pub fn decode(
+        key_name: &str,
+        keys: &[String],
+        data: &[String],
+    ) -> Result<AmmEvent, EventDecodeError> {
+        let (name, is_cairo_1) = match key_name.strip_prefix(CAIRO_1_EVENT_PREFIX) {
+            Some(name) => (name, true),
+            None => (key_name, false),
+        };
+
+        let decode_transfer = if is_cairo_1 {
+            decode_transfer_cairo_1
+        } else {
+            decode_transfer_cairo_0
+        };
+
+        let trade = |name: &str| -> Result<TradeEvent, EventDecodeError> {
+            let (caller, option_token, capital_transfered, option_tokens) =
+                decode_transfer(name, keys, data)?;
+            Ok(TradeEvent {
+                caller,
+                option_token,
+                capital_transfered,
+                option_tokens,
+            })
+        };
+
+        let liquidity = |name: &str| -> Result<LiquidityEvent, EventDecodeError> {
+            let (caller, lp_token, capital_transfered, lp_tokens) =
+                decode_transfer(name, keys, data)?;
+            Ok(LiquidityEvent {
+                caller,
+                lp_token,
+                capital_transfered,
+                lp_tokens,
+            })
+        };
+
+        match name {
+            "TradeOpen" => Ok(AmmEvent::TradeOpen(trade(key_name)?)),
+            "TradeClose" => Ok(AmmEvent::TradeClose(trade(key_name)?)),
+            "TradeSettle" => Ok(AmmEvent::TradeSettle(trade(key_name)?)),
+            "DepositLiquidity" => Ok(AmmEvent::DepositLiquidity(liquidity(key_name)?)),
+            "WithdrawLiquidity" => Ok(AmmEvent::WithdrawLiquidity(liquidity(key_name)?)),
+            "ExpireOptionTokenForPool" => Ok(AmmEvent::ExpireOptionTokenForPool(decode_expire(
+                key_name, data, is_cairo_1,
+            )?)),
+            _ => Err(EventDecodeError::UnknownEvent(key_name.to_owned())),
+        }
+    }
+
+    /// Event name without the Cairo 1 prefix
+    pub fn name(&self) -> &'static str {
+        match self {
+            AmmEvent::TradeOpen(_) => "TradeOpen",
+            AmmEvent::TradeClose(_) => "TradeClose",
+            AmmEvent::TradeSettle(_) => "TradeSettle",
+            AmmEvent::DepositLiquidity(_) => "DepositLiquidity",
+            AmmEvent::WithdrawLiquidity(_) => "WithdrawLiquidity",
+            AmmEvent::ExpireOptionTokenForPool(_) => "ExpireOptionTokenForPool",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn cairo_0_trade() {
+        let keys = strings(&["0x1"]);
+        let data = strings(&["0xca11e7", "0x0971", "0x64", "0x0", "0x3e8", "0x1"]);
+        let event = AmmEvent::decode("TradeOpen", &keys, &data).unwrap();
+        let trade = match event {
+            AmmEvent::TradeOpen(trade) => trade,
+            _ => panic!("wrong variant"),
+        };
+        assert_eq!(trade.caller, "0xca11e7");
+        assert_eq!(trade.option_token, "0x0971");
+        assert_eq!(trade.capital_transfered.to_hex(), "0x64");
+        assert_eq!(
+            trade.option_tokens.to_hex(),
+            "0x1000000000000000000000000000003e8"
+        );
+    }
+
+    #[test]
+    fn cairo_1_keyed_liquidity() {
+        let keys = strings(&["0x1", "0xca11e7", "0x1b"]);
+        let data = strings(&["0x10", "0x0", "0x20", "0x0"]);
+        let event = AmmEvent::decode(
+            "carmine_protocol::amm_core::amm::AMM::WithdrawLiquidity",
+            &keys,
+            &data,
+        )
+        .unwrap();
+        assert_eq!(event.name(), "WithdrawLiquidity");
+        assert_eq!(
+            event,
+            AmmEvent::WithdrawLiquidity(LiquidityEvent {
+                caller: "0xca11e7".to_string(),
+                lp_token: "0x1b".to_string(),
+                capital_transfered: Uint256 { low: 16, high: 0 },
+                lp_tokens: Uint256 { low: 32, high: 0 },
+            })
+        );
+    }
+
+    #[test]
+    fn expire_option() {
+        let c0 = strings(&["0x1b", "0x1", "0x2", "0x64ec5f7f"]);
+        let c1 = strings(&["0x1b", "0x1", "0x2", "0x0", "0x64ec5f7f"]);
+        let expected = AmmEvent::ExpireOptionTokenForPool(ExpireOptionTokenForPoolEvent {
+            lptoken_address: "0x1b".to_string(),
+            option_side: 1,
+            strike_price: "0x2".to_string(),
+            maturity: 0x64ec5f7f,
+        });
+        assert_eq!(
+            AmmEvent::decode("ExpireOptionTokenForPool", &[], &c0).unwrap(),
+            expected
+        );
+        assert_eq!(
+            AmmEvent::decode(
+                "carmine_protocol::amm_core::amm::AMM::ExpireOptionTokenForPool",
+                &[],
+                &c1
+            )
+            .unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn malformed_events() {
+        let short = strings(&["0xca11e7", "0x0971", "0x64"]);
+        assert!(matches!(
+            AmmEvent::decode("TradeClose", &[], &short),
+            Err(EventDecodeError::WrongLength { data: 3, .. })
+        ));
+        let invalid = strings(&["0xca11e7", "0x0971", "0xzz", "0x0", "0x1", "0x0"]);
+        assert!(matches!(
+            AmmEvent::decode("TradeSettle", &[], &invalid),
+            Err(EventDecodeError::InvalidFelt { .. })
+        ));
+        assert!(matches!(
+            AmmEvent::decode("Upgraded", &[], &[]),
+            Err(EventDecodeError::UnknownEvent(_))
+        ));
+    }
+}
@@ -1,6 +1,11 @@
+pub mod amm_events;
+pub mod metrics;
 pub mod network;
 pub mod pool;
 pub mod schema;
 pub mod telegram_bot;
+pub mod telemetry;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 pub mod types;
 pub mod utils;
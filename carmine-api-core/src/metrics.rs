@@ -0,0 +1,181 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+
+use tracing::warn;
+
+use crate::network::Network;
+
+// RPC calls and DB writes take way longer than API requests
+const FAST_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+const SLOW_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "carmine_http_requests_total",
+        "HTTP requests by route, method and status",
+        &["route", "method", "status"]
+    )
+    .unwrap();
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "carmine_http_request_duration_seconds",
+        "HTTP request latency by route",
+        &["route", "method"],
+        FAST_BUCKETS.to_vec()
+    )
+    .unwrap();
+    static ref CACHE_LAST_UPDATE: IntGaugeVec = register_int_gauge_vec!(
+        "carmine_cache_last_update_timestamp_seconds",
+        "Unix time the network's app state was last replaced",
+        &["network"]
+    )
+    .unwrap();
+    static ref DB_QUERY_DURATION: HistogramVec = register_histogram_vec!(
+        "carmine_db_query_duration_seconds",
+        "DB query duration by statement kind",
+        &["network", "operation", "result"],
+        FAST_BUCKETS.to_vec()
+    )
+    .unwrap();
+    static ref RPC_CALLS: IntCounterVec = register_int_counter_vec!(
+        "carmine_rpc_calls_total",
+        "RPC calls by node and result",
+        &["node", "network", "result"]
+    )
+    .unwrap();
+    static ref RPC_CALL_DURATION: HistogramVec = register_histogram_vec!(
+        "carmine_rpc_call_duration_seconds",
+        "RPC call latency by node",
+        &["node", "network"],
+        SLOW_BUCKETS.to_vec()
+    )
+    .unwrap();
+    static ref STARKSCAN_PAGES: IntCounterVec = register_int_counter_vec!(
+        "carmine_starkscan_pages_total",
+        "Starkscan event pages fetched by result",
+        &["result"]
+    )
+    .unwrap();
+    static ref LAST_PROCESSED_BLOCK: IntGaugeVec = register_int_gauge_vec!(
+        "carmine_last_processed_block",
+        "Last block stored to the DB by data type",
+        &["network", "data"]
+    )
+    .unwrap();
+    static ref BLOCK_LAG: IntGaugeVec = register_int_gauge_vec!(
+        "carmine_rpc_block_lag",
+        "Blocks the node is behind the most recent node",
+        &["node"]
+    )
+    .unwrap();
+}
+
+fn network_label(network: &Network) -> &'static str {
+    match network {
+        Network::Mainnet => "mainnet",
+        Network::Testnet => "testnet",
+    }
+}
+
+fn result_label(ok: bool) -> &'static str {
+    match ok {
+        true => "ok",
+        false => "error",
+    }
+}
+
+/// `route` is the matched pattern, not the path, so that addresses
+/// do not end up as labels
+pub fn observe_http_request(route: &str, method: &str, status: u16, duration: Duration) {
+    HTTP_REQUESTS
+        .with_label_values(&[route, method, &status.to_string()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[route, method])
+        .observe(duration.as_secs_f64());
+}
+
+/// Cache age is `time() - carmine_cache_last_update_timestamp_seconds`
+pub fn set_cache_updated(network: &Network) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    CACHE_LAST_UPDATE
+        .with_label_values(&[network_label(network)])
+        .set(now);
+}
+
+/// `operation` is the SQL statement kind, eg. "select" or "insert"
+pub fn observe_db_query(network: &Network, operation: &str, ok: bool, duration: Duration) {
+    DB_QUERY_DURATION
+        .with_label_values(&[network_label(network), operation, result_label(ok)])
+        .observe(duration.as_secs_f64());
+}
+
+pub fn observe_rpc_call(node: &str, network: &Network, ok: bool, duration: Duration) {
+    let network = network_label(network);
+    RPC_CALLS
+        .with_label_values(&[node, network, result_label(ok)])
+        .inc();
+    RPC_CALL_DURATION
+        .with_label_values(&[node, network])
+        .observe(duration.as_secs_f64());
+}
+
+/// `result` is "ok", "error" or "retry" when Starkscan returned a message
+pub fn inc_starkscan_pages(result: &str) {
+    STARKSCAN_PAGES.with_label_values(&[result]).inc();
+}
+
+/// `data` is what was stored, eg. "amm_state" or "events"
+pub fn set_last_processed_block(network: &Network, data: &str, block_number: i64) {
+    LAST_PROCESSED_BLOCK
+        .with_label_values(&[network_label(network), data])
+        .set(block_number);
+}
+
+pub fn set_block_lag(node: &str, lag: i64) {
+    BLOCK_LAG.with_label_values(&[node]).set(lag);
+}
+
+/// All metrics of the process in the Prometheus text format
+pub fn render() -> String {
+    let mut buffer = vec![];
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        warn!(error = %e, "Failed encoding metrics");
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+/// Content type of `render` output
+pub fn content_type() -> String {
+    TextEncoder::new().format_type().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_recorded_metrics() {
+        observe_rpc_call(
+            "Infura",
+            &Network::Mainnet,
+            false,
+            Duration::from_millis(20),
+        );
+        set_last_processed_block(&Network::Testnet, "amm_state", 42);
+
+        let text = render();
+        assert!(text.contains(
+            "carmine_rpc_calls_total{network=\"mainnet\",node=\"Infura\",result=\"error\"} 1"
+        ));
+        assert!(text
+            .contains("carmine_last_processed_block{data=\"amm_state\",network=\"testnet\"} 42"));
+    }
+}
@@ -1,16 +1,12 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
+
+/// How many blocks back stored data can still be orphaned by a reorg,
+/// the fetcher checks these blocks and the API reads them again
+pub const REORG_WINDOW: i64 = 100;
 
 // Starknet addresses
-const TESTNET_CALL_LP_ADDRESS: &str =
-    "0x5b3bafb3afa300eeea9415d049b8694c6dc3e1e0e07d04d8f17766cb49745e";
-const TESTNET_PUT_LP_ADDRESS: &str =
-    "0x19c7d26452843d6458eac8027e8e0a4699e072c36280c6ef842297fd246d8d1";
 const TESTNET_CONTRACT_ADDRESS: &str =
     "0x070eb12729e80d751e999557c9c1b0754a0c7933fbde0f310b99c8b6886e139e";
-const MAINNET_CALL_LP_ADDRESS: &str =
-    "0x7aba50fdb4e024c1ba63e2c60565d0fd32566ff4b18aa5818fc80c30e749024";
-const MAINNET_PUT_LP_ADDRESS: &str =
-    "0x18a6abca394bd5f822cfa5f88783c01b13e593d1603e7b41b00d31d2ea4827a";
 const MAINNET_CONTRACT_ADDRESS: &str =
     "0x76dbabc4293db346b0a56b29b6ea9fe18e93742c73f12348c8747ecfc1050aa";
 
@@ -157,7 +153,7 @@ impl fmt::Display for Protocol {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Network {
     Testnet,
     Mainnet,
@@ -172,17 +168,15 @@ impl fmt::Display for Network {
     }
 }
 
-pub fn call_lp_address(network: &Network) -> &'static str {
-    match &network {
-        Network::Mainnet => MAINNET_CALL_LP_ADDRESS,
-        Network::Testnet => TESTNET_CALL_LP_ADDRESS,
-    }
-}
+impl FromStr for Network {
+    type Err = String;
 
-pub fn put_lp_address(network: &Network) -> &'static str {
-    match &network {
-        Network::Mainnet => MAINNET_PUT_LP_ADDRESS,
-        Network::Testnet => TESTNET_PUT_LP_ADDRESS,
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            unknown => Err(format!("Unknown network {}", unknown)),
+        }
     }
 }
 
@@ -377,14 +371,6 @@ mod tests {
 
     #[test]
     fn addresses_mainnet() {
-        assert_eq!(
-            call_lp_address(&Network::Mainnet),
-            "0x7aba50fdb4e024c1ba63e2c60565d0fd32566ff4b18aa5818fc80c30e749024"
-        );
-        assert_eq!(
-            put_lp_address(&Network::Mainnet),
-            "0x18a6abca394bd5f822cfa5f88783c01b13e593d1603e7b41b00d31d2ea4827a"
-        );
         assert_eq!(
             amm_address(&Network::Mainnet),
             "0x76dbabc4293db346b0a56b29b6ea9fe18e93742c73f12348c8747ecfc1050aa"
@@ -397,14 +383,6 @@ mod tests {
 
     #[test]
     fn addresses_testnet() {
-        assert_eq!(
-            call_lp_address(&Network::Testnet),
-            "0x3b176f8e5b4c9227b660e49e97f2d9d1756f96e5878420ad4accd301dd0cc17"
-        );
-        assert_eq!(
-            put_lp_address(&Network::Testnet),
-            "0x30fe5d12635ed696483a824eca301392b3f529e06133b42784750503a24972"
-        );
         assert_eq!(
             amm_address(&Network::Testnet),
             "0x42a7d485171a01b8c38b6b37e0092f0f096e9d3f945c50c77799171916f5a54"
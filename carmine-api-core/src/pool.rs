@@ -1,24 +1,89 @@
 use crate::network::Network;
-use std::fmt;
+use crate::types::Pool as DbPool;
+use crate::utils::same_felt;
+use arc_swap::ArcSwap;
+use serde::Deserialize;
+use std::{
+    env, fmt, fs,
+    sync::{Arc, OnceLock},
+};
+use tracing::warn;
+
+// pools known at build time, POOLS_CONFIG replaces them
+const DEFAULT_POOLS_CONFIG: &str = include_str!("../pools.json");
 
-#[derive(Debug)]
+static REGISTRY: OnceLock<PoolRegistry> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
-    pub address: &'static str,
-    pub symbol: &'static str,
+    pub address: String,
+    pub symbol: String,
     pub decimals: u8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Pool {
-    pub address: &'static str,
+    pub address: String,
     pub network: Network,
     pub type_: Type,
     pub base: Token,
     pub quote: Token,
-    pub id: &'static str,
+    pub id: String,
 }
 
-#[derive(Debug)]
+impl Pool {
+    /// Human readable pool name, eg. "ETH/USDC Call"
+    pub fn description(&self) -> String {
+        format!("{}/{} {}", self.base.symbol, self.quote.symbol, self.type_)
+    }
+
+    /// Oracle price pair of the pool, eg. "eth-usdc"
+    pub fn pair_id(&self) -> String {
+        format!("{}-{}", self.base.symbol, self.quote.symbol).to_lowercase()
+    }
+
+    /// Pool stored in the DB, None if the row has no token metadata
+    pub fn from_db(row: &DbPool, network: Network) -> Option<Pool> {
+        let type_ = match row.option_type? {
+            0 => Type::Call,
+            1 => Type::Put,
+            _ => return None,
+        };
+        Some(Pool {
+            address: row.lp_address.to_owned(),
+            network,
+            type_,
+            base: Token {
+                address: row.base_address.to_owned()?,
+                symbol: row.base_symbol.to_owned()?,
+                decimals: u8::try_from(row.base_decimals?).ok()?,
+            },
+            quote: Token {
+                address: row.quote_address.to_owned()?,
+                symbol: row.quote_symbol.to_owned()?,
+                decimals: u8::try_from(row.quote_decimals?).ok()?,
+            },
+            id: row.pool_id.to_owned()?,
+        })
+    }
+
+    pub fn to_db(&self) -> DbPool {
+        DbPool {
+            lp_address: self.address.to_owned(),
+            pool_id: Some(self.id.to_owned()),
+            option_type: Some(self.type_ as i16),
+            base_address: Some(self.base.address.to_owned()),
+            base_symbol: Some(self.base.symbol.to_owned()),
+            base_decimals: Some(self.base.decimals.into()),
+            quote_address: Some(self.quote.address.to_owned()),
+            quote_symbol: Some(self.quote.symbol.to_owned()),
+            quote_decimals: Some(self.quote.decimals.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Type {
     Call = 0,
     Put = 1,
@@ -33,113 +98,219 @@ impl fmt::Display for Type {
     }
 }
 
-const TESTNET_ETH: Token = Token {
-    address: "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7",
-    decimals: 18,
-    symbol: "ETH",
-};
+#[derive(Debug, Deserialize)]
+struct TokenConfig {
+    network: String,
+    symbol: String,
+    address: String,
+    decimals: u8,
+}
 
-const TESTNET_USDC: Token = Token {
-    address: "0x5a643907b9a4bc6a55e9069c4fd5fd1f5c79a22470690f75556c4736e34426",
-    decimals: 6,
-    symbol: "USDC",
-};
+#[derive(Debug, Deserialize)]
+struct PoolConfig {
+    network: String,
+    id: String,
+    address: String,
+    #[serde(rename = "type")]
+    type_: Type,
+    // token symbols
+    base: String,
+    quote: String,
+}
 
-const TESTNET_BTC: Token = Token {
-    address: "0x12d537dc323c439dc65c976fad242d5610d27cfb5f31689a0a319b8be7f3d56",
-    decimals: 8,
-    symbol: "BTC",
-};
+#[derive(Debug, Deserialize)]
+struct PoolsConfig {
+    tokens: Vec<TokenConfig>,
+    pools: Vec<PoolConfig>,
+}
 
-const MAINNET_ETH: Token = Token {
-    address: "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7",
-    decimals: 18,
-    symbol: "ETH",
-};
+/// Parses JSON with "tokens" and "pools" referencing the tokens by symbol
+pub fn parse_pools_config(content: &str) -> Result<Vec<Pool>, String> {
+    let config: PoolsConfig = serde_json::from_str(content).map_err(|e| e.to_string())?;
 
-const MAINNET_USDC: Token = Token {
-    address: "0x53c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8",
-    decimals: 6,
-    symbol: "USDC",
-};
+    let mut tokens: Vec<(Network, Token)> = vec![];
+    for token in config.tokens {
+        tokens.push((
+            token.network.parse()?,
+            Token {
+                address: token.address,
+                symbol: token.symbol,
+                decimals: token.decimals,
+            },
+        ));
+    }
 
-#[allow(dead_code)]
-// BTC not yet implemented for the Mainnet
-const MAINNET_BTC: Token = Token {
-    address: "0x3fe2b97c1fd336e750087d68b9b867997fd64a2661ff3ca5a7c771641e8e7ac",
-    decimals: 8,
-    symbol: "BTC",
-};
+    let find_token = |network: Network, symbol: &str| -> Result<Token, String> {
+        tokens
+            .iter()
+            .find(|(n, token)| n == &network && token.symbol == symbol)
+            .map(|(_, token)| token.clone())
+            .ok_or(format!("Unknown {} token {}", network, symbol))
+    };
 
-const TESTNET_ETH_USDC_CALL: Pool = Pool {
-    address: "0x73e39528d223b3bb7a27400516120f634564f14d45cddf4ba04834d083f2968",
-    network: Network::Testnet,
-    type_: Type::Call,
-    base: TESTNET_ETH,
-    quote: TESTNET_USDC,
-    id: "eth-usdc-call",
-};
+    config
+        .pools
+        .into_iter()
+        .map(|pool| {
+            let network: Network = pool.network.parse()?;
+            Ok(Pool {
+                base: find_token(network, &pool.base)?,
+                quote: find_token(network, &pool.quote)?,
+                address: pool.address,
+                network,
+                type_: pool.type_,
+                id: pool.id,
+            })
+        })
+        .collect()
+}
 
-const TESTNET_ETH_USDC_PUT: Pool = Pool {
-    address: "0x519ddd4a869bc75fdfccaf40af1c8aa42ea34b703391d248b2098513ed2e98e",
-    network: Network::Testnet,
-    type_: Type::Put,
-    base: TESTNET_ETH,
-    quote: TESTNET_USDC,
-    id: "eth-usdc-put",
-};
+/// Pools of both networks, can be extended at runtime
+pub struct PoolRegistry {
+    mainnet: ArcSwap<Vec<Pool>>,
+    testnet: ArcSwap<Vec<Pool>>,
+}
 
-const TESTNET_BTC_USDC_CALL: Pool = Pool {
-    address: "0x4a6016451ec67270b2c2e6b3431343053891ef28ee01fc613923e7d3c61ee1",
-    network: Network::Testnet,
-    type_: Type::Call,
-    base: TESTNET_BTC,
-    quote: TESTNET_USDC,
-    id: "btc-usdc-call",
-};
+impl PoolRegistry {
+    pub fn new(pools: Vec<Pool>) -> Self {
+        let (mainnet, testnet): (Vec<Pool>, Vec<Pool>) = pools
+            .into_iter()
+            .partition(|pool| pool.network == Network::Mainnet);
+        PoolRegistry {
+            mainnet: ArcSwap::from_pointee(mainnet),
+            testnet: ArcSwap::from_pointee(testnet),
+        }
+    }
 
-const TESTNET_BTC_USDC_PUT: Pool = Pool {
-    address: "0x7a82787b8fc4a90dd5e3e573d95e32606c972747bce6600711dcfea9fb8c868",
-    network: Network::Testnet,
-    type_: Type::Put,
-    base: TESTNET_BTC,
-    quote: TESTNET_USDC,
-    id: "btc-usdc-put",
-};
+    /// Reads the file in POOLS_CONFIG, falls back to the pools shipped with the build
+    pub fn from_env() -> Result<Self, String> {
+        let pools = match env::var("POOLS_CONFIG") {
+            Ok(path) => fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| parse_pools_config(&content))
+                .map_err(|e| format!("Failed reading pools config {}: {}", path, e))?,
+            Err(_) => parse_pools_config(DEFAULT_POOLS_CONFIG)?,
+        };
+        Ok(PoolRegistry::new(pools))
+    }
 
-const MAINNET_ETH_USDC_CALL: Pool = Pool {
-    address: "0x7aba50fdb4e024c1ba63e2c60565d0fd32566ff4b18aa5818fc80c30e749024",
-    network: Network::Mainnet,
-    type_: Type::Call,
-    base: MAINNET_ETH,
-    quote: MAINNET_USDC,
-    id: "eth-usdc-call",
-};
+    fn network_pools(&self, network: &Network) -> &ArcSwap<Vec<Pool>> {
+        match network {
+            Network::Mainnet => &self.mainnet,
+            Network::Testnet => &self.testnet,
+        }
+    }
 
-const MAINNET_ETH_USDC_PUT: Pool = Pool {
-    address: "0x18a6abca394bd5f822cfa5f88783c01b13e593d1603e7b41b00d31d2ea4827a",
-    network: Network::Mainnet,
-    type_: Type::Put,
-    base: MAINNET_ETH,
-    quote: MAINNET_USDC,
-    id: "eth-usdc-put",
-};
+    pub fn pools(&self, network: &Network) -> Arc<Vec<Pool>> {
+        self.network_pools(network).load_full()
+    }
 
-pub fn get_all_pools(network: &Network) -> Vec<Pool> {
-    match network {
-        Network::Mainnet => vec![MAINNET_ETH_USDC_CALL, MAINNET_ETH_USDC_PUT],
-        Network::Testnet => vec![
-            TESTNET_ETH_USDC_CALL,
-            TESTNET_ETH_USDC_PUT,
-            TESTNET_BTC_USDC_CALL,
-            TESTNET_BTC_USDC_PUT,
-        ],
+    /// Adds the pool unless its address is already registered, returns if it was added
+    pub fn add(&self, pool: Pool) -> bool {
+        let mut added = false;
+        // rcu retries when another add stored in the meantime
+        self.network_pools(&pool.network).rcu(|current| {
+            added = !current.iter().any(|p| same_felt(&p.address, &pool.address));
+            if !added {
+                return Arc::clone(current);
+            }
+            let mut updated = current.as_ref().clone();
+            updated.push(pool.clone());
+            Arc::new(updated)
+        });
+        added
+    }
+}
+
+/// Reads and validates the pools config, to be called on startup
+/// before the registry is used
+pub fn load_pool_registry() -> Result<&'static PoolRegistry, String> {
+    if let Some(registry) = REGISTRY.get() {
+        return Ok(registry);
     }
+    let registry = PoolRegistry::from_env()?;
+    Ok(REGISTRY.get_or_init(|| registry))
 }
 
-pub fn get_all_pool_addresses(network: &Network) -> Vec<&'static str> {
+/// Registry loaded by `load_pool_registry`, processes which did not load it
+/// read the config on first use and fall back to the pools shipped with the build
+pub fn pool_registry() -> &'static PoolRegistry {
+    REGISTRY.get_or_init(|| {
+        PoolRegistry::from_env().unwrap_or_else(|e| {
+            warn!(error = %e, "Invalid pools config, using default pools");
+            PoolRegistry::new(parse_pools_config(DEFAULT_POOLS_CONFIG).unwrap_or_default())
+        })
+    })
+}
+
+pub fn get_all_pools(network: &Network) -> Vec<Pool> {
+    pool_registry().pools(network).as_ref().clone()
+}
+
+pub fn get_all_pool_addresses(network: &Network) -> Vec<String> {
     get_all_pools(network)
-        .iter()
+        .into_iter()
         .map(|pool| pool.address)
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_pools() {
+        let registry = PoolRegistry::new(parse_pools_config(DEFAULT_POOLS_CONFIG).unwrap());
+        let mainnet = registry.pools(&Network::Mainnet);
+
+        assert_eq!(mainnet.len(), 2);
+        assert_eq!(
+            mainnet[0].address,
+            "0x7aba50fdb4e024c1ba63e2c60565d0fd32566ff4b18aa5818fc80c30e749024"
+        );
+        assert_eq!(mainnet[0].description(), "ETH/USDC Call");
+        assert_eq!(
+            mainnet[1].address,
+            "0x18a6abca394bd5f822cfa5f88783c01b13e593d1603e7b41b00d31d2ea4827a"
+        );
+        assert_eq!(mainnet[1].quote.decimals, 6);
+        assert_eq!(registry.pools(&Network::Testnet).len(), 4);
+
+        // already registered
+        assert!(!registry.add(mainnet[0].clone()));
+
+        let mut btc = mainnet[0].clone();
+        btc.address = "0x1".to_owned();
+        btc.id = "btc-usdc-call".to_owned();
+        assert!(registry.add(btc.clone()));
+        assert_eq!(Pool::from_db(&btc.to_db(), Network::Mainnet), Some(btc));
+    }
+
+    #[test]
+    fn concurrent_adds() {
+        let registry = Arc::new(PoolRegistry::new(vec![]));
+        let pool = parse_pools_config(DEFAULT_POOLS_CONFIG).unwrap()[0].clone();
+        let handles: Vec<_> = (1..=8)
+            .map(|i| {
+                let registry = Arc::clone(&registry);
+                let mut pool = pool.clone();
+                pool.address = format!("0x{}", i);
+                std::thread::spawn(move || registry.add(pool))
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+        assert_eq!(registry.pools(&Network::Mainnet).len(), 8);
+    }
+
+    #[test]
+    fn unknown_token() {
+        let config = r#"{"tokens": [], "pools": [{"network": "mainnet", "id": "a",
+            "address": "0x1", "type": "call", "base": "ETH", "quote": "USDC"}]}"#;
+        assert_eq!(
+            parse_pools_config(config),
+            Err("Unknown Mainnet token ETH".to_owned())
+        );
+    }
+}
@@ -33,12 +33,21 @@ diesel::table! {
     blocks (block_number) {
         block_number -> Int8,
         timestamp -> Int8,
+        block_hash -> Nullable<Text>,
     }
 }
 
 diesel::table! {
     pools (lp_address) {
         lp_address -> Text,
+        pool_id -> Nullable<Text>,
+        option_type -> Nullable<Int2>,
+        base_address -> Nullable<Text>,
+        base_symbol -> Nullable<Text>,
+        base_decimals -> Nullable<Int2>,
+        quote_address -> Nullable<Text>,
+        quote_symbol -> Nullable<Text>,
+        quote_decimals -> Nullable<Int2>,
     }
 }
 
@@ -2,6 +2,7 @@ use lazy_static::lazy_static;
 use std::env::var;
 use teloxide::prelude::*;
 use teloxide::types::ParseMode;
+use tracing::{debug, warn};
 
 lazy_static! {
     static ref BOT_TOKEN_ENVAR: String = var("BOT_TOKEN").expect("Failed to read BOT_TOKEN");
@@ -12,7 +13,7 @@ pub async fn send_message(msg: &str) {
     let bot_token = BOT_TOKEN_ENVAR.to_owned();
     let chat_id = CHAT_ID_ENVAR.to_owned();
 
-    println!("sending message with {}{}", bot_token, chat_id);
+    debug!(message = msg, "Sending Telegram message");
     // Create a teloxide runtime and bot instance
     let bot = Bot::new(bot_token);
 
@@ -22,5 +23,7 @@ pub async fn send_message(msg: &str) {
         .parse_mode(ParseMode::MarkdownV2)
         .send()
         .await;
-    println!("Telegram message result:\n{:?}", res);
+    if let Err(e) = res {
+        warn!(error = %e, "Failed sending Telegram message");
+    }
 }
@@ -0,0 +1,77 @@
+use std::env;
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// Keeps the span exporter running, buffered spans are flushed when dropped
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed flushing spans: {}", e);
+            }
+        }
+    }
+}
+
+// exporter reads the collector address and headers from the standard OTEL_EXPORTER_OTLP_* variables
+fn otlp_provider(service: &'static str) -> Option<SdkTracerProvider> {
+    env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT")?;
+
+    let exporter = match SpanExporter::builder().with_http().build() {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            eprintln!(
+                "Failed creating OTLP exporter, spans are not exported: {}",
+                e
+            );
+            return None;
+        }
+    };
+
+    Some(
+        SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(Resource::builder().with_service_name(service).build())
+            .build(),
+    )
+}
+
+/// Sets up logging and spans of the process:
+/// - RUST_LOG - filter (default "info")
+/// - ENVIRONMENT - "local" prints human readable lines, JSON otherwise
+/// - OTEL_EXPORTER_OTLP_ENDPOINT - if set, spans are also exported to the collector
+///
+/// Records of the `log` crate (actix, teloxide) are forwarded as well.
+pub fn init(service: &'static str) -> Telemetry {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let output = match env::var("ENVIRONMENT") {
+        Ok(v) if v == "local" => fmt::layer().boxed(),
+        _ => fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    };
+
+    let provider = otlp_provider(service);
+    let export = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer(service)));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output)
+        .with(export)
+        .init();
+
+    Telemetry { provider }
+}
@@ -0,0 +1,102 @@
+use crate::types::{IOption, PoolStateWithTimestamp, TradeHistory};
+
+/// Pool state for tests, empty pool at the block with timestamp
+/// equal to the block number unless set otherwise
+pub struct PoolStateBuilder(PoolStateWithTimestamp);
+
+impl PoolStateBuilder {
+    pub fn new(block_number: i64) -> Self {
+        PoolStateBuilder(PoolStateWithTimestamp {
+            unlocked_cap: "0x0".to_string(),
+            locked_cap: "0x0".to_string(),
+            lp_balance: "0x0".to_string(),
+            pool_position: None,
+            lp_token_value: None,
+            block_number,
+            lp_address: "0x1".to_string(),
+            timestamp: block_number,
+        })
+    }
+
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.0.timestamp = timestamp;
+        self
+    }
+
+    pub fn locked_cap(mut self, locked_cap: &str) -> Self {
+        self.0.locked_cap = locked_cap.to_string();
+        self
+    }
+
+    pub fn unlocked_cap(mut self, unlocked_cap: &str) -> Self {
+        self.0.unlocked_cap = unlocked_cap.to_string();
+        self
+    }
+
+    pub fn lp_token_value(mut self, lp_token_value: &str) -> Self {
+        self.0.lp_token_value = Some(lp_token_value.to_string());
+        self
+    }
+
+    pub fn lp_address(mut self, lp_address: &str) -> Self {
+        self.0.lp_address = lp_address.to_string();
+        self
+    }
+
+    pub fn build(self) -> PoolStateWithTimestamp {
+        self.0
+    }
+}
+
+/// Trade for tests, "TradeOpen" of nothing by "0x1" without option or pool
+/// unless set otherwise
+pub struct TradeHistoryBuilder(TradeHistory);
+
+impl TradeHistoryBuilder {
+    pub fn new(timestamp: i64, transaction_hash: &str) -> Self {
+        TradeHistoryBuilder(TradeHistory {
+            timestamp,
+            transaction_hash: transaction_hash.to_string(),
+            action: "TradeOpen".to_string(),
+            caller: "0x1".to_string(),
+            capital_transfered: "0x0".to_string(),
+            tokens_minted: "0x0".to_string(),
+            option: None,
+            liquidity_pool: None,
+        })
+    }
+
+    pub fn action(mut self, action: &str) -> Self {
+        self.0.action = action.to_string();
+        self
+    }
+
+    pub fn caller(mut self, caller: &str) -> Self {
+        self.0.caller = caller.to_string();
+        self
+    }
+
+    pub fn capital_transfered(mut self, capital_transfered: &str) -> Self {
+        self.0.capital_transfered = capital_transfered.to_string();
+        self
+    }
+
+    pub fn tokens_minted(mut self, tokens_minted: &str) -> Self {
+        self.0.tokens_minted = tokens_minted.to_string();
+        self
+    }
+
+    pub fn option(mut self, option: IOption) -> Self {
+        self.0.option = Some(option);
+        self
+    }
+
+    pub fn liquidity_pool(mut self, liquidity_pool: &str) -> Self {
+        self.0.liquidity_pool = Some(liquidity_pool.to_string());
+        self
+    }
+
+    pub fn build(self) -> TradeHistory {
+        self.0
+    }
+}
@@ -1,15 +1,20 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
+use crate::network::Network;
 use crate::schema::{
     blocks, events, options, options_volatility, oracle_prices, pool_state, pools, starkscan_events,
 };
+use arc_swap::ArcSwap;
 use carmine_api_airdrop::merkle_tree::MerkleTree;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct TradeHistory {
     pub timestamp: i64,
+    pub transaction_hash: String,
     pub action: String,
     pub caller: String,
     pub capital_transfered: String,
@@ -32,15 +37,46 @@ pub struct AppData {
     pub option_volatility: Vec<OptionWithVolatility>,
     pub state: HashMap<String, Vec<PoolStateWithTimestamp>>,
     pub oracle_prices: HashMap<String, Vec<OraclePriceConcise>>,
-    pub apy: HashMap<String, f64>,
+    pub apy: HashMap<String, Vec<ApyResult>>,
+    /// pool id -> interval in seconds -> buckets of the whole state history
+    pub state_buckets: HashMap<String, HashMap<i64, Vec<PoolStateBucket>>>,
 }
 
+/// Each network data is an immutable snapshot, readers never wait
+/// for the updater - it swaps in a whole new snapshot when it is ready
 pub struct AppState {
-    pub mainnet: AppData,
-    pub testnet: AppData,
+    pub mainnet: ArcSwap<AppData>,
+    pub testnet: ArcSwap<AppData>,
     pub airdrop: MerkleTree,
 }
 
+impl AppState {
+    pub fn new(mainnet: AppData, testnet: AppData, airdrop: MerkleTree) -> Self {
+        AppState {
+            mainnet: ArcSwap::from_pointee(mainnet),
+            testnet: ArcSwap::from_pointee(testnet),
+            airdrop,
+        }
+    }
+
+    /// Current snapshot for the given network
+    pub fn get_data(&self, network: &Network) -> Arc<AppData> {
+        match network {
+            Network::Mainnet => self.mainnet.load_full(),
+            Network::Testnet => self.testnet.load_full(),
+        }
+    }
+
+    /// Replaces snapshot for the given network, readers holding
+    /// the old snapshot keep it until they are done
+    pub fn update_data(&self, network: &Network, data: AppData) {
+        match network {
+            Network::Mainnet => self.mainnet.store(Arc::new(data)),
+            Network::Testnet => self.testnet.store(Arc::new(data)),
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StarkScanEventResult {
     pub next_url: Option<String>,
@@ -92,7 +128,9 @@ pub struct Event {
     pub tokens_minted: String,
 }
 
-#[derive(Associations, Debug, Clone, Queryable, Insertable, Serialize, PartialEq, Selectable)]
+#[derive(
+    Associations, Debug, Clone, Queryable, Insertable, Serialize, PartialEq, Selectable, ToSchema,
+)]
 #[diesel(belongs_to(Pool, foreign_key = lp_address))]
 #[diesel(table_name = options)]
 pub struct IOption {
@@ -106,7 +144,7 @@ pub struct IOption {
     pub lp_address: String,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct Volatility {
     pub block_number: i64,
     pub timestamp: i64,
@@ -114,7 +152,7 @@ pub struct Volatility {
     pub option_position: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct OptionWithVolatility {
     pub option_side: i16,
     pub maturity: i64,
@@ -150,7 +188,7 @@ pub struct PoolState {
     pub lp_address: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct PoolStateWithTimestamp {
     pub unlocked_cap: String,
     pub locked_cap: String,
@@ -167,12 +205,23 @@ pub struct PoolStateWithTimestamp {
 pub struct DbBlock {
     pub block_number: i64,
     pub timestamp: i64,
+    // missing for blocks stored before hashes were recorded
+    pub block_hash: Option<String>,
 }
 
 #[derive(Debug, Clone, Queryable, Insertable, Serialize, PartialEq, Selectable)]
 #[diesel(table_name = pools)]
 pub struct Pool {
     pub lp_address: String,
+    // metadata, missing for pools stored before the pool registry
+    pub pool_id: Option<String>,
+    pub option_type: Option<i16>,
+    pub base_address: Option<String>,
+    pub base_symbol: Option<String>,
+    pub base_decimals: Option<i16>,
+    pub quote_address: Option<String>,
+    pub quote_symbol: Option<String>,
+    pub quote_decimals: Option<i16>,
 }
 
 #[derive(Associations, Debug, Clone, Queryable, Insertable, Serialize, PartialEq, Selectable)]
@@ -200,10 +249,212 @@ pub struct OraclePrice {
     pub block_number: i64,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct OraclePriceConcise {
     pub price: i64,
     pub decimals: i16,
     pub last_updated_timestamp: i64,
     pub block_number: i64,
 }
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UserOptionPosition {
+    pub option_side: i16,
+    pub option_type: i16,
+    pub maturity: i64,
+    pub strike_price: f64,
+    pub quote_token_address: String,
+    pub base_token_address: String,
+    /// amount of option tokens, u256 hex
+    pub position_size: String,
+    /// value of the position in the pool's token
+    pub value_of_position: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UserPoolInfo {
+    pub lp_address: String,
+    pub pool_id: Option<String>,
+    pub quote_token_address: String,
+    pub base_token_address: String,
+    pub option_type: i16,
+    /// value of the user's LP tokens in pool's token, u256 hex
+    pub value_of_user_stake: String,
+    /// amount of LP tokens held by the user, u256 hex
+    pub size_of_users_tokens: String,
+    pub staked_capital: String,
+    pub unlocked_capital: String,
+    pub value_of_pool_position: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Portfolio {
+    pub address: String,
+    pub options: Vec<UserOptionPosition>,
+    pub pools: Vec<UserPoolInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OptionPnl {
+    pub option_address: String,
+    pub pool_id: Option<String>,
+    pub option_side: i16,
+    pub option_type: i16,
+    pub maturity: i64,
+    pub strike_price: f64,
+    /// symbol of the token PnL is denominated in (pool's token)
+    pub currency: String,
+    pub size_opened: f64,
+    pub size_closed: f64,
+    pub remaining_size: f64,
+    pub realized_pnl: f64,
+    /// None when there is no open position or it cannot be marked (eg. expired, not settled)
+    pub unrealized_pnl: Option<f64>,
+    pub current_premia: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PnlTotal {
+    pub currency: String,
+    pub realized_pnl: f64,
+    pub unrealized_pnl: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UserPnl {
+    pub address: String,
+    pub options: Vec<OptionPnl>,
+    /// totals per currency, tokens of different pools cannot be summed
+    pub totals: Vec<PnlTotal>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChainOption {
+    /// "long" or "short"
+    pub option_side: String,
+    /// "call" or "put"
+    pub option_type: String,
+    /// premia of a single option in the pool's token, rounded to its decimals
+    pub premia: f64,
+    /// None if volatility or spot price is not known yet
+    pub greeks: Option<Greeks>,
+}
+
+/// Black-Scholes Greeks of a position of size 1, short positions have opposite sign.
+/// Theta and vega are in the quote token.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Greeks {
+    pub delta: f64,
+    pub gamma: f64,
+    /// change of value in one day
+    pub theta: f64,
+    /// change of value for 1 % of volatility
+    pub vega: f64,
+    /// last AMM volatility in %
+    pub volatility: f64,
+    /// volatility in % implied by the premia, long options only
+    pub implied_volatility: Option<f64>,
+    pub spot_price: f64,
+    pub days_to_maturity: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OptionGreeks {
+    pub pool_id: String,
+    pub option_side: String,
+    pub option_type: String,
+    pub maturity: i64,
+    pub strike_price: f64,
+    pub premia: f64,
+    pub greeks: Greeks,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChainStrike {
+    pub strike_price: f64,
+    pub options: Vec<ChainOption>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChainMaturity {
+    pub maturity: i64,
+    pub strikes: Vec<ChainStrike>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PoolOptionChain {
+    pub pool_id: String,
+    pub base: String,
+    pub quote: String,
+    /// symbol of the token premia is denominated in (pool's token)
+    pub currency: String,
+    pub maturities: Vec<ChainMaturity>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UtilizationPoint {
+    pub block_number: i64,
+    pub timestamp: i64,
+    /// in the pool's token
+    pub locked_capital: f64,
+    pub unlocked_capital: f64,
+    /// locked capital / total capital
+    pub utilization: f64,
+}
+
+/// Exposure of the pool as the counterparty of all open positions,
+/// delta and gamma are in the base token, theta and vega in the quote token
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PoolRisk {
+    pub pool_id: String,
+    pub net_delta: f64,
+    pub net_gamma: f64,
+    pub net_theta: f64,
+    pub net_vega: f64,
+    /// options with open position included in the net values
+    pub options_priced: usize,
+    /// options with open position missing volatility or spot price
+    pub options_not_priced: usize,
+    /// None if there is no pool state
+    pub utilization: Option<UtilizationPoint>,
+    pub utilization_history: Vec<UtilizationPoint>,
+}
+
+/// Annualized return of the LP token over a window of pool state history
+#[derive(Debug, Clone, Serialize, PartialEq, ToSchema)]
+pub struct ApyResult {
+    /// eg. "7d", "30d" or "inception"
+    pub window: String,
+    /// "ok" or "insufficient_data" when history does not cover the window
+    pub status: String,
+    /// APY in %, None for insufficient data
+    pub apy: Option<f64>,
+    /// return over the window in %, not annualized
+    pub period_return: Option<f64>,
+    /// timestamps of the compared LP token values
+    pub from_timestamp: Option<i64>,
+    pub to_timestamp: Option<i64>,
+}
+
+/// First, last, min and max of a pool state value in a bucket, hex as stored
+#[derive(Debug, Clone, Serialize, PartialEq, ToSchema)]
+pub struct ValueRange {
+    pub first: String,
+    pub last: String,
+    pub min: String,
+    pub max: String,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, ToSchema)]
+pub struct PoolStateBucket {
+    /// start of the bucket
+    pub timestamp: i64,
+    pub first_block_number: i64,
+    pub last_block_number: i64,
+    /// number of stored states in the bucket
+    pub count: usize,
+    pub lp_token_value: Option<ValueRange>,
+    pub locked_cap: Option<ValueRange>,
+    pub unlocked_cap: Option<ValueRange>,
+    pub pool_position: Option<ValueRange>,
+}
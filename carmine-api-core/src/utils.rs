@@ -5,3 +5,73 @@ pub fn token_pair_id(token_pair: &TokenPair) -> String {
         TokenPair::EthUsdc => "eth-usdc".to_owned(),
     }
 }
+
+/// Parses hex ("0x...") or decimal felt, fails for values over 128 bits
+pub fn felt_to_u128(felt: &str) -> Option<u128> {
+    match felt.strip_prefix("0x") {
+        Some(hex) => u128::from_str_radix(hex, 16).ok(),
+        None => felt.parse::<u128>().ok(),
+    }
+}
+
+/// Parses hex ("0x...") or decimal felt of any size, precision is that of f64
+pub fn felt_to_f64(felt: &str) -> Option<f64> {
+    let (digits, radix) = match felt.strip_prefix("0x") {
+        Some(hex) => (hex, 16),
+        None => (felt, 10),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    digits.chars().try_fold(0f64, |acc, c| {
+        Some(acc * radix as f64 + c.to_digit(radix)? as f64)
+    })
+}
+
+/// Compares felts ignoring case and leading zeros, values up to 128 bits
+/// (eg. strike prices) are compared as numbers so hex equals decimal
+pub fn same_felt(a: &str, b: &str) -> bool {
+    if let (Some(a), Some(b)) = (felt_to_u128(a), felt_to_u128(b)) {
+        return a == b;
+    }
+    let strip = |v: &str| {
+        v.to_lowercase()
+            .trim_start_matches("0x")
+            .trim_start_matches('0')
+            .to_owned()
+    };
+    strip(a) == strip(b)
+}
+
+/// Decodes Cairo short string (up to 31 ASCII characters packed in a felt), eg. ERC20 symbol
+pub fn felt_to_short_string(felt: &str) -> Option<String> {
+    let hex = felt.strip_prefix("0x")?;
+    let hex = match hex.len() % 2 {
+        0 => hex.to_owned(),
+        _ => format!("0{}", hex),
+    };
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let bytes: Vec<u8> = bytes.into_iter().skip_while(|b| *b == 0).collect();
+    if bytes.is_empty() || bytes.len() > 31 || !bytes.is_ascii() {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Cairo 0 fixed point number with 61 decimal bits
+pub fn math_64x61_to_f64(felt: &str) -> Option<f64> {
+    felt_to_u128(felt).map(|v| v as f64 / 2f64.powi(61))
+}
+
+/// Cairo 1 (Cubit) fixed point number with 64 decimal bits and separate sign
+pub fn cubit_fixed_to_f64(mag: &str, sign: &str) -> Option<f64> {
+    let value = felt_to_u128(mag)? as f64 / 2f64.powi(64);
+    match felt_to_u128(sign)? {
+        0 => Some(value),
+        1 => Some(-value),
+        _ => None,
+    }
+}
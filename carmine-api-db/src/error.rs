@@ -0,0 +1,34 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum DbError {
+    /// pool could not be created, eg. missing env variable
+    Config(String),
+    /// no connection available within the pool timeout
+    Connection(String),
+    Query {
+        context: &'static str,
+        source: diesel::result::Error,
+    },
+    NotFound(&'static str),
+}
+
+impl DbError {
+    /// Use with `map_err` to attach context to a failed query
+    pub fn query(context: &'static str) -> impl FnOnce(diesel::result::Error) -> DbError {
+        move |source| DbError::Query { context, source }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::Config(msg) => write!(f, "DB config error: {}", msg),
+            DbError::Connection(msg) => write!(f, "DB connection error: {}", msg),
+            DbError::Query { context, source } => write!(f, "{}: {}", context, source),
+            DbError::NotFound(what) => write!(f, "Not found: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
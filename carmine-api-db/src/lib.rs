@@ -6,55 +6,43 @@ use carmine_api_core::types::{
 };
 
 use diesel::dsl::max;
-use diesel::pg::PgConnection;
 use diesel::prelude::*;
-use std::env;
+use diesel::upsert::excluded;
 use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, instrument};
 
-const BATCH_SIZE: usize = 500;
+mod error;
+mod notify;
+mod pool;
+mod query_metrics;
 
-fn get_db_url(network: &Network) -> String {
-    let environment = env::var("ENVIRONMENT").expect("Could not read \"ENVIRONMENT\"");
-    // your local DB
-    if environment.as_str() == "local" {
-        return match network {
-            Network::Testnet => "postgres://localhost/carmine-testnet".to_string(),
-            Network::Mainnet => "postgres://localhost/carmine-mainnet".to_string(),
-        };
-    }
-    let username = env::var("DB_USER").expect("Could not read \"DB_USER\"");
-    let password = env::var("DB_PASSWORD").expect("Could not read \"DB_PASSWORD\"");
-    let ip = env::var("DB_IP").expect("Could not read \"DB_IP\"");
-
-    let base = format!("postgres://{}:{}@{}", username, password, ip);
-    match network {
-        Network::Testnet => format!("{}/carmine-testnet", base).to_string(),
-        Network::Mainnet => format!("{}/carmine-mainnet", base).to_string(),
-    }
-}
+pub use error::DbError;
+use notify::notify_change;
+pub use notify::{listen_for_changes, DataChange};
+pub use pool::{get_connection, DbConnection, DbPool, PoolConfig};
 
-fn establish_connection(network: &Network) -> PgConnection {
-    let database_url = get_db_url(network);
-    PgConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
-}
+const BATCH_SIZE: usize = 500;
 
-pub fn create_event(new_event: Event, network: &Network) {
+#[instrument(skip_all, fields(network = %network, block = new_event.block_number))]
+pub fn create_event(new_event: Event, network: &Network) -> Result<(), DbError> {
     use crate::schema::events::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
     diesel::insert_into(events)
         .values(&new_event)
         .on_conflict_do_nothing()
         .execute(&mut connection)
-        .expect("Error saving event");
+        .map_err(DbError::query("Error saving event"))?;
+
+    Ok(())
 }
 
-pub fn create_batch_of_events(new_events: &Vec<Event>, network: &Network) {
+#[instrument(skip_all, fields(network = %network, rows = new_events.len()))]
+pub fn create_batch_of_events(new_events: &Vec<Event>, network: &Network) -> Result<(), DbError> {
     use crate::schema::events::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
     let chunks = new_events.chunks(BATCH_SIZE);
 
@@ -63,14 +51,20 @@ pub fn create_batch_of_events(new_events: &Vec<Event>, network: &Network) {
             .values(chunk)
             .on_conflict_do_nothing()
             .execute(&mut connection)
-            .expect("Error saving batch of events");
+            .map_err(DbError::query("Error saving batch of events"))?;
     }
+
+    Ok(())
 }
 
-pub fn create_batch_of_starkscan_events(events: &Vec<StarkScanEventSettled>, network: &Network) {
+#[instrument(skip_all, fields(network = %network, rows = events.len()))]
+pub fn create_batch_of_starkscan_events(
+    events: &Vec<StarkScanEventSettled>,
+    network: &Network,
+) -> Result<(), DbError> {
     use crate::schema::starkscan_events::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
     let chunks = events.chunks(BATCH_SIZE);
 
@@ -81,179 +75,271 @@ pub fn create_batch_of_starkscan_events(events: &Vec<StarkScanEventSettled>, net
             .values(chunk)
             .on_conflict_do_nothing()
             .execute(&mut connection)
-            .expect("Error saving batch of events");
+            .map_err(DbError::query("Error saving batch of events"))?;
         inserted += res as u32;
     }
 
-    println!("Inserted {} Starkscan events", inserted);
+    info!(inserted, "Inserted Starkscan events");
+
+    if inserted > 0 {
+        notify_change(&mut connection, DataChange::Events);
+    }
+
+    Ok(())
 }
 
-pub fn create_option(option: IOption, network: &Network) {
+#[instrument(skip_all, fields(network = %network, option = %option.option_address))]
+pub fn create_option(option: IOption, network: &Network) -> Result<(), DbError> {
     use crate::schema::options::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
-    diesel::insert_into(options)
+    let inserted = diesel::insert_into(options)
         .values(&option)
         .on_conflict_do_nothing()
         .execute(&mut connection)
-        .expect("Error saving option");
+        .map_err(DbError::query("Error saving option"))?;
+
+    if inserted > 0 {
+        notify_change(&mut connection, DataChange::Options);
+    }
+
+    Ok(())
 }
 
-pub fn create_batch_of_options(new_options: &Vec<IOption>, network: &Network) {
+#[instrument(skip_all, fields(network = %network, rows = new_options.len()))]
+pub fn create_batch_of_options(
+    new_options: &Vec<IOption>,
+    network: &Network,
+) -> Result<(), DbError> {
     use crate::schema::options::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
     let chunks = new_options.chunks(BATCH_SIZE);
 
+    let mut inserted = 0;
+
     for chunk in chunks {
-        diesel::insert_into(options)
+        inserted += diesel::insert_into(options)
             .values(chunk)
             .on_conflict_do_nothing()
             .execute(&mut connection)
-            .expect("Error saving batch of options");
+            .map_err(DbError::query("Error saving batch of options"))?;
     }
+
+    if inserted > 0 {
+        notify_change(&mut connection, DataChange::Options);
+    }
+
+    Ok(())
 }
 
-pub fn create_block(data: &DbBlock, network: &Network) {
+#[instrument(skip_all, fields(network = %network, block = data.block_number))]
+pub fn create_block(data: &DbBlock, network: &Network) -> Result<(), DbError> {
     use crate::schema::blocks::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
     diesel::insert_into(blocks)
         .values(data)
         .on_conflict_do_nothing()
         .execute(&mut connection)
-        .expect("Error saving block");
+        .map_err(DbError::query("Error saving block"))?;
+
+    Ok(())
 }
 
-pub fn create_pools(data: Vec<Pool>, network: &Network) {
+#[instrument(skip_all, fields(network = %network, rows = data.len()))]
+pub fn create_pools(data: Vec<Pool>, network: &Network) -> Result<(), DbError> {
     use crate::schema::pools::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
+    // pools stored without metadata get it filled in
     diesel::insert_into(pools)
         .values(&data)
-        .on_conflict_do_nothing()
+        .on_conflict(lp_address)
+        .do_update()
+        .set((
+            pool_id.eq(excluded(pool_id)),
+            option_type.eq(excluded(option_type)),
+            base_address.eq(excluded(base_address)),
+            base_symbol.eq(excluded(base_symbol)),
+            base_decimals.eq(excluded(base_decimals)),
+            quote_address.eq(excluded(quote_address)),
+            quote_symbol.eq(excluded(quote_symbol)),
+            quote_decimals.eq(excluded(quote_decimals)),
+        ))
         .execute(&mut connection)
-        .expect("Error saving pools");
+        .map_err(DbError::query("Error saving pools"))?;
+
+    notify_change(&mut connection, DataChange::Pools);
+
+    Ok(())
 }
 
-pub fn create_oracle_price(data: &OraclePrice, network: &Network) {
+#[instrument(skip_all, fields(network = %network, block = data.block_number))]
+pub fn create_oracle_price(data: &OraclePrice, network: &Network) -> Result<(), DbError> {
     use crate::schema::oracle_prices::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
-    diesel::insert_into(oracle_prices)
+    let inserted = diesel::insert_into(oracle_prices)
         .values(data)
         .on_conflict_do_nothing()
         .execute(&mut connection)
-        .expect("Error saving oracle price");
+        .map_err(DbError::query("Error saving oracle price"))?;
+
+    if inserted > 0 {
+        notify_change(&mut connection, DataChange::OraclePrices);
+    }
+
+    Ok(())
 }
 
-pub fn get_last_block_for_protocol_event(network: &Network, protocol: &Protocol) -> Option<i64> {
+pub fn get_last_block_for_protocol_event(
+    network: &Network,
+    protocol: &Protocol,
+) -> Result<Option<i64>, DbError> {
     use crate::schema::starkscan_events::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
 
     starkscan_events
         .filter(from_address.eq(protocol_address(network, protocol)))
         .select(max(block_number))
         .first(connection)
-        .expect("Error getting last block_number for protocol events")
+        .map_err(DbError::query(
+            "Error getting last block_number for protocol events",
+        ))
 }
 
 pub fn get_last_timestamp_for_protocol_event(
     network: &Network,
     protocol: &Protocol,
-) -> Option<i64> {
+) -> Result<Option<i64>, DbError> {
     use crate::schema::starkscan_events::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
 
     starkscan_events
         .filter(from_address.eq(protocol_address(network, protocol)))
         .select(max(timestamp))
         .first(connection)
-        .expect("Error loading last timestamp for protocol event")
+        .map_err(DbError::query(
+            "Error loading last timestamp for protocol event",
+        ))
 }
 
 // TODO: move events (Carmine specific) to starkscan_events (general)
-pub fn get_last_timestamp_carmine_event(network: &Network) -> Option<i64> {
+pub fn get_last_timestamp_carmine_event(network: &Network) -> Result<Option<i64>, DbError> {
     use crate::schema::events::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
 
     events
         .filter(from_address.eq(protocol_address(network, &Protocol::CarmineOptions)))
         .select(max(timestamp))
         .first(connection)
-        .expect("Error loading last timestamp for protocol event")
+        .map_err(DbError::query(
+            "Error loading last timestamp for protocol event",
+        ))
 }
 
-pub fn get_oracle_prices(network: &Network) -> Vec<OraclePrice> {
+pub fn get_oracle_prices(network: &Network) -> Result<Vec<OraclePrice>, DbError> {
     use crate::schema::oracle_prices::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
     oracle_prices
         .load::<OraclePrice>(connection)
-        .expect("Error loading oracle prices")
+        .map_err(DbError::query("Error loading oracle prices"))
+}
+
+pub fn get_oracle_prices_from_block(
+    network: &Network,
+    from_block_number: i64,
+) -> Result<Vec<OraclePrice>, DbError> {
+    get_oracle_prices_in_range(network, from_block_number, i64::MAX)
 }
 
-pub fn get_pools(network: &Network) -> Vec<Pool> {
+/// Prices after `from_block_number` up to `to_block_number` (inclusive)
+pub fn get_oracle_prices_in_range(
+    network: &Network,
+    from_block_number: i64,
+    to_block_number: i64,
+) -> Result<Vec<OraclePrice>, DbError> {
+    use crate::schema::oracle_prices::dsl::*;
+
+    let connection = &mut get_connection(network)?;
+    oracle_prices
+        .filter(block_number.gt(from_block_number))
+        .filter(block_number.le(to_block_number))
+        .load::<OraclePrice>(connection)
+        .map_err(DbError::query("Error loading oracle prices"))
+}
+
+pub fn get_pools(network: &Network) -> Result<Vec<Pool>, DbError> {
     use crate::schema::pools::dsl::*;
 
-    let connection = &mut establish_connection(network);
-    pools.load::<Pool>(connection).expect("Error loading pools")
+    let connection = &mut get_connection(network)?;
+    pools
+        .load::<Pool>(connection)
+        .map_err(DbError::query("Error loading pools"))
 }
 
-pub fn get_protocol_events(network: &Network, protocol: &Protocol) -> Vec<StarkScanEventSettled> {
+pub fn get_protocol_events(
+    network: &Network,
+    protocol: &Protocol,
+) -> Result<Vec<StarkScanEventSettled>, DbError> {
     use crate::schema::starkscan_events::dsl::*;
 
     let address = protocol_address(network, protocol);
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
     starkscan_events
         .filter(from_address.eq(address))
         .load::<StarkScanEventSettled>(connection)
-        .expect("Error loading starkscan events")
+        .map_err(DbError::query("Error loading starkscan events"))
 }
 
 pub fn get_protocol_events_from_block(
     network: &Network,
     protocol: &Protocol,
     from_block_number: i64,
-) -> Vec<StarkScanEventSettled> {
+) -> Result<Vec<StarkScanEventSettled>, DbError> {
     use crate::schema::starkscan_events::dsl::*;
 
     let address = protocol_address(network, protocol);
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
     starkscan_events
         .filter(block_number.gt(from_block_number))
         .filter(from_address.eq(address))
         .load::<StarkScanEventSettled>(connection)
-        .expect("Error loading starkscan events")
+        .map_err(DbError::query("Error loading starkscan events"))
 }
 
-pub fn get_events(network: &Network) -> Vec<Event> {
+pub fn get_events(network: &Network) -> Result<Vec<Event>, DbError> {
     use crate::schema::events::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
     events
         .load::<Event>(connection)
-        .expect("Error loading events")
+        .map_err(DbError::query("Error loading events"))
 }
 
-pub fn get_events_by_caller_address(address: &str, network: &Network) -> Vec<Event> {
+pub fn get_events_by_caller_address(
+    address: &str,
+    network: &Network,
+) -> Result<Vec<Event>, DbError> {
     use crate::schema::events::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
     events
         .filter(caller.eq(address))
         .load::<Event>(connection)
-        .expect("Error loading events by caller address")
+        .map_err(DbError::query("Error loading events by caller address"))
 }
 
 pub fn get_option_with_address(
@@ -262,45 +348,44 @@ pub fn get_option_with_address(
     in_maturity: i64,
     in_strike_price: &String,
     in_lp_address: &String,
-) -> Option<IOption> {
+) -> Result<Option<IOption>, DbError> {
     use crate::schema::options::dsl::*;
 
-    let connection = &mut establish_connection(network);
-    let res: Option<IOption> = options
+    let connection = &mut get_connection(network)?;
+    options
         .filter(lp_address.eq(in_lp_address))
         .filter(maturity.eq(in_maturity))
         .filter(strike_price.eq(in_strike_price))
         .filter(option_side.eq(in_option_side))
         .first::<IOption>(connection)
         .optional()
-        .expect("Error loading options");
-
-    res
+        .map_err(DbError::query("Error loading options"))
 }
 
-pub fn get_options(network: &Network) -> Vec<IOption> {
+pub fn get_options(network: &Network) -> Result<Vec<IOption>, DbError> {
     use crate::schema::options::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
     options
         .load::<IOption>(connection)
-        .expect("Error loading options")
+        .map_err(DbError::query("Error loading options"))
 }
 
-pub fn get_block_by_number(num: i64, network: &Network) -> Option<DbBlock> {
+pub fn get_block_by_number(num: i64, network: &Network) -> Result<Option<DbBlock>, DbError> {
     use crate::schema::blocks::dsl::*;
 
-    let connection = &mut establish_connection(network);
-    match blocks.find(num).first(connection) {
-        Ok(v) => Some(v),
-        _ => None,
-    }
+    let connection = &mut get_connection(network)?;
+    blocks
+        .find(num)
+        .first(connection)
+        .optional()
+        .map_err(DbError::query("Error loading block"))
 }
 
-pub fn get_last_block_in_db(network: &Network) -> DbBlock {
+pub fn get_last_block_in_db(network: &Network) -> Result<DbBlock, DbError> {
     use crate::schema::blocks::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
 
     // TODO: this is the right way to do it, but Diesel has some weird problem with it
     // let res = blocks.select(max(block_number)).first(connection);
@@ -310,58 +395,105 @@ pub fn get_last_block_in_db(network: &Network) -> DbBlock {
     // }
 
     // get all and find max, because Diesel does not like the SQL solution ¯\_(ツ)_/¯
-    if let Ok(all_blocks) = blocks.load::<DbBlock>(connection) {
-        return all_blocks
-            .into_iter()
-            .max_by_key(|b| b.block_number)
-            .expect("did not find last block in DB");
-    }
-    unreachable!("did not find last block in DB");
+    blocks
+        .load::<DbBlock>(connection)
+        .map_err(DbError::query("Error loading blocks"))?
+        .into_iter()
+        .max_by_key(|b| b.block_number)
+        .ok_or(DbError::NotFound("last block in DB"))
 }
 
-pub fn create_batch_of_volatilities(volatilities: &Vec<OptionVolatility>, network: &Network) {
+#[instrument(skip_all, fields(network = %network, rows = volatilities.len()))]
+pub fn create_batch_of_volatilities(
+    volatilities: &Vec<OptionVolatility>,
+    network: &Network,
+) -> Result<(), DbError> {
     use crate::schema::options_volatility::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
     let chunks = volatilities.chunks(BATCH_SIZE);
 
+    let mut inserted = 0;
+
     for chunk in chunks {
-        diesel::insert_into(options_volatility)
+        inserted += diesel::insert_into(options_volatility)
             .values(chunk)
             .on_conflict_do_nothing()
             .execute(&mut connection)
-            .expect("Error saving batch of volatilities");
+            .map_err(DbError::query("Error saving batch of volatilities"))?;
     }
+
+    if inserted > 0 {
+        notify_change(&mut connection, DataChange::Volatility);
+    }
+
+    Ok(())
 }
 
-pub fn create_batch_of_pool_states(states: &Vec<PoolState>, network: &Network) {
+#[instrument(skip_all, fields(network = %network, rows = states.len()))]
+pub fn create_batch_of_pool_states(
+    states: &Vec<PoolState>,
+    network: &Network,
+) -> Result<(), DbError> {
     use crate::schema::pool_state::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
     let chunks = states.chunks(BATCH_SIZE);
 
+    let mut inserted = 0;
+
     for chunk in chunks {
-        diesel::insert_into(pool_state)
+        inserted += diesel::insert_into(pool_state)
             .values(chunk)
             .on_conflict_do_nothing()
             .execute(&mut connection)
-            .expect("Error saving batch of pool states");
+            .map_err(DbError::query("Error saving batch of pool states"))?;
+    }
+
+    if inserted > 0 {
+        notify_change(&mut connection, DataChange::PoolState);
     }
+
+    Ok(())
 }
 
-pub fn get_pool_state(pool_address: &str, network: &Network) -> Vec<PoolStateWithTimestamp> {
+pub fn get_pool_state(
+    pool_address: &str,
+    network: &Network,
+) -> Result<Vec<PoolStateWithTimestamp>, DbError> {
+    get_pool_state_from_block(pool_address, -1, network)
+}
+
+/// Pool states after the block, newest first
+pub fn get_pool_state_from_block(
+    pool_address: &str,
+    from_block_number: i64,
+    network: &Network,
+) -> Result<Vec<PoolStateWithTimestamp>, DbError> {
+    get_pool_state_in_range(pool_address, from_block_number, i64::MAX, network)
+}
+
+/// Pool states after `from_block_number` up to `to_block_number` (inclusive), newest first
+pub fn get_pool_state_in_range(
+    pool_address: &str,
+    from_block_number: i64,
+    to_block_number: i64,
+    network: &Network,
+) -> Result<Vec<PoolStateWithTimestamp>, DbError> {
     use crate::schema::blocks::dsl::*;
     use crate::schema::pool_state::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
     let mut data: Vec<PoolStateWithTimestamp> = pool_state
         .inner_join(blocks)
         .filter(lp_address.eq(pool_address))
+        .filter(crate::schema::pool_state::dsl::block_number.gt(from_block_number))
+        .filter(crate::schema::pool_state::dsl::block_number.le(to_block_number))
         .select((PoolState::as_select(), DbBlock::as_select()))
         .load::<(PoolState, DbBlock)>(connection)
-        .expect("Error loading pool state")
+        .map_err(DbError::query("Error loading pool state"))?
         .into_iter()
         .map(|(pool, block)| PoolStateWithTimestamp {
             unlocked_cap: pool.unlocked_cap,
@@ -377,17 +509,17 @@ pub fn get_pool_state(pool_address: &str, network: &Network) -> Vec<PoolStateWit
 
     data.sort_by(|a, b| b.block_number.cmp(&a.block_number));
 
-    data
+    Ok(data)
 }
 
 pub fn get_pool_state_block_numbers_in_range(
     start_block: i64,
     end_block: i64,
     network: &Network,
-) -> Vec<i64> {
+) -> Result<Vec<i64>, DbError> {
     use crate::schema::pool_state::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
     pool_state
         .select(block_number)
         .filter(
@@ -397,11 +529,15 @@ pub fn get_pool_state_block_numbers_in_range(
         )
         .order(block_number.asc())
         .load::<i64>(connection)
-        .expect("Error loading pool_state")
+        .map_err(DbError::query("Error loading pool_state"))
 }
 
-pub fn get_pool_state_block_holes(start: i64, end: i64, network: &Network) -> Vec<i64> {
-    let blocks = get_pool_state_block_numbers_in_range(start, end, network);
+pub fn get_pool_state_block_holes(
+    start: i64,
+    end: i64,
+    network: &Network,
+) -> Result<Vec<i64>, DbError> {
+    let blocks = get_pool_state_block_numbers_in_range(start, end, network)?;
 
     let range_numbers: Vec<i64> = (start..=end).collect();
 
@@ -411,15 +547,47 @@ pub fn get_pool_state_block_holes(start: i64, end: i64, network: &Network) -> Ve
         .cloned()
         .collect();
 
-    holes
+    Ok(holes)
+}
+
+/// Tells listeners that states of the blocks (inclusive) were stored after newer ones,
+/// eg. holes plugged by the fetcher, so that they read the blocks again
+pub fn notify_backfill(from_block: i64, to_block: i64, network: &Network) -> Result<(), DbError> {
+    let mut connection = get_connection(network)?;
+    notify_change(
+        &mut connection,
+        DataChange::Backfill {
+            from_block,
+            to_block,
+        },
+    );
+    Ok(())
+}
+
+pub fn get_options_volatility(network: &Network) -> Result<Vec<OptionWithVolatility>, DbError> {
+    get_options_volatility_from_block(network, -1)
 }
 
-pub fn get_options_volatility(network: &Network) -> Vec<OptionWithVolatility> {
+/// Live options with their volatilities after the block, newest first
+pub fn get_options_volatility_from_block(
+    network: &Network,
+    from_block_number: i64,
+) -> Result<Vec<OptionWithVolatility>, DbError> {
+    get_options_volatility_in_range(network, from_block_number, i64::MAX)
+}
+
+/// Live options with their volatilities after `from_block_number`
+/// up to `to_block_number` (inclusive), newest first
+pub fn get_options_volatility_in_range(
+    network: &Network,
+    from_block_number: i64,
+    to_block_number: i64,
+) -> Result<Vec<OptionWithVolatility>, DbError> {
     use crate::schema::blocks::dsl::*;
     use crate::schema::options::dsl::*;
     use crate::schema::options_volatility::dsl::*;
 
-    let connection = &mut establish_connection(network);
+    let connection = &mut get_connection(network)?;
 
     let start = SystemTime::now();
     let timestamp_now = start
@@ -433,18 +601,20 @@ pub fn get_options_volatility(network: &Network) -> Vec<OptionWithVolatility> {
         .filter(maturity.gt(cutoff))
         .select(IOption::as_select())
         .load(connection)
-        .expect("Failed getting all options");
+        .map_err(DbError::query("Failed getting all options"))?;
 
     let mut options_with_volatilities: Vec<OptionWithVolatility> = vec![];
 
     for opt in live_options {
         let volatilities: Vec<Volatility> = options_volatility
             .filter(crate::schema::options_volatility::dsl::option_address.eq(&opt.option_address))
+            .filter(crate::schema::options_volatility::dsl::block_number.gt(from_block_number))
+            .filter(crate::schema::options_volatility::dsl::block_number.le(to_block_number))
             .inner_join(blocks)
             .order(crate::schema::blocks::dsl::block_number.desc())
             .select((OptionVolatility::as_select(), DbBlock::as_select()))
             .load::<(OptionVolatility, DbBlock)>(connection)
-            .expect("Error loading option volatility")
+            .map_err(DbError::query("Error loading option volatility"))?
             .iter()
             .map(|(vol, block)| Volatility {
                 block_number: block.block_number,
@@ -468,27 +638,97 @@ pub fn get_options_volatility(network: &Network) -> Vec<OptionWithVolatility> {
         });
     }
 
-    options_with_volatilities
+    Ok(options_with_volatilities)
 }
 
+#[instrument(skip_all, fields(network = %network, block, option = %address))]
 pub fn update_option_volatility(
     network: &Network,
     block: i64,
     vol: Option<String>,
     pos: Option<String>,
     address: String,
-) {
+) -> Result<(), DbError> {
     use crate::schema::options_volatility::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = get_connection(network)?;
 
-    let res = diesel::update(options_volatility)
+    let updated = diesel::update(options_volatility)
         .filter(block_number.eq(block))
         .filter(option_address.eq(address))
         .set((volatility.eq(vol), option_position.eq(pos)))
-        .execute(&mut connection);
+        .execute(&mut connection)
+        .map_err(DbError::query("Error updating option volatility"))?;
 
-    if let Err(_e) = res {
-        println!("FAILED! {}", block);
+    if updated > 0 {
+        notify_change(&mut connection, DataChange::Volatility);
     }
+
+    Ok(())
+}
+
+pub fn get_blocks_from(from_block: i64, network: &Network) -> Result<Vec<DbBlock>, DbError> {
+    use crate::schema::blocks::dsl::*;
+
+    let connection = &mut get_connection(network)?;
+    blocks
+        .filter(block_number.ge(from_block))
+        .filter(block_hash.is_not_null())
+        .order(block_number.asc())
+        .load::<DbBlock>(connection)
+        .map_err(DbError::query("Error loading blocks"))
+}
+
+/// Block number and block hash of every block with a stored event
+pub fn get_event_block_hashes_from(
+    from_block: i64,
+    network: &Network,
+) -> Result<Vec<(i64, String)>, DbError> {
+    use crate::schema::starkscan_events::dsl::*;
+
+    let connection = &mut get_connection(network)?;
+    starkscan_events
+        .select((block_number, block_hash))
+        .filter(block_number.ge(from_block))
+        .distinct()
+        .order(block_number.asc())
+        .load::<(i64, String)>(connection)
+        .map_err(DbError::query("Error loading event block hashes"))
+}
+
+/// Removes everything stored from the block on, used when the chain reorganized
+#[instrument(skip(network), fields(network = %network))]
+pub fn delete_from_block(from_block: i64, network: &Network) -> Result<(), DbError> {
+    use crate::schema::{
+        blocks, events, options_volatility, oracle_prices, pool_state, starkscan_events,
+    };
+
+    let connection = &mut get_connection(network)?;
+
+    connection
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            // rows referencing blocks go first
+            diesel::delete(pool_state::table.filter(pool_state::block_number.ge(from_block)))
+                .execute(conn)?;
+            diesel::delete(
+                options_volatility::table.filter(options_volatility::block_number.ge(from_block)),
+            )
+            .execute(conn)?;
+            diesel::delete(oracle_prices::table.filter(oracle_prices::block_number.ge(from_block)))
+                .execute(conn)?;
+            diesel::delete(blocks::table.filter(blocks::block_number.ge(from_block)))
+                .execute(conn)?;
+            diesel::delete(
+                starkscan_events::table.filter(starkscan_events::block_number.ge(from_block)),
+            )
+            .execute(conn)?;
+            diesel::delete(events::table.filter(events::block_number.ge(from_block)))
+                .execute(conn)?;
+            Ok(())
+        })
+        .map_err(DbError::query("Error deleting orphaned blocks"))?;
+
+    notify_change(connection, DataChange::All);
+
+    Ok(())
 }
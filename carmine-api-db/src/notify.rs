@@ -0,0 +1,190 @@
+use std::{
+    fmt,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use carmine_api_core::network::Network;
+use diesel::{pg::PgConnection, prelude::*, sql_types::Text};
+use tracing::{error, warn};
+
+use crate::pool::get_db_url;
+
+/// Postgres channel the writers notify after commit
+pub const CHANNEL: &str = "carmine_updates";
+
+// libpq buffers notifications, checking them is cheap
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// dead TCP connection is not noticed until something is sent
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Kind of data written to the DB, sent as the notification payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataChange {
+    Events,
+    Options,
+    Pools,
+    PoolState,
+    Volatility,
+    OraclePrices,
+    /// pool state, volatility and prices stored for older blocks (inclusive),
+    /// sent as "backfill:FROM-TO"
+    Backfill {
+        from_block: i64,
+        to_block: i64,
+    },
+    /// anything could have changed, eg. after reorg or missed notifications
+    All,
+}
+
+impl DataChange {
+    /// Sections of the data, each refreshed on its own
+    pub const SECTIONS: [DataChange; 6] = [
+        DataChange::Events,
+        DataChange::Options,
+        DataChange::Pools,
+        DataChange::PoolState,
+        DataChange::Volatility,
+        DataChange::OraclePrices,
+    ];
+}
+
+impl fmt::Display for DataChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataChange::Events => write!(f, "events"),
+            DataChange::Options => write!(f, "options"),
+            DataChange::Pools => write!(f, "pools"),
+            DataChange::PoolState => write!(f, "pool_state"),
+            DataChange::Volatility => write!(f, "volatility"),
+            DataChange::OraclePrices => write!(f, "oracle_prices"),
+            DataChange::Backfill {
+                from_block,
+                to_block,
+            } => write!(f, "backfill:{}-{}", from_block, to_block),
+            DataChange::All => write!(f, "all"),
+        }
+    }
+}
+
+fn parse_backfill(range: &str) -> Option<DataChange> {
+    let (from_block, to_block) = range.split_once('-')?;
+    Some(DataChange::Backfill {
+        from_block: from_block.parse().ok()?,
+        to_block: to_block.parse().ok()?,
+    })
+}
+
+impl FromStr for DataChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "events" => Ok(DataChange::Events),
+            "options" => Ok(DataChange::Options),
+            "pools" => Ok(DataChange::Pools),
+            "pool_state" => Ok(DataChange::PoolState),
+            "volatility" => Ok(DataChange::Volatility),
+            "oracle_prices" => Ok(DataChange::OraclePrices),
+            "all" => Ok(DataChange::All),
+            unknown => unknown
+                .strip_prefix("backfill:")
+                .and_then(parse_backfill)
+                .ok_or(format!("Unknown data change {}", unknown)),
+        }
+    }
+}
+
+/// Notifies listeners that `change` was committed. Data is already stored,
+/// so failure is only logged - listeners also refresh periodically.
+pub(crate) fn notify_change(connection: &mut PgConnection, change: DataChange) {
+    let result = diesel::sql_query("SELECT pg_notify($1, $2)")
+        .bind::<Text, _>(CHANNEL)
+        .bind::<Text, _>(change.to_string())
+        .execute(connection);
+
+    if let Err(e) = result {
+        warn!(change = %change, error = %e, "Failed sending notification");
+    }
+}
+
+fn listen(network: &Network) -> Result<PgConnection, String> {
+    let mut connection =
+        PgConnection::establish(&get_db_url(network)?).map_err(|e| e.to_string())?;
+    diesel::sql_query(format!("LISTEN {}", CHANNEL))
+        .execute(&mut connection)
+        .map_err(|e| e.to_string())?;
+    Ok(connection)
+}
+
+/// Returns only when the connection fails
+fn receive(
+    connection: &mut PgConnection,
+    on_change: &impl Fn(DataChange),
+) -> Result<(), diesel::result::Error> {
+    let mut last_health_check = Instant::now();
+    loop {
+        for notification in connection.notifications_iter() {
+            let notification = notification?;
+            match DataChange::from_str(&notification.payload) {
+                Ok(change) => on_change(change),
+                Err(e) => warn!(error = %e, "Ignoring notification"),
+            }
+        }
+        if last_health_check.elapsed() >= HEALTH_CHECK_INTERVAL {
+            diesel::sql_query("SELECT 1").execute(connection)?;
+            last_health_check = Instant::now();
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Calls `on_change` for every change committed to the network's DB, blocks
+/// the thread forever. Uses its own connection, not one from the pool.
+pub fn listen_for_changes(network: &Network, on_change: impl Fn(DataChange)) {
+    let mut first = true;
+    loop {
+        let mut connection = match listen(network) {
+            Ok(c) => c,
+            Err(e) => {
+                error!(network = %network, error = %e, "Failed listening for DB changes");
+                thread::sleep(RECONNECT_DELAY);
+                continue;
+            }
+        };
+        // changes committed while not listening were missed
+        if !first {
+            on_change(DataChange::All);
+        }
+        first = false;
+
+        if let Err(e) = receive(&mut connection, &on_change) {
+            warn!(network = %network, error = %e, "Lost DB notifications connection");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_roundtrip() {
+        for change in [
+            DataChange::Events,
+            DataChange::PoolState,
+            DataChange::OraclePrices,
+            DataChange::Backfill {
+                from_block: 190500,
+                to_block: 190520,
+            },
+            DataChange::All,
+        ] {
+            assert_eq!(DataChange::from_str(&change.to_string()), Ok(change));
+        }
+        assert!(DataChange::from_str("blocks").is_err());
+        assert!(DataChange::from_str("backfill:190500").is_err());
+    }
+}
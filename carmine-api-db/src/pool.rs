@@ -0,0 +1,124 @@
+use carmine_api_core::network::Network;
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+use lazy_static::lazy_static;
+use std::env;
+use std::time::Duration;
+
+use crate::error::DbError;
+use crate::query_metrics::QueryMetrics;
+
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+pub type DbConnection = PooledConnection<ConnectionManager<PgConnection>>;
+
+const DEFAULT_MAX_SIZE: u32 = 10;
+const DEFAULT_CONNECTION_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 600;
+
+lazy_static! {
+    // one pool per network, shared by everything in the process
+    static ref MAINNET_POOL: Result<DbPool, String> = create_pool(&Network::Mainnet);
+    static ref TESTNET_POOL: Result<DbPool, String> = create_pool(&Network::Testnet);
+}
+
+/// Pool settings, read from the environment:
+/// - DB_POOL_MAX_SIZE (default 10)
+/// - DB_POOL_MIN_IDLE (default same as max size)
+/// - DB_POOL_CONNECTION_TIMEOUT - seconds to wait for a connection (default 30)
+/// - DB_POOL_IDLE_TIMEOUT - seconds before idle connection is closed, 0 disables (default 600)
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_size: u32,
+    pub min_idle: Option<u32>,
+    pub connection_timeout: Duration,
+    pub idle_timeout: Option<Duration>,
+}
+
+fn env_number<T: std::str::FromStr>(name: &str) -> Result<Option<T>, String> {
+    match env::var(name) {
+        Ok(v) => match v.parse::<T>() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => Err(format!("Invalid \"{}\": {}", name, v)),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+impl PoolConfig {
+    pub fn from_env() -> Result<Self, String> {
+        let max_size = env_number("DB_POOL_MAX_SIZE")?.unwrap_or(DEFAULT_MAX_SIZE);
+        let min_idle = env_number("DB_POOL_MIN_IDLE")?;
+        let connection_timeout =
+            env_number("DB_POOL_CONNECTION_TIMEOUT")?.unwrap_or(DEFAULT_CONNECTION_TIMEOUT_SECS);
+        let idle_timeout = env_number("DB_POOL_IDLE_TIMEOUT")?.unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS);
+
+        if max_size == 0 {
+            return Err("\"DB_POOL_MAX_SIZE\" must be greater than 0".to_string());
+        }
+
+        Ok(PoolConfig {
+            max_size,
+            min_idle,
+            connection_timeout: Duration::from_secs(connection_timeout),
+            idle_timeout: match idle_timeout {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+        })
+    }
+}
+
+pub(crate) fn get_db_url(network: &Network) -> Result<String, String> {
+    let read = |name: &str| env::var(name).map_err(|_| format!("Could not read \"{}\"", name));
+
+    let environment = read("ENVIRONMENT")?;
+    // your local DB
+    if environment.as_str() == "local" {
+        return Ok(match network {
+            Network::Testnet => "postgres://localhost/carmine-testnet".to_string(),
+            Network::Mainnet => "postgres://localhost/carmine-mainnet".to_string(),
+        });
+    }
+    let username = read("DB_USER")?;
+    let password = read("DB_PASSWORD")?;
+    let ip = read("DB_IP")?;
+
+    let base = format!("postgres://{}:{}@{}", username, password, ip);
+    Ok(match network {
+        Network::Testnet => format!("{}/carmine-testnet", base),
+        Network::Mainnet => format!("{}/carmine-mainnet", base),
+    })
+}
+
+fn create_pool(network: &Network) -> Result<DbPool, String> {
+    let config = PoolConfig::from_env()?;
+    let manager = ConnectionManager::<PgConnection>::new(get_db_url(network)?);
+
+    // connections are opened lazily, DB being down is reported by "get_connection"
+    let pool = Pool::builder()
+        .max_size(config.max_size)
+        .min_idle(config.min_idle)
+        .connection_timeout(config.connection_timeout)
+        .idle_timeout(config.idle_timeout)
+        .test_on_check_out(true)
+        .connection_customizer(Box::new(QueryMetrics(*network)))
+        .build_unchecked(manager);
+
+    Ok(pool)
+}
+
+/// Connection from the network's pool, waits at most
+/// the configured connection timeout
+pub fn get_connection(network: &Network) -> Result<DbConnection, DbError> {
+    let pool = match network {
+        Network::Mainnet => &*MAINNET_POOL,
+        Network::Testnet => &*TESTNET_POOL,
+    };
+
+    match pool {
+        Ok(pool) => pool
+            .get()
+            .map_err(|e| DbError::Connection(format!("{} pool: {}", network, e))),
+        Err(e) => Err(DbError::Config(format!("{} pool: {}", network, e))),
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    fmt::{self, Write},
+    time::Instant,
+};
+
+use carmine_api_core::{metrics, network::Network};
+use diesel::{
+    connection::{Instrumentation, InstrumentationEvent},
+    pg::PgConnection,
+    r2d2::{CustomizeConnection, Error},
+    Connection,
+};
+
+// collects the first word of the formatted query and stops the formatting
+// there, batch inserts would otherwise render thousands of binds
+#[derive(Default)]
+struct FirstWord(String);
+
+impl Write for FirstWord {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if !c.is_whitespace() {
+                self.0.push(c.to_ascii_lowercase());
+            } else if !self.0.is_empty() {
+                return Err(fmt::Error);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Statement kind used as the metric label, eg. "select" or "insert"
+fn statement_kind(query: &dyn fmt::Display) -> String {
+    let mut word = FirstWord::default();
+    let _ = write!(word, "{}", query);
+    word.0
+}
+
+/// Records duration of every query run on the connection
+struct QueryTimer {
+    network: Network,
+    started: Option<(Instant, String)>,
+}
+
+impl Instrumentation for QueryTimer {
+    fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+        match event {
+            InstrumentationEvent::StartQuery { query, .. } => {
+                self.started = Some((Instant::now(), statement_kind(query)));
+            }
+            InstrumentationEvent::FinishQuery { error, .. } => {
+                if let Some((start, operation)) = self.started.take() {
+                    metrics::observe_db_query(
+                        &self.network,
+                        &operation,
+                        error.is_none(),
+                        start.elapsed(),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Installs the query timer on every connection the pool opens
+#[derive(Debug)]
+pub(crate) struct QueryMetrics(pub Network);
+
+impl CustomizeConnection<PgConnection, Error> for QueryMetrics {
+    fn on_acquire(&self, connection: &mut PgConnection) -> Result<(), Error> {
+        connection.set_instrumentation(QueryTimer {
+            network: self.0,
+            started: None,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_by_first_word() {
+        assert_eq!(
+            statement_kind(&"INSERT INTO \"events\" (\"id\") VALUES ($1)"),
+            "insert"
+        );
+        assert_eq!(statement_kind(&"\n  SELECT 1"), "select");
+        assert_eq!(statement_kind(&""), "");
+    }
+}
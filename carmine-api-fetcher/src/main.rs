@@ -1,18 +1,21 @@
 use std::env;
 
 use actix_web::{get, App, HttpResponse, HttpServer, Responder};
-use carmine_api_rpc_gateway::{blast_api_latest_block_number, carmine_latest_block_number};
+use carmine_api_rpc_gateway::latest_block_numbers;
 use tokio::time::{sleep, Duration};
+use tracing::{error, info, warn};
 
-use carmine_api_core::telegram_bot;
+use carmine_api_core::{metrics, network::Network, telegram_bot, telemetry};
 use carmine_api_starknet::{
-    plug_holes_amm_state, update_database_amm_state, update_database_events,
+    discover_new_pools, plug_holes_amm_state, pool_registry::init_pool_registry,
+    update_database_amm_state, update_database_events,
 };
 
 const BLOCK_OFFSET: i64 = 5;
 const PLUG_HOLES: bool = true;
 const GET_NEW_BLOCKS: bool = true;
 const GET_NEW_EVENTS: bool = true;
+const DISCOVER_POOLS: bool = true;
 
 const BLOCK_DISCREPENCY_THRESHOLD: i64 = 5;
 
@@ -27,29 +30,36 @@ fn ip_address() -> &'static str {
 }
 
 async fn report_block_discrepency() {
-    let (carm_block_number, blast_block_number) = match (
-        carmine_latest_block_number().await,
-        blast_api_latest_block_number().await,
-    ) {
-        (Ok(carm), Ok(blast)) => (carm, blast),
-        (Err(_), _) => {
-            // carmine failed, report it
-            telegram_bot::send_message("Failed getting latest block number from Carmine Juno node")
-                .await;
-            return;
+    let mut block_numbers = vec![];
+
+    for (node, result) in latest_block_numbers(&Network::Mainnet).await {
+        match result {
+            Ok(block_number) => block_numbers.push((node, block_number)),
+            Err(e) => {
+                warn!(node = %node, error = ?e, "Failed getting latest block number");
+                let msg = format!("Failed getting latest block number from {} node", node);
+                telegram_bot::send_message(msg.as_str()).await;
+            }
         }
-        // blast failed, but carmine ok - do not report
-        _ => return,
-    };
+    }
 
-    let diff = blast_block_number - carm_block_number;
-    if diff > BLOCK_DISCREPENCY_THRESHOLD {
-        let msg = format!(
-            "BLOCK DISCREPENCY is {}: Carmine: {}, BlastApi: {}",
-            diff, carm_block_number, blast_block_number
-        );
+    let max_block_number = match block_numbers.iter().map(|(_, n)| *n).max() {
+        Some(n) => n,
+        None => return,
+    };
 
-        telegram_bot::send_message(msg.as_str()).await;
+    for (node, block_number) in block_numbers {
+        let diff = max_block_number - block_number;
+        metrics::set_block_lag(&node, diff);
+        if diff > BLOCK_DISCREPENCY_THRESHOLD {
+            warn!(node = %node, block = block_number, lag = diff, "Block discrepency");
+            let msg = format!(
+                "BLOCK DISCREPENCY is {}: {}: {}, latest: {}",
+                diff, node, block_number, max_block_number
+            );
+
+            telegram_bot::send_message(msg.as_str()).await;
+        }
     }
 }
 
@@ -58,12 +68,28 @@ async fn liveness() -> impl Responder {
     HttpResponse::Ok().body("alive")
 }
 
+#[get("/metrics")]
+async fn metrics_handler() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(metrics::content_type())
+        .body(metrics::render())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    println!("👷 Starting fetcher");
+    let _telemetry = telemetry::init("carmine-api-fetcher");
+    info!("Starting fetcher");
+
+    info!("Loading pools");
+    for network in [Network::Mainnet, Network::Testnet] {
+        if let Err(e) = init_pool_registry(&network).await {
+            error!(network = %network, error = %e, "Failed loading pools");
+            return Err(std::io::Error::other(e));
+        }
+    }
 
     if GET_NEW_EVENTS {
-        println!("🛠️  Spawning event fetching thread...");
+        info!("Spawning event fetching thread");
         actix_web::rt::spawn(async move {
             loop {
                 if let Err(err) =
@@ -71,13 +97,13 @@ async fn main() -> std::io::Result<()> {
                 {
                     // failed, probably network overload, wait to send message
                     sleep(Duration::from_secs(10)).await;
-                    println!("update_database_events panicked\n{:?}", err);
+                    error!(error = ?err, "update_database_events panicked");
                     telegram_bot::send_message(
                         "Carmine API `update_database_events` just panicked",
                     )
                     .await;
                 } else {
-                    println!("Database updated with events");
+                    info!("Database updated with events");
                 }
                 sleep(Duration::from_secs(150)).await;
             }
@@ -85,7 +111,7 @@ async fn main() -> std::io::Result<()> {
     }
 
     if GET_NEW_BLOCKS {
-        println!("🛠️  Spawning new blocks fetching thread...");
+        info!("Spawning new blocks fetching thread");
         actix_web::rt::spawn(async move {
             loop {
                 report_block_discrepency().await;
@@ -96,13 +122,13 @@ async fn main() -> std::io::Result<()> {
                 {
                     // failed, probably network overload, wait to send message
                     sleep(Duration::from_secs(100)).await;
-                    println!("Update database amm state panicked\n{:?}", err);
+                    error!(error = ?err, "update_database_amm_state panicked");
                     telegram_bot::send_message(
                         "Carmine API `update_database_amm_state` just panicked",
                     )
                     .await;
                 } else {
-                    println!("Database updated with AMM state");
+                    info!("Database updated with AMM state");
                 }
                 sleep(Duration::from_secs(150)).await;
             }
@@ -110,27 +136,45 @@ async fn main() -> std::io::Result<()> {
     }
 
     if PLUG_HOLES {
-        println!("🛠️  Spawning hole plugging thread...");
+        info!("Spawning hole plugging thread");
         actix_web::rt::spawn(async move {
             loop {
                 if let Err(err) = actix_web::rt::spawn(async { plug_holes_amm_state().await }).await
                 {
                     // failed, probably network overload, wait to send message
                     sleep(Duration::from_secs(120)).await;
-                    println!("Plug holes panicked\n{:?}", err);
+                    error!(error = ?err, "plug_holes_amm_state panicked");
                     telegram_bot::send_message("Carmine API `plug_holes_amm_state` just panicked")
                         .await;
                 } else {
-                    println!("Holes in AMM state pluged");
+                    info!("Holes in AMM state plugged");
                 }
                 sleep(Duration::from_secs(150)).await;
             }
         });
     }
 
-    println!("🚀 Fetcher started successfully");
+    if DISCOVER_POOLS {
+        info!("Spawning pool discovery thread");
+        actix_web::rt::spawn(async move {
+            loop {
+                if let Err(err) = actix_web::rt::spawn(async { discover_new_pools().await }).await {
+                    // failed, probably network overload, wait to send message
+                    sleep(Duration::from_secs(120)).await;
+                    error!(error = ?err, "discover_new_pools panicked");
+                    telegram_bot::send_message("Carmine API `discover_new_pools` just panicked")
+                        .await;
+                } else {
+                    info!("New pools discovered");
+                }
+                sleep(Duration::from_secs(600)).await;
+            }
+        });
+    }
+
+    info!("Fetcher started successfully");
 
-    HttpServer::new(|| App::new().service(liveness))
+    HttpServer::new(|| App::new().service(liveness).service(metrics_handler))
         .bind((ip_address(), 8080))?
         .run()
         .await
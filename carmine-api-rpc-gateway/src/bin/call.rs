@@ -1,18 +1,13 @@
-use carmine_api_rpc_gateway::{
-    blast_api_latest_block_number, carmine_latest_block_number, infura_latest_block_number,
-};
+use carmine_api_core::network::Network;
+use carmine_api_rpc_gateway::latest_block_numbers;
 use dotenvy::dotenv;
-use tokio::try_join;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
 
-    let latest_blocks = try_join!(
-        carmine_latest_block_number(),
-        infura_latest_block_number(),
-        blast_api_latest_block_number(),
-    );
+    let latest_blocks = latest_block_numbers(&Network::Mainnet).await;
 
     println!("{:#?}", latest_blocks);
 }
@@ -1,14 +1,17 @@
 use std::time::{Duration, Instant};
 
-use carmine_api_core::network::{amm_address, call_lp_address, Network};
-use carmine_api_rpc_gateway::{rpc_call, BlockTag, Entrypoint, RpcNode};
+use carmine_api_core::{
+    network::{amm_address, Network},
+    pool::get_all_pool_addresses,
+};
+use carmine_api_rpc_gateway::{registry, rpc_call, BlockTag, Entrypoint, RpcNode};
 use dotenvy::dotenv;
 use tokio::time::sleep;
 
 #[allow(dead_code)]
 #[derive(Debug)]
 struct BenchResult {
-    node: RpcNode,
+    node: String,
     cumulative_time: u128,
     successful: usize,
     failed: usize,
@@ -21,7 +24,7 @@ impl BenchResult {
     }
 }
 
-async fn bench(node: RpcNode, number_of_runs: usize) -> BenchResult {
+async fn bench(node: &RpcNode, number_of_runs: usize) -> BenchResult {
     let mut failed: usize = 0;
     let mut successful: usize = 0;
     let mut cum_time: u128 = 0;
@@ -32,39 +35,46 @@ async fn bench(node: RpcNode, number_of_runs: usize) -> BenchResult {
         let res = rpc_call(
             amm_address(&Network::Mainnet).to_string(),
             format!("{}", Entrypoint::GetAllNonExpiredOptionsWithPremia),
-            vec![call_lp_address(&Network::Mainnet).to_owned()],
+            vec![get_all_pool_addresses(&Network::Mainnet)[0].to_owned()],
             BlockTag::Latest,
             node,
         )
         .await;
-        let t = before.elapsed().as_millis();
+        let elapsed = before.elapsed();
         match res {
             Ok(_) => {
+                node.record_success(elapsed);
                 successful += 1;
-                cum_time += t;
+                cum_time += elapsed.as_millis();
             }
             Err(_) => {
+                node.record_failure(Instant::now());
                 failed += 1;
             }
         };
     }
 
     BenchResult {
-        node,
+        node: node.name.to_owned(),
         cumulative_time: cum_time,
         successful,
         failed,
-        average: cum_time / successful as u128,
+        average: cum_time.checked_div(successful as u128).unwrap_or(0),
     }
 }
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
 
-    bench(RpcNode::BlastAPI, 20).await.report();
-    sleep(Duration::from_secs(10)).await;
-    bench(RpcNode::Infura, 20).await.report();
-    sleep(Duration::from_secs(10)).await;
-    bench(RpcNode::CarmineJunoNode, 20).await.report();
+    let nodes = registry().nodes_for(&Network::Mainnet, Instant::now());
+    for (i, node) in nodes.into_iter().enumerate() {
+        if i > 0 {
+            sleep(Duration::from_secs(10)).await;
+        }
+        bench(node, 20).await.report();
+    }
+
+    println!("{:#?}", registry().stats(&Network::Mainnet));
 }
@@ -1,25 +1,18 @@
 use core::fmt;
-use std::env;
+use std::{future::Future, time::Instant};
 
 use carmine_api_core::{
+    metrics,
     network::{amm_address, Network},
     types::DbBlock,
 };
-use lazy_static::lazy_static;
 use reqwest::RequestBuilder;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{instrument, warn};
 
-lazy_static! {
-    static ref BLAST_API_URL: String =
-        env::var("BLAST_API_URL").expect("missing env var BLAST_API_URL");
-    static ref INFURA_URL: String = env::var("INFURA_URL").expect("missing env var INFURA_URL");
-    static ref INFURA_TESTNET_URL: String =
-        env::var("INFURA_TESTNET_URL").expect("missing env var INFURA_TESTNET_URL");
-    static ref CARMINE_JUNO_NODE_URL: String =
-        env::var("CARMINE_JUNO_NODE_URL").expect("missing env var CARMINE_JUNO_NODE_URL");
-    static ref CARMINE_JUNO_TESTNET_NODE_URL: String = env::var("CARMINE_JUNO_TESTNET_NODE_URL")
-        .expect("missing env var CARMINE_JUNO_TESTNET_NODE_URL");
-}
+mod nodes;
+
+pub use nodes::{registry, NodeRegistry, NodeStats, RpcNode};
 
 #[derive(Debug, Serialize)]
 pub struct RpcCallData {
@@ -38,11 +31,55 @@ pub enum BlockTag {
     Number(i64),
 }
 
+/// Filter of "starknet_getEvents", blocks are inclusive
+#[derive(Debug, Clone, Serialize)]
+pub struct EventFilter {
+    pub from_block: BlockTag,
+    pub to_block: BlockTag,
+    pub address: String,
+    pub keys: Vec<Vec<String>>,
+    pub chunk_size: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(untagged)]
 enum Params {
     CallData(RpcCallData),
     BlockTag(BlockTag),
+    Filter(EventFilter),
+    TransactionHash(String),
+}
+
+/// Event returned by "starknet_getEvents", block is missing for pending events
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmittedEvent {
+    pub from_address: String,
+    pub keys: Vec<String>,
+    pub data: Vec<String>,
+    pub block_hash: Option<String>,
+    pub block_number: Option<i64>,
+    pub transaction_hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsChunk {
+    pub events: Vec<EmittedEvent>,
+    pub continuation_token: Option<String>,
+}
+
+/// Event as listed in the transaction receipt
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReceiptEvent {
+    pub from_address: String,
+    pub keys: Vec<String>,
+    pub data: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransactionReceipt {
+    events: Vec<ReceiptEvent>,
 }
 
 #[derive(Debug, Serialize)]
@@ -85,6 +122,13 @@ pub enum Entrypoint {
     GetPoolVolatilityAuto,
     #[serde(rename = "0x2902df4b2064da30c68f1bfad76271da9c6b10a3cfc41396ae75eef960bfcb")]
     GetOptionPosition,
+    #[serde(rename = "0x32faf317c9b96f569665659a9b7e78b9004b1f835594ef6dc644cb9405949a3")]
+    GetPoolDefinitionFromLptokenAddress,
+    // ERC20
+    #[serde(rename = "0x216b05c387bab9ac31918a3e61672f4618601f3c598a2f3f2710f37053e1ea4")]
+    Symbol,
+    #[serde(rename = "0x4c4fb1ab068f6039d5780c68dd0fa2f8742cceb3426d19667778ca7f3518a9")]
+    Decimals,
     #[serde(untagged)]
     Literal(String),
 }
@@ -152,6 +196,18 @@ impl fmt::Display for Entrypoint {
                 f,
                 "0x2902df4b2064da30c68f1bfad76271da9c6b10a3cfc41396ae75eef960bfcb"
             ),
+            Entrypoint::GetPoolDefinitionFromLptokenAddress => write!(
+                f,
+                "0x32faf317c9b96f569665659a9b7e78b9004b1f835594ef6dc644cb9405949a3"
+            ),
+            Entrypoint::Symbol => write!(
+                f,
+                "0x216b05c387bab9ac31918a3e61672f4618601f3c598a2f3f2710f37053e1ea4"
+            ),
+            Entrypoint::Decimals => write!(
+                f,
+                "0x4c4fb1ab068f6039d5780c68dd0fa2f8742cceb3426d19667778ca7f3518a9"
+            ),
             Entrypoint::Literal(s) => write!(f, "{}", s.clone()),
         }
     }
@@ -162,21 +218,23 @@ pub enum Contract {
     AMMTestnet,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum RpcNode {
-    BlastAPI,
-    Infura,
-    InfuraTestnet,
-    CarmineJunoNode,
-    CarmineTestnetJunoNode,
-}
-
-#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 struct RpcErrorResponse {
-    code: u8,
+    code: i64,
     message: String,
-    data: String,
+    // string in older RPC versions, object with the contract error since 0.6
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+}
+
+impl RpcErrorResponse {
+    fn detail(self) -> String {
+        match self.data {
+            Some(serde_json::Value::String(data)) => data,
+            Some(data) => data.to_string(),
+            None => self.message,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -184,6 +242,8 @@ pub enum RpcError {
     ContractNotFound,
     ContractError(String),
     BlockNotFound,
+    // rejected by the node, other nodes would reject it as well
+    InvalidRequest(String),
     Other(String),
 }
 
@@ -223,21 +283,48 @@ pub fn build_call_body(
     }
 }
 
-fn rpc_request<T: Serialize>(body: T, node: RpcNode) -> RequestBuilder {
-    let url: String = match node {
-        RpcNode::BlastAPI => BLAST_API_URL.to_string(),
-        RpcNode::Infura => INFURA_URL.to_string(),
-        RpcNode::InfuraTestnet => INFURA_TESTNET_URL.to_string(),
-        RpcNode::CarmineJunoNode => CARMINE_JUNO_NODE_URL.to_string(),
-        RpcNode::CarmineTestnetJunoNode => CARMINE_JUNO_TESTNET_NODE_URL.to_string(),
-    };
-
+fn rpc_request<T: Serialize>(body: T, node: &RpcNode) -> RequestBuilder {
     let client = reqwest::Client::new();
 
-    client.post(url).json(&body)
+    client.post(&node.url).json(&body)
+}
+
+/// Tries nodes of the network from the healthiest, records how each of them did
+async fn with_failover<T, F, Fut>(network: &Network, f: F) -> Result<T, RpcError>
+where
+    F: Fn(&'static RpcNode) -> Fut,
+    Fut: Future<Output = Result<T, RpcError>>,
+{
+    let mut last_error = RpcError::Other(format!("No RPC node for {}", network));
+
+    for node in registry().nodes_for(network, Instant::now()) {
+        let start = Instant::now();
+        match f(node).await {
+            Ok(data) => {
+                node.record_success(start.elapsed());
+                metrics::observe_rpc_call(&node.name, network, true, start.elapsed());
+                return Ok(data);
+            }
+            // if Other error, cascade to next RPC Node
+            Err(RpcError::Other(msg)) => {
+                warn!(node = %node.name, error = %msg, "RPC node failed");
+                node.record_failure(Instant::now());
+                metrics::observe_rpc_call(&node.name, network, false, start.elapsed());
+                last_error = RpcError::Other(msg);
+            }
+            // if other than Other error, node works, calling other node would give same result
+            Err(e) => {
+                node.record_success(start.elapsed());
+                metrics::observe_rpc_call(&node.name, network, true, start.elapsed());
+                return Err(e);
+            }
+        }
+    }
+
+    Err(last_error)
 }
 
-pub async fn rpc_latest_block_number(node: RpcNode) -> Result<i64, RpcError> {
+pub async fn rpc_latest_block_number(node: &RpcNode) -> Result<i64, RpcError> {
     let body = RpcCallBody {
         jsonrpc: "2.0".to_owned(),
         method: "starknet_blockNumber".to_owned(),
@@ -257,7 +344,7 @@ pub async fn rpc_latest_block_number(node: RpcNode) -> Result<i64, RpcError> {
     let parsed_response_option = match response.json::<Option<RpcResponse<i64>>>().await {
         Ok(res) => res,
         Err(e) => {
-            println!("Request failed: {:?}", e);
+            warn!(node = %node.name, error = ?e, "Failed parsing block number");
             return Err(RpcError::Other("RPC block number failed".to_string()));
         }
     };
@@ -274,7 +361,8 @@ pub async fn rpc_latest_block_number(node: RpcNode) -> Result<i64, RpcError> {
     Ok(block_number)
 }
 
-pub async fn rpc_block_header(block: BlockTag, node: RpcNode) -> Result<DbBlock, RpcError> {
+#[instrument(skip(node), fields(node = %node.name, network = %node.network))]
+pub async fn rpc_block_header(block: BlockTag, node: &RpcNode) -> Result<DbBlock, RpcError> {
     let params = vec![Params::BlockTag(block)];
     let body = RpcCallBody {
         jsonrpc: "2.0".to_owned(),
@@ -297,7 +385,7 @@ pub async fn rpc_block_header(block: BlockTag, node: RpcNode) -> Result<DbBlock,
     let result = match parsed_response {
         Ok(res) => res.result,
         Err(e) => {
-            println!("Request failed: {:?}", e);
+            warn!(error = ?e, "Failed parsing block header");
             return Err(RpcError::Other("RPC block header failed".to_string()));
         }
     };
@@ -308,12 +396,97 @@ pub async fn rpc_block_header(block: BlockTag, node: RpcNode) -> Result<DbBlock,
     }
 }
 
+// known errors of the request itself are not node failures,
+// anything else (eg. internal error or rate limit) is tried on the next node
+fn send_error(method: &str, e: RpcErrorResponse) -> RpcError {
+    match e.code {
+        24 => RpcError::BlockNotFound,
+        // page size too big, invalid continuation token, too many keys in filter
+        31 | 33 | 34 => RpcError::InvalidRequest(format!(
+            "{} returned code {}: {}",
+            method, e.code, e.message
+        )),
+        _ => RpcError::Other(format!(
+            "{} returned unexpected code {}: {}",
+            method, e.code, e.message
+        )),
+    }
+}
+
+// sends body and parses result
+#[instrument(skip_all, fields(method = %body.method, node = %node.name, network = %node.network))]
+async fn rpc_send<T: DeserializeOwned + fmt::Debug>(
+    body: RpcCallBody,
+    node: &RpcNode,
+) -> Result<T, RpcError> {
+    let method = body.method.to_owned();
+    let request = rpc_request(body, node);
+
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            let msg = format!("{} call failed: {:?}", method, e);
+            return Err(RpcError::Other(msg));
+        }
+    };
+
+    let rpc_response = match response.json::<RpcResponse<T>>().await {
+        Ok(data) => data,
+        Err(e) => {
+            let msg = format!("{} failed parsing response: {:?}", method, e);
+            return Err(RpcError::Other(msg));
+        }
+    };
+
+    if let Some(e) = rpc_response.error {
+        return Err(send_error(&method, e));
+    }
+    match rpc_response.result {
+        Some(data) => Ok(data),
+        None => Err(RpcError::Other(format!("{} got empty response", method))),
+    }
+}
+
+pub async fn rpc_get_events(filter: EventFilter, node: &RpcNode) -> Result<EventsChunk, RpcError> {
+    let body = RpcCallBody {
+        jsonrpc: "2.0".to_owned(),
+        method: "starknet_getEvents".to_owned(),
+        id: 0,
+        params: vec![Params::Filter(filter)],
+    };
+    rpc_send(body, node).await
+}
+
+pub async fn rpc_transaction_events(
+    transaction_hash: String,
+    node: &RpcNode,
+) -> Result<Vec<ReceiptEvent>, RpcError> {
+    let body = RpcCallBody {
+        jsonrpc: "2.0".to_owned(),
+        method: "starknet_getTransactionReceipt".to_owned(),
+        id: 0,
+        params: vec![Params::TransactionHash(transaction_hash)],
+    };
+    let receipt: TransactionReceipt = rpc_send(body, node).await?;
+    Ok(receipt.events)
+}
+
+#[instrument(
+    skip_all,
+    fields(
+        node = %node.name,
+        network = %node.network,
+        block = ?block,
+        contract = %contract_address,
+        entry_point = %entry_point_selector,
+    )
+)]
 pub async fn rpc_call(
     contract_address: String,
     entry_point_selector: String,
     calldata: Vec<String>,
     block: BlockTag,
-    node: RpcNode,
+    node: &RpcNode,
 ) -> Result<Vec<String>, RpcError> {
     let body = build_call_body(contract_address, entry_point_selector, calldata, block);
 
@@ -322,7 +495,7 @@ pub async fn rpc_call(
     let response = match request.send().await {
         Ok(response) => response,
         Err(e) => {
-            println!("call failed: {:#?}", e);
+            warn!(error = ?e, "Call request failed");
             return Err(RpcError::Other("call failed".to_string()));
         }
     };
@@ -332,7 +505,7 @@ pub async fn rpc_call(
     let rpc_response = match parsed_response {
         Ok(data) => data,
         Err(e) => {
-            println!("Request failed: {:?}", e);
+            warn!(error = ?e, "Failed parsing call response");
             return Err(RpcError::Other("RPC node request failed".to_string()));
         }
     };
@@ -341,7 +514,7 @@ pub async fn rpc_call(
         return match e.code {
             20 => Err(RpcError::ContractNotFound),
             24 => Err(RpcError::BlockNotFound),
-            40 => Err(RpcError::ContractError(e.data)),
+            40 => Err(RpcError::ContractError(e.detail())),
             _ => Err(RpcError::Other(format!(
                 "RPC returned unexpected code {}",
                 e.code
@@ -364,111 +537,122 @@ pub async fn call(
     block: BlockTag,
     network: &Network,
 ) -> Result<Vec<String>, RpcError> {
-    match network {
-        &Network::Mainnet => {
-            mainnet_call(contract_address, entry_point_selector, calldata, block).await
-        }
-        &Network::Testnet => {
-            testnet_call(contract_address, entry_point_selector, calldata, block).await
-        }
-    }
+    with_failover(network, |node| {
+        rpc_call(
+            contract_address.clone(),
+            entry_point_selector.clone(),
+            calldata.clone(),
+            block,
+            node,
+        )
+    })
+    .await
 }
 
-pub async fn testnet_call(
-    contract_address: String,
-    entry_point_selector: String,
-    calldata: Vec<String>,
-    block: BlockTag,
-) -> Result<Vec<String>, RpcError> {
-    let juno_res = rpc_call(
-        contract_address.clone(),
-        entry_point_selector.clone(),
-        calldata.to_vec(),
-        block,
-        RpcNode::CarmineTestnetJunoNode,
-    )
-    .await;
-
-    match juno_res {
-        Ok(data) => return Ok(data),
-        // if Other error, cascade to next RPC Node
-        Err(e) if matches!(e, RpcError::Other(_)) => (),
-        // if other than Other error, return error - calling other node would give same result
-        Err(e) => return Err(e),
-    };
-
-    rpc_call(
-        contract_address,
-        entry_point_selector,
-        calldata,
-        block,
-        RpcNode::InfuraTestnet,
-    )
-    .await
+pub async fn get_block_header(block: BlockTag, network: &Network) -> Result<DbBlock, RpcError> {
+    with_failover(network, |node| rpc_block_header(block, node)).await
 }
 
-pub async fn mainnet_call(
-    contract_address: String,
-    entry_point_selector: String,
-    calldata: Vec<String>,
-    block: BlockTag,
-) -> Result<Vec<String>, RpcError> {
-    let juno_res = rpc_call(
-        contract_address.clone(),
-        entry_point_selector.clone(),
-        calldata.to_vec(),
-        block,
-        RpcNode::CarmineJunoNode,
-    )
-    .await;
-
-    match juno_res {
-        Ok(data) => return Ok(data),
-        // if Other error, cascade to next RPC Node
-        Err(e) if matches!(e, RpcError::Other(_)) => (),
-        // if other than Other error, return error - calling other node would give same result
-        Err(e) => return Err(e),
-    };
+pub async fn latest_block_number(network: &Network) -> Result<i64, RpcError> {
+    with_failover(network, rpc_latest_block_number).await
+}
 
-    let blast_api_res = rpc_call(
-        contract_address.clone(),
-        entry_point_selector.clone(),
-        calldata.to_vec(),
-        block,
-        RpcNode::BlastAPI,
-    )
-    .await;
-
-    match blast_api_res {
-        Ok(data) => return Ok(data),
-        // if Other error, cascade to next RPC Node
-        Err(e) if matches!(e, RpcError::Other(_)) => (),
-        // if other than Other error, return error - calling other node would give same result
-        Err(e) => return Err(e),
-    };
+/// Latest block of every node of the network, to spot nodes falling behind
+pub async fn latest_block_numbers(network: &Network) -> Vec<(String, Result<i64, RpcError>)> {
+    let mut res = vec![];
+    for node in registry().nodes_for(network, Instant::now()) {
+        res.push((node.name.to_owned(), rpc_latest_block_number(node).await));
+    }
+    res
+}
 
-    rpc_call(
-        contract_address,
-        entry_point_selector,
-        calldata,
-        block,
-        RpcNode::Infura,
-    )
+/// Forwards raw JSON-RPC request body and returns raw response body
+pub async fn proxy_call(payload: Vec<u8>, network: &Network) -> Result<Vec<u8>, RpcError> {
+    with_failover(network, |node| {
+        let payload = payload.clone();
+        async move {
+            let response = reqwest::Client::new()
+                .post(&node.url)
+                .header("Content-Type", "application/json")
+                .body(payload)
+                .send()
+                .await
+                .map_err(|e| RpcError::Other(format!("Proxy call failed: {:?}", e)))?;
+            // 5xx and rate limit pages are node failures, not responses
+            if !response.status().is_success() {
+                return Err(RpcError::Other(format!(
+                    "Proxy call failed with status {}",
+                    response.status()
+                )));
+            }
+            response
+                .bytes()
+                .await
+                .map(|bytes| bytes.to_vec())
+                .map_err(|e| RpcError::Other(format!("Proxy call failed: {:?}", e)))
+        }
+    })
     .await
 }
 
-pub async fn blast_api_latest_block_number() -> Result<i64, RpcError> {
-    rpc_latest_block_number(RpcNode::BlastAPI).await
+/// Single page of events, next page is requested with the returned continuation token
+pub async fn get_events(filter: EventFilter, network: &Network) -> Result<EventsChunk, RpcError> {
+    with_failover(network, |node| rpc_get_events(filter.clone(), node)).await
 }
 
-pub async fn infura_latest_block_number() -> Result<i64, RpcError> {
-    rpc_latest_block_number(RpcNode::Infura).await
+/// All events emitted by the transaction, in order
+pub async fn get_transaction_events(
+    transaction_hash: &str,
+    network: &Network,
+) -> Result<Vec<ReceiptEvent>, RpcError> {
+    with_failover(network, |node| {
+        rpc_transaction_events(transaction_hash.to_owned(), node)
+    })
+    .await
 }
 
-pub async fn carmine_get_block_header(block: BlockTag) -> Result<DbBlock, RpcError> {
-    rpc_block_header(block, RpcNode::CarmineJunoNode).await
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_standard_and_contract_errors() {
+        let invalid: RpcResponse<Vec<String>> = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","id":0,"error":{"code":-32602,"message":"Invalid params"}}"#,
+        )
+        .unwrap();
+        let e = invalid.error.unwrap();
+        assert_eq!(e.code, -32602);
+        assert_eq!(e.detail(), "Invalid params");
+
+        let contract: RpcResponse<Vec<String>> = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","id":0,"error":{"code":40,"message":"Contract error","data":{"revert_error":"Option expired"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            contract.error.unwrap().detail(),
+            r#"{"revert_error":"Option expired"}"#
+        );
+    }
 
-pub async fn carmine_latest_block_number() -> Result<i64, RpcError> {
-    rpc_latest_block_number(RpcNode::CarmineJunoNode).await
+    #[test]
+    fn send_errors() {
+        let error = |code| RpcErrorResponse {
+            code,
+            message: "failed".to_string(),
+            data: None,
+        };
+        assert!(matches!(
+            send_error("starknet_getEvents", error(33)),
+            RpcError::InvalidRequest(_)
+        ));
+        assert!(matches!(
+            send_error("starknet_getEvents", error(-32603)),
+            RpcError::Other(_)
+        ));
+        assert!(matches!(
+            send_error("starknet_getTransactionReceipt", error(24)),
+            RpcError::BlockNotFound
+        ));
+    }
 }
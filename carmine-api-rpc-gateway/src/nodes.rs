@@ -0,0 +1,342 @@
+use std::{
+    env, fs,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use carmine_api_core::network::Network;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+// weight of the newest sample in the moving averages
+const EWMA_WEIGHT: f64 = 0.2;
+// consecutive failures before the node is ejected
+const EJECT_AFTER_FAILURES: u32 = 3;
+// ejection doubles with every failed re-admission, up to the max
+const MIN_EJECTION: Duration = Duration::from_secs(30);
+const MAX_EJECTION: Duration = Duration::from_secs(600);
+// 100% error rate costs as much as this latency
+const ERROR_PENALTY_MS: f64 = 5000.0;
+
+// used when RPC_NODES_CONFIG is not set, in order of preference
+const LEGACY_NODES: [(&str, &str, Network); 5] = [
+    ("carmine-juno", "CARMINE_JUNO_NODE_URL", Network::Mainnet),
+    ("blast", "BLAST_API_URL", Network::Mainnet),
+    ("infura", "INFURA_URL", Network::Mainnet),
+    (
+        "carmine-juno-testnet",
+        "CARMINE_JUNO_TESTNET_NODE_URL",
+        Network::Testnet,
+    ),
+    ("infura-testnet", "INFURA_TESTNET_URL", Network::Testnet),
+];
+
+lazy_static! {
+    static ref REGISTRY: NodeRegistry = NodeRegistry::from_env();
+}
+
+/// Single entry of the RPC_NODES_CONFIG JSON array
+#[derive(Debug, Deserialize)]
+struct NodeConfig {
+    name: String,
+    network: String,
+    url: String,
+}
+
+#[derive(Debug, Default)]
+struct NodeHealth {
+    latency_ms: Option<f64>,
+    error_rate: f64,
+    successes: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    // failed re-admissions in a row, drives the ejection length
+    ejections: u32,
+    ejected_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub struct RpcNode {
+    pub name: String,
+    pub url: String,
+    pub network: Network,
+    // position in the config, breaks ties between equally healthy nodes
+    priority: usize,
+    health: Mutex<NodeHealth>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NodeStats {
+    pub name: String,
+    pub network: String,
+    pub latency_ms: Option<f64>,
+    pub error_rate: f64,
+    pub successes: u64,
+    pub failures: u64,
+    pub ejected: bool,
+}
+
+impl RpcNode {
+    pub fn new(name: &str, url: &str, network: Network, priority: usize) -> Self {
+        RpcNode {
+            name: name.to_owned(),
+            url: url.to_owned(),
+            network,
+            priority,
+            health: Mutex::new(NodeHealth::default()),
+        }
+    }
+
+    fn health(&self) -> MutexGuard<'_, NodeHealth> {
+        // health is only statistics, poisoned lock is still usable
+        self.health.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Node answered, even if the answer is an error of the call itself
+    pub fn record_success(&self, latency: Duration) {
+        let mut h = self.health();
+        let ms = latency.as_secs_f64() * 1000.0;
+        h.latency_ms = Some(match h.latency_ms {
+            Some(avg) => avg + EWMA_WEIGHT * (ms - avg),
+            None => ms,
+        });
+        h.error_rate -= EWMA_WEIGHT * h.error_rate;
+        h.successes += 1;
+        h.consecutive_failures = 0;
+        h.ejections = 0;
+        h.ejected_until = None;
+    }
+
+    /// Node did not answer or answered garbage
+    pub fn record_failure(&self, now: Instant) {
+        let mut h = self.health();
+        h.error_rate += EWMA_WEIGHT * (1.0 - h.error_rate);
+        h.failures += 1;
+        h.consecutive_failures += 1;
+
+        // re-admitted node gets only one chance
+        if h.consecutive_failures >= EJECT_AFTER_FAILURES || h.ejections > 0 {
+            let ejection = MIN_EJECTION
+                .saturating_mul(2u32.saturating_pow(h.ejections))
+                .min(MAX_EJECTION);
+            h.ejections += 1;
+            h.ejected_until = Some(now + ejection);
+            warn!(node = %self.name, seconds = ejection.as_secs(), "RPC node ejected");
+        }
+    }
+
+    fn is_ejected(&self, now: Instant) -> bool {
+        match self.health().ejected_until {
+            Some(until) => until > now,
+            None => false,
+        }
+    }
+
+    /// Lower is better, node without latency samples gets `default_latency_ms`
+    fn score(&self, default_latency_ms: f64) -> f64 {
+        let h = self.health();
+        h.latency_ms.unwrap_or(default_latency_ms) + h.error_rate * ERROR_PENALTY_MS
+    }
+
+    pub fn stats(&self, now: Instant) -> NodeStats {
+        let ejected = self.is_ejected(now);
+        let h = self.health();
+        NodeStats {
+            name: self.name.to_owned(),
+            network: self.network.to_string(),
+            latency_ms: h.latency_ms,
+            error_rate: h.error_rate,
+            successes: h.successes,
+            failures: h.failures,
+            ejected,
+        }
+    }
+}
+
+pub struct NodeRegistry {
+    nodes: Vec<RpcNode>,
+}
+
+impl NodeRegistry {
+    pub fn new(nodes: Vec<RpcNode>) -> Self {
+        NodeRegistry { nodes }
+    }
+
+    /// Reads JSON array of {"name", "network", "url"} from the file in RPC_NODES_CONFIG,
+    /// falls back to the node URL env variables
+    pub fn from_env() -> Self {
+        let nodes = match env::var("RPC_NODES_CONFIG") {
+            Ok(path) => match NodeRegistry::read_config(&path) {
+                Ok(nodes) => nodes,
+                Err(e) => {
+                    error!(error = %e, "Failed reading RPC nodes config");
+                    vec![]
+                }
+            },
+            Err(_) => NodeRegistry::legacy_nodes(),
+        };
+
+        if nodes.is_empty() {
+            error!("No RPC nodes configured");
+        }
+
+        NodeRegistry::new(nodes)
+    }
+
+    fn read_config(path: &str) -> Result<Vec<RpcNode>, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let configs: Vec<NodeConfig> =
+            serde_json::from_str(&content).map_err(|e| format!("{}: {}", path, e))?;
+
+        configs
+            .iter()
+            .enumerate()
+            .map(|(i, config)| {
+                let network: Network = config
+                    .network
+                    .parse()
+                    .map_err(|e| format!("{} of node {}", e, config.name))?;
+                Ok(RpcNode::new(&config.name, &config.url, network, i))
+            })
+            .collect()
+    }
+
+    fn legacy_nodes() -> Vec<RpcNode> {
+        LEGACY_NODES
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (name, var, network))| match env::var(var) {
+                Ok(url) => Some(RpcNode::new(name, &url, *network, i)),
+                Err(_) => {
+                    warn!(node = name, "{} not set, skipping RPC node", var);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Healthy nodes ordered by score, ejected nodes go last
+    /// (soonest re-admitted first) so there is always something to try
+    pub fn nodes_for(&self, network: &Network, now: Instant) -> Vec<&RpcNode> {
+        let (mut healthy, mut ejected): (Vec<&RpcNode>, Vec<&RpcNode>) = self
+            .nodes
+            .iter()
+            .filter(|node| &node.network == network)
+            .partition(|node| !node.is_ejected(now));
+
+        // nodes that were not called yet are ranked as average, not as the fastest
+        let sampled: Vec<f64> = healthy
+            .iter()
+            .filter_map(|node| node.health().latency_ms)
+            .collect();
+        let mean_latency_ms = match sampled.len() {
+            0 => 0.0,
+            n => sampled.iter().sum::<f64>() / n as f64,
+        };
+
+        healthy.sort_by(|a, b| {
+            a.score(mean_latency_ms)
+                .total_cmp(&b.score(mean_latency_ms))
+                .then(a.priority.cmp(&b.priority))
+        });
+        ejected.sort_by_key(|node| node.health().ejected_until);
+
+        healthy.extend(ejected);
+        healthy
+    }
+
+    pub fn stats(&self, network: &Network) -> Vec<NodeStats> {
+        let now = Instant::now();
+        self.nodes_for(network, now)
+            .into_iter()
+            .map(|node| node.stats(now))
+            .collect()
+    }
+}
+
+pub fn registry() -> &'static NodeRegistry {
+    &REGISTRY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(nodes: Vec<&RpcNode>) -> Vec<&str> {
+        nodes.iter().map(|node| node.name.as_str()).collect()
+    }
+
+    #[test]
+    fn prefers_healthy_and_fast_nodes() {
+        let registry = NodeRegistry::new(vec![
+            RpcNode::new("a", "http://a", Network::Mainnet, 0),
+            RpcNode::new("b", "http://b", Network::Mainnet, 1),
+            RpcNode::new("c", "http://c", Network::Testnet, 2),
+        ]);
+        let now = Instant::now();
+        assert_eq!(
+            names(registry.nodes_for(&Network::Mainnet, now)),
+            ["a", "b"]
+        );
+
+        registry.nodes[0].record_success(Duration::from_millis(300));
+        registry.nodes[1].record_success(Duration::from_millis(100));
+        assert_eq!(
+            names(registry.nodes_for(&Network::Mainnet, now)),
+            ["b", "a"]
+        );
+
+        registry.nodes[1].record_failure(now);
+        assert_eq!(
+            names(registry.nodes_for(&Network::Mainnet, now)),
+            ["a", "b"]
+        );
+    }
+
+    #[test]
+    fn unsampled_node_is_ranked_as_average() {
+        let registry = NodeRegistry::new(vec![
+            RpcNode::new("a", "http://a", Network::Mainnet, 0),
+            RpcNode::new("b", "http://b", Network::Mainnet, 1),
+            RpcNode::new("c", "http://c", Network::Mainnet, 2),
+        ]);
+        let now = Instant::now();
+
+        registry.nodes[0].record_success(Duration::from_millis(300));
+        registry.nodes[1].record_success(Duration::from_millis(100));
+        assert_eq!(
+            names(registry.nodes_for(&Network::Mainnet, now)),
+            ["b", "c", "a"]
+        );
+    }
+
+    #[test]
+    fn ejects_and_readmits() {
+        let registry = NodeRegistry::new(vec![
+            RpcNode::new("a", "http://a", Network::Mainnet, 0),
+            RpcNode::new("b", "http://b", Network::Mainnet, 1),
+        ]);
+        let node = &registry.nodes[0];
+        let now = Instant::now();
+
+        for _ in 0..EJECT_AFTER_FAILURES {
+            node.record_failure(now);
+        }
+        assert!(node.is_ejected(now));
+        // ejected node is still listed, but last
+        assert_eq!(
+            names(registry.nodes_for(&Network::Mainnet, now)),
+            ["b", "a"]
+        );
+
+        // re-admitted after the ejection, single failure ejects it for longer
+        let later = now + MIN_EJECTION;
+        assert!(!node.is_ejected(later));
+        node.record_failure(later);
+        assert!(node.is_ejected(later + MIN_EJECTION));
+
+        node.record_success(Duration::from_millis(100));
+        assert!(!node.is_ejected(later));
+    }
+}
@@ -1,15 +1,17 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use carmine_api_core::{
+    metrics,
     network::Network,
     types::{DbBlock, OracleName, TokenPair},
 };
 use carmine_api_db::{
     create_batch_of_pool_states, create_batch_of_volatilities, create_block, create_oracle_price,
-    get_last_block_in_db, get_pool_state_block_holes,
+    get_last_block_in_db, get_pool_state_block_holes, notify_backfill,
 };
 use carmine_api_rpc_gateway::BlockTag;
 use tokio::{join, time::sleep};
+use tracing::{debug, error, info, instrument, warn};
 
 use crate::{carmine::Carmine, oracle::Oracle};
 
@@ -28,22 +30,23 @@ impl AmmStateObserver {
         }
     }
 
+    #[instrument(skip(self), fields(network = %self.network, block = block_number))]
     pub async fn update_single_block(&self, block_number: i64) -> Result<(), ()> {
-        let t0 = Instant::now();
         let strk_block = match self
             .carmine
             .get_block_by_id(BlockTag::Number(block_number))
             .await
         {
             Ok(v) => v,
-            Err(_) => {
-                println!("Failed getting block number {}", block_number);
+            Err(e) => {
+                warn!(error = ?e, "Failed getting block");
                 return Err(());
             }
         };
         let block = DbBlock {
             block_number: i64::try_from(strk_block.block_number).unwrap(),
             timestamp: i64::try_from(strk_block.timestamp).unwrap(),
+            block_hash: strk_block.block_hash,
         };
 
         let (options_volatility_result, amm_state_result, pragma_eth_usdc_result) = join!(
@@ -52,7 +55,7 @@ impl AmmStateObserver {
             self.pragma.get_spot_median(TokenPair::EthUsdc, &block),
         );
 
-        println!("Fetched single block state in {:.2?}", t0.elapsed());
+        debug!("Fetched block state");
 
         match (
             options_volatility_result,
@@ -60,28 +63,39 @@ impl AmmStateObserver {
             pragma_eth_usdc_result,
         ) {
             (Ok(options_volatility), Ok(amm_state), Ok(pragma_eth_usdc)) => {
-                // got everything - store it to the database
-                create_block(&block, &self.network);
-                create_batch_of_volatilities(&options_volatility, &self.network);
-                create_batch_of_pool_states(&amm_state, &self.network);
-                create_oracle_price(&pragma_eth_usdc, &self.network);
-                Ok(())
+                // got everything - store it to the database,
+                // block goes first, the rest references it
+                let stored = create_block(&block, &self.network)
+                    .and_then(|_| create_batch_of_volatilities(&options_volatility, &self.network))
+                    .and_then(|_| create_batch_of_pool_states(&amm_state, &self.network))
+                    .and_then(|_| create_oracle_price(&pragma_eth_usdc, &self.network));
+
+                match stored {
+                    Ok(_) => Ok(()),
+                    Err(e) => {
+                        error!(error = %e, "Failed storing block");
+                        Err(())
+                    }
+                }
             }
             _ => Err(()),
         }
     }
 
     pub async fn update_state(&self, n: i64) {
-        let last_block_db = get_last_block_in_db(&self.network);
+        let last_block_db = match get_last_block_in_db(&self.network) {
+            Ok(block) => block,
+            Err(e) => {
+                warn!(error = %e, "Failed getting last block in DB, skipping this update cycle");
+                return;
+            }
+        };
         let last_block_starknet_result = self.carmine.get_latest_block().await;
 
         let last_block_starknet: DbBlock = match last_block_starknet_result {
             Ok(block) => block,
             Err(e) => {
-                println!(
-                    "Failed getting latest block, skipping this update cycle.\n{:#?}",
-                    e
-                );
+                warn!(error = ?e, "Failed getting latest block, skipping this update cycle");
                 return;
             }
         };
@@ -103,10 +117,7 @@ impl AmmStateObserver {
         let last_block_starknet: DbBlock = match last_block_starknet_result {
             Ok(block) => block,
             Err(e) => {
-                println!(
-                    "Failed getting latest block, skipping this update cycle.\n{:?}",
-                    e
-                );
+                warn!(error = ?e, "Failed getting latest block, skipping this update cycle");
                 return;
             }
         };
@@ -114,19 +125,24 @@ impl AmmStateObserver {
         let start = 190500;
         let finish = i64::try_from(last_block_starknet.block_number).unwrap();
 
-        let holes = get_pool_state_block_holes(start, finish, &Network::Mainnet);
+        let holes = match get_pool_state_block_holes(start, finish, &Network::Mainnet) {
+            Ok(holes) => holes,
+            Err(e) => {
+                warn!(error = %e, "Failed getting holes in state");
+                return;
+            }
+        };
         for block_number in holes {
-            let now = Instant::now();
             match self.update_single_block(block_number).await {
                 Ok(_) => {
-                    println!("Plugged hole #{} in {:.2?}", block_number, now.elapsed());
+                    info!(block = block_number, "Plugged hole");
+                    // state of older block is not read by incremental cache updates
+                    if let Err(e) = notify_backfill(block_number, block_number, &self.network) {
+                        warn!(block = block_number, error = %e, "Failed notifying plugged hole");
+                    }
                 }
                 Err(_) => {
-                    println!(
-                        "Failed plugging hole #{} in {:.2?}, retrying...",
-                        block_number,
-                        now.elapsed()
-                    );
+                    warn!(block = block_number, "Failed plugging hole, retrying");
                     sleep(Duration::from_secs(10)).await;
                 }
             }
@@ -134,24 +150,20 @@ impl AmmStateObserver {
     }
 
     pub async fn update_state_over_block_range(&self, start: i64, finish: i64, increment: i64) {
-        println!("getting data from block #{} to #{}", start, finish);
+        info!(network = %self.network, start, finish, "Getting state over block range");
 
         let mut n = start;
 
         while n <= finish {
-            let now = Instant::now();
             match self.update_single_block(n).await {
                 Ok(_) => {
-                    println!("Updated block #{} in {:.2?}", n, now.elapsed());
+                    info!(block = n, "Updated block");
+                    metrics::set_last_processed_block(&self.network, "amm_state", n);
                     // only increment if successful
                     n = n + increment;
                 }
                 Err(_) => {
-                    println!(
-                        "Failed updating block #{} in {:.2?}, retrying...",
-                        n,
-                        now.elapsed()
-                    );
+                    warn!(block = n, "Failed updating block, retrying");
 
                     // error is most likely rate limit
                     // wait 3s to be able to fetch again
@@ -160,6 +172,6 @@ impl AmmStateObserver {
             }
         }
 
-        println!("State updated");
+        info!(network = %self.network, "State updated");
     }
 }
@@ -6,14 +6,17 @@ use carmine_api_db::{create_oracle_price, get_block_by_number};
 use carmine_api_starknet::{amm_state::AmmStateObserver, oracle::Oracle};
 use dotenvy::dotenv;
 use futures::future::try_join_all;
-use tokio::time::sleep;
 use std::{env, time::Duration};
+use tokio::time::sleep;
+use tracing::{info, warn};
 
 async fn add_price_for_block(pragma: &Oracle, block: &DbBlock) -> Result<(), ()> {
     let pragma_eth_usdc_result = pragma.get_spot_median(TokenPair::EthUsdc, block).await;
     if let Ok(pragma_eth_usdc) = pragma_eth_usdc_result {
-        create_oracle_price(&pragma_eth_usdc, &Network::Mainnet);
-        println!("updated prices for block {}", block.block_number);
+        if create_oracle_price(&pragma_eth_usdc, &Network::Mainnet).is_err() {
+            return Err(());
+        }
+        info!(network = %Network::Mainnet, block = block.block_number, "Updated prices");
         return Ok(());
     } else {
         return Err(());
@@ -23,6 +26,7 @@ async fn add_price_for_block(pragma: &Oracle, block: &DbBlock) -> Result<(), ()>
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
     env::set_var("ENVIRONMENT", "docker");
     env::set_var("DB_IP", "34.76.28.66");
 
@@ -39,8 +43,8 @@ async fn main() {
         for n in (current_block_number - increment + 1)..=current_block_number {
             let block_res = get_block_by_number(n, &Network::Mainnet);
             match block_res {
-                Some(block) => blocks.push(block),
-                None => missing_block_numbers.push(n),
+                Ok(Some(block)) => blocks.push(block),
+                _ => missing_block_numbers.push(n),
             }
         }
 
@@ -53,12 +57,12 @@ async fn main() {
             sleep(Duration::from_secs(5)).await;
             let res = state_updater.update_single_block(block_number).await;
             match res {
-                Ok(_) => println!("Updated state for block {}", block_number),
+                Ok(_) => info!(network = %Network::Mainnet, block = block_number, "Updated state"),
                 Err(_) => {
-                    println!("Failed updating state for block {} !!!", block_number);
+                    warn!(network = %Network::Mainnet, block = block_number, "Failed updating state");
                     // is still missing, add it back to the vec
                     missing_block_numbers.push(block_number);
-                },
+                }
             };
         }
 
@@ -66,18 +70,20 @@ async fn main() {
         let futures = blocks.iter().map(|b| add_price_for_block(&pragma, b));
         match try_join_all(futures).await {
             Ok(_) => {
-                println!(
-                    "Updated block range {} - {}",
-                    current_block_number,
-                    current_block_number - increment + 1
+                info!(
+                    network = %Network::Mainnet,
+                    from_block = current_block_number - increment + 1,
+                    to_block = current_block_number,
+                    "Updated block range"
                 );
                 current_block_number -= increment;
             }
             Err(_) => {
-                println!(
-                    "Block range {} - {} failed, retrying...",
-                    current_block_number,
-                    current_block_number - increment + 1
+                warn!(
+                    network = %Network::Mainnet,
+                    from_block = current_block_number - increment + 1,
+                    to_block = current_block_number,
+                    "Failed updating block range, retrying"
                 );
             }
         }
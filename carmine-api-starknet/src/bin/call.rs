@@ -1,15 +1,16 @@
-use carmine_api_rpc_gateway::{
-    blast_api_latest_block_number, carmine_latest_block_number, infura_latest_block_number,
-};
+use carmine_api_core::network::Network;
+use carmine_api_rpc_gateway::latest_block_numbers;
 use dotenvy::dotenv;
+use tracing::info;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
 
-    let c = carmine_latest_block_number().await.unwrap();
-    let b = blast_api_latest_block_number().await.unwrap();
-    let i = infura_latest_block_number().await.unwrap();
+    let network = Network::Mainnet;
 
-    println!("carmine: {}, blast api: {}, infura: {}", c, b, i);
+    for (node, block_number) in latest_block_numbers(&network).await {
+        info!(network = %network, node = %node, block = ?block_number, "Latest block");
+    }
 }
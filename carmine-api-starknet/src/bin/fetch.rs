@@ -6,10 +6,12 @@ use carmine_api_db::create_batch_of_starkscan_events;
 
 use carmine_api_starknet::starkscan::get_block_range_events;
 use dotenvy::dotenv;
+use tracing::{info, warn};
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
 
     let network = &Network::Mainnet;
     let protocols = vec![
@@ -53,11 +55,19 @@ async fn main() {
         while current < max {
             let new_events =
                 get_block_range_events(protocol, network, current, current + increment).await;
-            println!("{} fetched {} - {}", protocol, current, current + increment);
+            info!(
+                network = %network,
+                protocol = %protocol,
+                from_block = current,
+                to_block = current + increment,
+                "Fetched events"
+            );
             current = current + increment;
             events.extend(new_events);
         }
     }
 
-    create_batch_of_starkscan_events(&events, network);
+    if let Err(e) = create_batch_of_starkscan_events(&events, network) {
+        warn!(network = %network, error = %e, "Failed storing events");
+    }
 }
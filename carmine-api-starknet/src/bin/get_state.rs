@@ -1,24 +1,29 @@
+use std::fs::File;
 use std::io::prelude::*;
-use std::{fs::File, time::Instant};
 
-use carmine_api_core::network::{call_lp_address, Network};
+use carmine_api_core::{network::Network, pool::get_all_pools};
 use carmine_api_db::{get_options_volatility, get_pool_state};
 use dotenvy::dotenv;
 use serde_json::to_string_pretty;
+use tracing::info;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
 
     let network = Network::Mainnet;
 
-    let now = Instant::now();
-    let state = get_pool_state(call_lp_address(&network), &network);
-    println!("Reading state took {:.2?}", now.elapsed());
+    let pool = get_all_pools(&network)
+        .into_iter()
+        .find(|pool| pool.id == "eth-usdc-call")
+        .expect("ETH/USDC Call pool not registered");
 
-    let now = Instant::now();
-    let opt_vol = get_options_volatility(&network);
-    println!("Reading options took {:.2?}", now.elapsed());
+    let state = get_pool_state(&pool.address, &network).expect("Failed reading state");
+    info!(network = %network, pool = %pool.id, states = state.len(), "Read pool state");
+
+    let opt_vol = get_options_volatility(&network).expect("Failed reading options");
+    info!(network = %network, options = opt_vol.len(), "Read options with volatility");
 
     let mut file = File::create("state.json").unwrap();
     file.write(to_string_pretty(&state).unwrap().as_bytes())
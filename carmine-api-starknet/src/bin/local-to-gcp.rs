@@ -3,36 +3,40 @@ use std::env;
 use carmine_api_core::network::Network;
 use carmine_api_core::schema;
 use carmine_api_core::types::{Event, IOption};
-use carmine_api_db::{get_events, get_options};
+use carmine_api_db::{get_events, get_options, DbError};
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use dotenvy::dotenv;
+use tracing::{error, info};
 
-fn get_db_url(network: &Network) -> String {
-    let username = env::var("DB_USER").expect("Could not read \"DB_USER\"");
-    let password = env::var("DB_PASSWORD").expect("Could not read \"DB_PASSWORD\"");
-    let ip = env::var("DB_IP").expect("Could not read \"DB_IP\"");
+fn get_db_url(network: &Network) -> Result<String, DbError> {
+    let read = |name: &str| {
+        env::var(name).map_err(|_| DbError::Config(format!("Could not read \"{}\"", name)))
+    };
+    let username = read("DB_USER")?;
+    let password = read("DB_PASSWORD")?;
+    let ip = read("DB_IP")?;
 
-    let base = format!("postgres://{}:{}@{}", username, password, ip);
+    info!(network = %network, db_ip = %ip, "Connecting to remote database");
 
-    println!("{}", base);
+    let base = format!("postgres://{}:{}@{}", username, password, ip);
 
-    match network {
-        Network::Testnet => format!("{}/carmine-testnet", base).to_string(),
-        Network::Mainnet => format!("{}/carmine-mainnet", base).to_string(),
-    }
+    Ok(match network {
+        Network::Testnet => format!("{}/carmine-testnet", base),
+        Network::Mainnet => format!("{}/carmine-mainnet", base),
+    })
 }
 
-fn establish_connection(network: &Network) -> PgConnection {
-    let database_url = get_db_url(network);
+fn establish_connection(network: &Network) -> Result<PgConnection, DbError> {
+    let database_url = get_db_url(network)?;
     PgConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+        .map_err(|e| DbError::Connection(format!("{}: {}", database_url, e)))
 }
 
-fn create_batch_of_events(new_events: &Vec<Event>, network: &Network) {
+fn create_batch_of_events(new_events: &Vec<Event>, network: &Network) -> Result<(), DbError> {
     use crate::schema::events::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = establish_connection(network)?;
 
     let chunks = new_events.chunks(100);
 
@@ -41,14 +45,16 @@ fn create_batch_of_events(new_events: &Vec<Event>, network: &Network) {
             .values(chunk)
             .on_conflict_do_nothing()
             .execute(&mut connection)
-            .expect("Error saving batch of events");
+            .map_err(DbError::query("Error saving batch of events"))?;
     }
+
+    Ok(())
 }
 
-fn create_batch_of_options(new_options: &Vec<IOption>, network: &Network) {
+fn create_batch_of_options(new_options: &Vec<IOption>, network: &Network) -> Result<(), DbError> {
     use crate::schema::options::dsl::*;
 
-    let mut connection = establish_connection(network);
+    let mut connection = establish_connection(network)?;
 
     let chunks = new_options.chunks(100);
 
@@ -57,23 +63,28 @@ fn create_batch_of_options(new_options: &Vec<IOption>, network: &Network) {
             .values(chunk)
             .on_conflict_do_nothing()
             .execute(&mut connection)
-            .expect("Error saving batch of events");
+            .map_err(DbError::query("Error saving batch of options"))?;
     }
+
+    Ok(())
 }
 
-fn transfer_data(n: &Network) {
-    let local_events = get_events(n);
-    let local_options = get_options(n);
+fn transfer_data(n: &Network) -> Result<(), DbError> {
+    let local_events = get_events(n)?;
+    let local_options = get_options(n)?;
 
-    create_batch_of_events(&local_events, n);
-    create_batch_of_options(&local_options, n);
+    create_batch_of_events(&local_events, n)?;
+    create_batch_of_options(&local_options, n)
 }
 
 fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
     let networks = vec![Network::Testnet, Network::Mainnet];
 
     for n in networks.iter() {
-        transfer_data(n);
+        if let Err(e) = transfer_data(n) {
+            error!(network = %n, error = %e, "Failed transferring data");
+        }
     }
 }
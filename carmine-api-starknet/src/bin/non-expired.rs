@@ -1,13 +1,16 @@
 use carmine_api_core::network::Network;
 use carmine_api_starknet::carmine::Carmine;
 use dotenvy::dotenv;
+use tracing::info;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
 
-    let carmine = Carmine::new(Network::Testnet);
+    let network = Network::Testnet;
+    let carmine = Carmine::new(network);
     let res = carmine.get_all_non_expired_options_with_premia().await;
 
-    println!("RESULT: {:#?}", res);
+    info!(network = %network, result = ?res, "Non expired options with premia");
 }
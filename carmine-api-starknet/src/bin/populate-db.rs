@@ -2,6 +2,7 @@ use carmine_api_core::network::Network;
 use carmine_api_db::{get_events, get_options};
 use carmine_api_starknet::{carmine::Carmine, starkscan::get_events_from_starkscan};
 use dotenvy::dotenv;
+use tracing::info;
 
 async fn populate_network(n: &Network) {
     let c = Carmine::new(*n);
@@ -9,17 +10,18 @@ async fn populate_network(n: &Network) {
 }
 
 fn validate_db(n: &Network) {
-    println!(
-        "Network {}\nevents: {}\noptions: {}",
-        n,
-        get_events(n).len(),
-        get_options(n).len(),
+    info!(
+        network = %n,
+        events = get_events(n).map(|v| v.len()).unwrap_or_default(),
+        options = get_options(n).map(|v| v.len()).unwrap_or_default(),
+        "Validated database"
     );
 }
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
 
     let networks = vec![Network::Testnet, Network::Mainnet];
 
@@ -1,19 +1,27 @@
-use carmine_api_core::types::{OracleName, TokenPair, DbBlock};
+use carmine_api_core::types::{DbBlock, OracleName, TokenPair};
 use carmine_api_starknet::oracle::Oracle;
+use tracing::{info, warn};
 
 #[tokio::main]
 async fn main() {
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
+
+    let block_number = 75312;
     let pragma = Oracle::new(OracleName::Pragma);
 
     let res = pragma
         .get_spot_median(
             TokenPair::EthUsdc,
             &DbBlock {
-                block_number: 75312,
+                block_number,
                 timestamp: 1686136008,
+                block_hash: None,
             },
         )
         .await;
 
-    println!("{:?}", res);
+    match res {
+        Ok(price) => info!(block = block_number, price = ?price, "Pragma spot median"),
+        Err(e) => warn!(block = block_number, error = ?e, "Failed getting Pragma spot median"),
+    }
 }
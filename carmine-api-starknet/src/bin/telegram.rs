@@ -2,15 +2,17 @@ use std::env;
 
 use carmine_api_core::telegram_bot;
 use dotenvy::dotenv;
+use tracing::info;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
 
-    let bot_token = env::var("BOT_TOKEN").unwrap();
+    let bot_token_set = env::var("BOT_TOKEN").is_ok();
     let chat_id = env::var("CHAT_ID").unwrap();
 
-    println!("bot_token: {}, chat_id: {}", bot_token, chat_id);
+    info!(bot_token_set, chat_id = %chat_id, "Sending test message");
 
     let _ = telegram_bot::send_message("Test message sent by Carmine API").await;
 }
@@ -1,11 +1,13 @@
 use carmine_api_starknet::update_database_events;
 use dotenvy::dotenv;
+use tracing::info;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
 
-    println!("Updating events...");
+    info!("Updating events");
     update_database_events().await;
-    println!("DONE");
+    info!("Events updated");
 }
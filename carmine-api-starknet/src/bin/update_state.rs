@@ -2,10 +2,12 @@ use std::env;
 
 use carmine_api_starknet::amm_state::AmmStateObserver;
 use dotenvy::dotenv;
+use tracing::info;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
     env::set_var("ENVIRONMENT", "docker");
     env::set_var("DB_IP", "34.159.91.62");
 
@@ -15,14 +17,14 @@ async fn main() {
 
     let res = state_updater.update_single_block(n1).await;
     match res {
-        Ok(_) => println!("Updated state for block {}", n1),
+        Ok(_) => info!(block = n1, "Updated state"),
         Err(_) => panic!("Failed updating block"),
     }
 
     // for n in 41432..=41434 {
     //     let res = state_updater.update_single_block(n).await;
     //     match res {
-    //         Ok(_) => println!("Updated state for block {}", n),
+    //         Ok(_) => info!(block = n, "Updated state"),
     //         Err(_) => panic!("Failed updating block"),
     //     }
     // }
@@ -3,14 +3,17 @@ use std::env;
 use carmine_api_core::network::Network;
 use carmine_api_db::get_options_volatility;
 use dotenvy::dotenv;
+use tracing::info;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let _telemetry = carmine_api_core::telemetry::init(env!("CARGO_BIN_NAME"));
     env::set_var("ENVIRONMENT", "docker");
     env::set_var("DB_IP", "34.159.91.62");
 
-    let v = get_options_volatility(&Network::Mainnet);
+    let network = Network::Mainnet;
+    let v = get_options_volatility(&network).expect("Failed reading volatility");
 
-    println!("{}", v.len());
+    info!(network = %network, options = v.len(), "Read options with volatility");
 }
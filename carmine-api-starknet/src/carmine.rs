@@ -1,15 +1,21 @@
 use carmine_api_core::network::{amm_address, Network};
-use carmine_api_core::pool::{get_all_pool_addresses, get_all_pools, Pool};
-use carmine_api_core::types::{DbBlock, IOption, OptionVolatility, PoolState};
-use carmine_api_db::{create_batch_of_options, get_option_with_address, get_options, get_pools};
-use carmine_api_rpc_gateway::{call, carmine_get_block_header, BlockTag, Entrypoint, RpcError};
+use carmine_api_core::pool::{get_all_pool_addresses, get_all_pools, Token, Type};
+use carmine_api_core::types::{
+    DbBlock, IOption, OptionVolatility, PoolState, Portfolio, UserOptionPosition, UserPoolInfo,
+};
+use carmine_api_core::utils::{felt_to_short_string, felt_to_u128};
+use carmine_api_db::{create_batch_of_options, get_option_with_address, get_options};
+use carmine_api_rpc_gateway::{call, get_block_header, BlockTag, Entrypoint, RpcError};
 use futures::future::join_all;
 use futures::FutureExt;
 use starknet::core::types::FieldElement;
 use starknet::{self};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tokio::time::sleep;
 use tokio::try_join;
+use tracing::{debug, info, instrument, warn};
+
+use crate::portfolio::{decode_options_with_position, decode_user_pool_infos};
 
 const TWO_DAYS_SECS: i64 = 172800;
 
@@ -27,16 +33,12 @@ struct FunctionDescriptor<'a> {
 }
 
 pub struct Carmine {
-    pools: Vec<Pool>,
     network: Network,
 }
 
 impl Carmine {
     pub fn new(network: Network) -> Self {
-        Carmine {
-            network,
-            pools: get_all_pools(&network),
-        }
+        Carmine { network }
     }
 
     pub async fn amm_call(
@@ -90,11 +92,7 @@ impl Carmine {
         &self,
         option_address: &str,
     ) -> Result<IOption, &str> {
-        let pool_addresses: Vec<String> = self
-            .pools
-            .iter()
-            .map(|pool| pool.address.to_string())
-            .collect();
+        let pool_addresses = get_all_pool_addresses(&self.network);
 
         let mut futures = vec![];
 
@@ -165,7 +163,7 @@ impl Carmine {
         {
             Ok(data) => Ok(data[0].to_owned()),
             Err(e) => {
-                println!("Failed \"get_option_token_address\" \n{:#?}", e);
+                warn!(error = ?e, "Failed \"get_option_token_address\"");
                 Err("Failed \"get_option_token_address\"")
             }
         }
@@ -182,7 +180,7 @@ impl Carmine {
 
         let data: Vec<String> = match contract_result {
             Err(provider_error) => {
-                println!("{:?}", provider_error);
+                warn!(error = ?provider_error, "Failed getting all options");
                 return;
             }
             Ok(v) => {
@@ -210,7 +208,7 @@ impl Carmine {
 
         for option_vec in chunks {
             if option_vec.len() != option_length {
-                println!("Wrong option_vec size!");
+                warn!(size = option_vec.len(), "Wrong option_vec size");
                 continue;
             }
 
@@ -229,10 +227,15 @@ impl Carmine {
                 &lp_address,
             );
 
-            if let Some(option_with_address) = db_hit {
-                options.push(option_with_address);
-                cache_hit += 1;
-                continue;
+            match db_hit {
+                Ok(Some(option_with_address)) => {
+                    options.push(option_with_address);
+                    cache_hit += 1;
+                    continue;
+                }
+                Ok(None) => {}
+                // not fatal, the option is fetched from the chain
+                Err(e) => warn!(error = %e, "Failed looking up option in DB"),
             }
 
             let (type_index, base_index, quote_index) = match self.network {
@@ -261,7 +264,7 @@ impl Carmine {
 
             let option_address = match option_address_result {
                 Err(e) => {
-                    println!("Failed to get option address\n{}", e);
+                    warn!(error = %e, "Failed to get option address");
                     continue;
                 }
                 Ok(v) => v.to_lowercase(),
@@ -281,12 +284,11 @@ impl Carmine {
             options.push(option);
         }
 
-        println!(
-            "{} options from cache, {} options newly fetched",
-            cache_hit, fetched
-        );
+        info!(network = %self.network, cache_hit, fetched, "Options loaded");
 
-        create_batch_of_options(&options, &self.network);
+        if let Err(e) = create_batch_of_options(&options, &self.network) {
+            warn!(network = %self.network, error = %e, "Failed storing options");
+        }
     }
 
     pub async fn get_options_with_addresses(&self) {
@@ -322,6 +324,124 @@ impl Carmine {
         Ok(data)
     }
 
+    /// Base token, quote token and type of the pool
+    pub async fn get_pool_definition(
+        &self,
+        lp_address: &str,
+    ) -> Result<(String, String, Type), ()> {
+        let data = self
+            .amm_call(
+                format!("{}", Entrypoint::GetPoolDefinitionFromLptokenAddress),
+                vec![lp_address.to_owned()],
+                BlockTag::Latest,
+            )
+            .await
+            .map_err(|e| warn!(pool = %lp_address, error = ?e, "Failed getting pool definition"))?;
+
+        // quote_token_address, base_token_address, option_type
+        if data.len() != 3 {
+            warn!(pool = %lp_address, ?data, "Unexpected pool definition");
+            return Err(());
+        }
+        let type_ = match felt_to_u128(&data[2]) {
+            Some(0) => Type::Call,
+            Some(1) => Type::Put,
+            _ => return Err(()),
+        };
+
+        Ok((data[1].to_owned(), data[0].to_owned(), type_))
+    }
+
+    /// Symbol and decimals from the ERC20 contract
+    pub async fn get_token(&self, address: &str) -> Result<Token, ()> {
+        let erc20_call = |entrypoint: Entrypoint| {
+            call(
+                address.to_owned(),
+                format!("{}", entrypoint),
+                vec![],
+                BlockTag::Latest,
+                &self.network,
+            )
+        };
+        let (symbol, decimals) = try_join!(
+            erc20_call(Entrypoint::Symbol),
+            erc20_call(Entrypoint::Decimals)
+        )
+        .map_err(|e| warn!(token = %address, error = ?e, "Failed getting token"))?;
+
+        let symbol = symbol.first().and_then(|v| felt_to_short_string(v));
+        let decimals = decimals
+            .first()
+            .and_then(|v| felt_to_u128(v))
+            .and_then(|v| u8::try_from(v).ok());
+
+        match (symbol, decimals) {
+            (Some(symbol), Some(decimals)) => Ok(Token {
+                address: address.to_owned(),
+                symbol,
+                decimals,
+            }),
+            _ => {
+                warn!(token = %address, "Unexpected token metadata");
+                Err(())
+            }
+        }
+    }
+
+    pub async fn get_option_with_position_of_user(
+        &self,
+        user_address: &str,
+    ) -> Result<Vec<UserOptionPosition>, RpcError> {
+        let mut data = self
+            .amm_call(
+                format!("{}", Entrypoint::GetOptionWithPositionOfUser),
+                vec![user_address.to_owned()],
+                BlockTag::Latest,
+            )
+            .await?;
+
+        if !data.is_empty() {
+            // first element is length of result array - remove it
+            data.remove(0);
+        }
+
+        decode_options_with_position(&data, &self.network).map_err(RpcError::Other)
+    }
+
+    pub async fn get_user_pool_infos(
+        &self,
+        user_address: &str,
+    ) -> Result<Vec<UserPoolInfo>, RpcError> {
+        let mut data = self
+            .amm_call(
+                format!("{}", Entrypoint::GetUserPoolInfos),
+                vec![user_address.to_owned()],
+                BlockTag::Latest,
+            )
+            .await?;
+
+        if !data.is_empty() {
+            // first element is length of result array - remove it
+            data.remove(0);
+        }
+
+        decode_user_pool_infos(&data, &self.network, &get_all_pools(&self.network))
+            .map_err(RpcError::Other)
+    }
+
+    pub async fn get_portfolio(&self, user_address: &str) -> Result<Portfolio, RpcError> {
+        let (options, pools) = try_join!(
+            self.get_option_with_position_of_user(user_address),
+            self.get_user_pool_infos(user_address),
+        )?;
+
+        Ok(Portfolio {
+            address: user_address.to_owned(),
+            options,
+            pools,
+        })
+    }
+
     pub async fn get_pool_single_value(
         &self,
         block_number: i64,
@@ -402,6 +522,7 @@ impl Carmine {
         }
     }
 
+    #[instrument(skip(self), fields(network = %self.network, pool = %pool, block = block_number))]
     pub async fn get_locked_unlocked_total_capital_for_pool(
         &self,
         pool: String,
@@ -417,7 +538,6 @@ impl Carmine {
         ),
         RpcError,
     > {
-        let now = Instant::now();
         let res = try_join!(
             self.get_pool_locked_capital(block_number, pool.to_owned()),
             self.get_unlocked_capital(block_number, pool.to_owned()),
@@ -425,11 +545,6 @@ impl Carmine {
             self.get_value_of_pool_position(block_number, pool.to_owned()),
             self.get_value_of_lp_token(block_number, pool.to_owned()),
         );
-        println!(
-            "Fetched pool state {} in {:.2?}",
-            pool.get(0..10).unwrap(),
-            now.elapsed()
-        );
         match res {
             Ok((
                 pool_locked_capital,
@@ -446,18 +561,14 @@ impl Carmine {
                 pool,
             )),
             Err(e) => {
-                println!("Failed getting balance data in block #{}", block_number);
-                println!("{:#?}", e);
+                warn!(error = ?e, "Failed getting pool balance data");
                 Err(e)
             }
         }
     }
 
     pub async fn get_amm_state(&self, block: &DbBlock) -> Result<Vec<PoolState>, ()> {
-        let pool_addresses: Vec<String> = get_pools(&self.network)
-            .iter()
-            .map(|p| p.lp_address.to_owned())
-            .collect();
+        let pool_addresses = get_all_pool_addresses(&self.network);
 
         let mut futures = vec![];
 
@@ -505,13 +616,18 @@ impl Carmine {
         Ok(cumulative_state)
     }
 
+    #[instrument(skip_all, fields(network = %self.network, block = block.block_number))]
     pub async fn get_all_options_volatility(
         &self,
         block: &DbBlock,
     ) -> Result<Vec<OptionVolatility>, ()> {
-        let now = Instant::now();
-
-        let options = get_options(&self.network);
+        let options = match get_options(&self.network) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(error = %e, "Failed getting options");
+                return Err(());
+            }
+        };
         let mut to_store: Vec<OptionVolatility> = vec![];
 
         let mut non_expired_options: Vec<IOption> = vec![];
@@ -561,7 +677,7 @@ impl Carmine {
                 });
             }
         }
-        println!("Options volatility fetched in {:.2?}", now.elapsed());
+        debug!(options = to_store.len(), "Options volatility fetched");
         Ok(to_store)
     }
 
@@ -609,7 +725,7 @@ impl Carmine {
     }
 
     pub async fn get_block_by_id(&self, block_tag: BlockTag) -> Result<DbBlock, RpcError> {
-        carmine_get_block_header(block_tag).await
+        get_block_header(block_tag, &self.network).await
     }
 
     pub async fn get_latest_block(&self) -> Result<DbBlock, RpcError> {
@@ -0,0 +1,134 @@
+use std::env;
+
+use carmine_api_core::{
+    network::{Network, Protocol},
+    types::StarkScanEventSettled,
+};
+use carmine_api_db::get_last_block_for_protocol_event;
+use futures::future::BoxFuture;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    rpc_events::fetch_rpc_events,
+    starkscan::{fetch_events, StarkscanUrlBuilder},
+};
+
+/// Source of protocol events, all of them produce the same "starkscan_events" rows
+pub trait EventSource: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Settled events from the block on, empty if fetching failed
+    fn events_from_block<'a>(
+        &'a self,
+        network: &'a Network,
+        protocol: &'a Protocol,
+        from_block: u32,
+    ) -> BoxFuture<'a, Vec<StarkScanEventSettled>>;
+}
+
+pub struct Starkscan;
+
+impl EventSource for Starkscan {
+    fn name(&self) -> &'static str {
+        "Starkscan"
+    }
+
+    fn events_from_block<'a>(
+        &'a self,
+        network: &'a Network,
+        protocol: &'a Protocol,
+        from_block: u32,
+    ) -> BoxFuture<'a, Vec<StarkScanEventSettled>> {
+        Box::pin(async move {
+            let url = StarkscanUrlBuilder::new(network)
+                .protocol(protocol)
+                .from_block(from_block)
+                .get_url();
+            fetch_events(url, 0).await
+        })
+    }
+}
+
+/// "starknet_getEvents" on our own RPC nodes
+pub struct Rpc;
+
+impl EventSource for Rpc {
+    fn name(&self) -> &'static str {
+        "RPC"
+    }
+
+    fn events_from_block<'a>(
+        &'a self,
+        network: &'a Network,
+        protocol: &'a Protocol,
+        from_block: u32,
+    ) -> BoxFuture<'a, Vec<StarkScanEventSettled>> {
+        Box::pin(fetch_rpc_events(network, protocol, from_block))
+    }
+}
+
+fn source_for(protocol: &Protocol, rpc_protocols: &str) -> &'static dyn EventSource {
+    let name = protocol.to_string();
+    match rpc_protocols.split(',').any(|p| p.trim() == name) {
+        true => &Rpc,
+        false => &Starkscan,
+    }
+}
+
+/// Starkscan unless the protocol is listed in RPC_EVENT_PROTOCOLS,
+/// eg. "CarmineOptions,zETH" (names as in the "starkscan_events" rows)
+pub fn event_source(protocol: &Protocol) -> &'static dyn EventSource {
+    source_for(
+        protocol,
+        &env::var("RPC_EVENT_PROTOCOLS").unwrap_or_default(),
+    )
+}
+
+/// New events of the protocol, starting from the last block stored in the DB
+#[instrument(skip_all, fields(network = %network, protocol = %protocol, source = source.name()))]
+pub async fn get_protocol_events(
+    network: &Network,
+    protocol: &Protocol,
+    source: &dyn EventSource,
+) -> Vec<StarkScanEventSettled> {
+    let last_block_number: u32 = match get_last_block_for_protocol_event(network, protocol) {
+        Ok(Some(t)) => match t.try_into() {
+            Ok(block) => block,
+            Err(_) => {
+                warn!(block = t, "Last protocol event block out of range");
+                return vec![];
+            }
+        },
+        Ok(None) => 0,
+        // do not start over from block 0 just because DB is not available
+        Err(e) => {
+            warn!(error = %e, "Failed getting last protocol event block");
+            return vec![];
+        }
+    };
+    info!(block = last_block_number, "Fetching protocol events");
+    source
+        .events_from_block(network, protocol, last_block_number)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_starkscan() {
+        assert_eq!(
+            source_for(&Protocol::CarmineOptions, "").name(),
+            "Starkscan"
+        );
+        assert_eq!(
+            source_for(&Protocol::CarmineOptions, "zETH, CarmineOptions").name(),
+            "RPC"
+        );
+        assert_eq!(
+            source_for(&Protocol::ZkLend, "CarmineOptions").name(),
+            "Starkscan"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use carmine_api_core::{
+    network::Network,
+    pool::{Pool, Type},
+    types::{Greeks, OptionWithVolatility, OraclePriceConcise},
+    utils::same_felt,
+};
+
+use crate::{
+    pnl::{strike_to_f64, OptionWithPremia},
+    portfolio::{is_cairo_1, parse_fixed},
+};
+
+const SECONDS_IN_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+const SECONDS_IN_DAY: f64 = 24.0 * 60.0 * 60.0;
+// the AMM prices options without interest
+const RISK_FREE_RATE: f64 = 0.0;
+// bounds and precision of the implied volatility search
+const MIN_VOLATILITY: f64 = 0.0001;
+const MAX_VOLATILITY: f64 = 10.0;
+const IV_ITERATIONS: usize = 100;
+
+fn normal_pdf(x: f64) -> f64 {
+    (-x * x / 2.0).exp() / (2.0 * PI).sqrt()
+}
+
+/// Zelen & Severo approximation, absolute error below 7.5e-8
+fn normal_cdf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.2316419 * x.abs());
+    let poly = t
+        * (0.319381530
+            + t * (-0.356563782 + t * (1.781477937 + t * (-1.821255978 + t * 1.330274429))));
+    let upper_tail = normal_pdf(x) * poly;
+    match x >= 0.0 {
+        true => 1.0 - upper_tail,
+        false => upper_tail,
+    }
+}
+
+/// Inputs of the Black-Scholes model, prices are in the quote token
+#[derive(Debug, Clone, Copy)]
+pub struct BlackScholes {
+    pub spot: f64,
+    pub strike: f64,
+    /// in years
+    pub time_to_maturity: f64,
+    /// annualized, 0.8 is 80 %
+    pub volatility: f64,
+    pub type_: Type,
+}
+
+impl BlackScholes {
+    fn d1_d2(&self) -> (f64, f64) {
+        let vol_sqrt_t = self.volatility * self.time_to_maturity.sqrt();
+        let d1 = ((self.spot / self.strike).ln()
+            + (RISK_FREE_RATE + self.volatility.powi(2) / 2.0) * self.time_to_maturity)
+            / vol_sqrt_t;
+        (d1, d1 - vol_sqrt_t)
+    }
+
+    /// price of a long option for 1 unit of the base token
+    pub fn price(&self) -> f64 {
+        let (d1, d2) = self.d1_d2();
+        let discounted_strike = self.strike * (-RISK_FREE_RATE * self.time_to_maturity).exp();
+        match self.type_ {
+            Type::Call => self.spot * normal_cdf(d1) - discounted_strike * normal_cdf(d2),
+            Type::Put => discounted_strike * normal_cdf(-d2) - self.spot * normal_cdf(-d1),
+        }
+    }
+
+    /// delta, gamma, theta per day and vega per 1 % of volatility of a long option
+    pub fn greeks(&self) -> (f64, f64, f64, f64) {
+        let (d1, d2) = self.d1_d2();
+        let sqrt_t = self.time_to_maturity.sqrt();
+        let discounted_strike = self.strike * (-RISK_FREE_RATE * self.time_to_maturity).exp();
+        let decay = -self.spot * normal_pdf(d1) * self.volatility / (2.0 * sqrt_t);
+
+        let (delta, theta) = match self.type_ {
+            Type::Call => (
+                normal_cdf(d1),
+                decay - RISK_FREE_RATE * discounted_strike * normal_cdf(d2),
+            ),
+            Type::Put => (
+                normal_cdf(d1) - 1.0,
+                decay + RISK_FREE_RATE * discounted_strike * normal_cdf(-d2),
+            ),
+        };
+        let gamma = normal_pdf(d1) / (self.spot * self.volatility * sqrt_t);
+        let vega = self.spot * normal_pdf(d1) * sqrt_t;
+
+        (delta, gamma, theta / 365.0, vega / 100.0)
+    }
+
+    /// volatility for which the model price equals the given price, None if out of bounds
+    pub fn implied_volatility(&self, price: f64) -> Option<f64> {
+        let with_volatility = |volatility: f64| {
+            BlackScholes {
+                volatility,
+                ..*self
+            }
+            .price()
+        };
+        let (mut low, mut high) = (MIN_VOLATILITY, MAX_VOLATILITY);
+        if !(with_volatility(low)..=with_volatility(high)).contains(&price) {
+            return None;
+        }
+        // price is increasing in volatility
+        for _ in 0..IV_ITERATIONS {
+            let mid = (low + high) / 2.0;
+            match with_volatility(mid) < price {
+                true => low = mid,
+                false => high = mid,
+            }
+        }
+        Some((low + high) / 2.0)
+    }
+}
+
+/// Latest Pragma price of the pool's pair, eg. "eth-usdc"
+pub fn spot_price(
+    pool: &Pool,
+    oracle_prices: &HashMap<String, Vec<OraclePriceConcise>>,
+) -> Option<f64> {
+    oracle_prices
+        .get(&pool.pair_id())?
+        .iter()
+        .max_by_key(|p| p.block_number)
+        .map(|p| p.price as f64 / 10f64.powi(p.decimals as i32))
+}
+
+/// AMM volatility in %, Cairo 1 only stores the magnitude
+pub fn volatility_to_f64(felt: &str, network: &Network) -> Option<f64> {
+    let felts = match is_cairo_1(network) {
+        true => vec![felt.to_owned(), "0x0".to_owned()],
+        false => vec![felt.to_owned()],
+    };
+    parse_fixed(&felts, network, "volatility").ok()
+}
+
+/// Last stored AMM volatility of the option in %
+fn latest_volatility(
+    option: &OptionWithPremia,
+    pool: &Pool,
+    option_volatility: &[OptionWithVolatility],
+    network: &Network,
+) -> Option<f64> {
+    let history = option_volatility.iter().find(|o| {
+        same_felt(&o.lp_address, &pool.address)
+            && o.option_side == option.option_side
+            && o.option_type == option.option_type
+            && o.maturity == option.maturity
+            && same_felt(&o.strike_price, &option.strike_price)
+    })?;
+    // history is ordered from the latest block
+    let felt = history
+        .volatilities
+        .iter()
+        .find_map(|v| v.volatility.to_owned())?;
+    volatility_to_f64(&felt, network)
+}
+
+/// Model of an option of the pool, None for invalid inputs or expired option
+pub fn pool_option_model(
+    pool: &Pool,
+    strike_price: &str,
+    maturity: i64,
+    volatility: f64,
+    spot: f64,
+    network: &Network,
+    now: i64,
+) -> Option<BlackScholes> {
+    let strike = strike_to_f64(strike_price, network)?;
+    let time_to_maturity = (maturity - now) as f64 / SECONDS_IN_YEAR;
+    if volatility <= 0.0 || spot <= 0.0 || strike <= 0.0 || time_to_maturity <= 0.0 {
+        return None;
+    }
+    Some(BlackScholes {
+        spot,
+        strike,
+        time_to_maturity,
+        volatility: volatility / 100.0,
+        type_: pool.type_,
+    })
+}
+
+/// 1 for long, -1 for short - short position has the opposite exposure
+pub fn side_sign(option_side: i16) -> f64 {
+    match option_side {
+        0 => 1.0,
+        _ => -1.0,
+    }
+}
+
+/// Greeks of a live option from its AMM volatility and the oracle spot price,
+/// None if any of the inputs is missing or the option is expired
+pub fn option_greeks(
+    option: &OptionWithPremia,
+    pool: &Pool,
+    option_volatility: &[OptionWithVolatility],
+    oracle_prices: &HashMap<String, Vec<OraclePriceConcise>>,
+    network: &Network,
+    now: i64,
+) -> Option<Greeks> {
+    let volatility = latest_volatility(option, pool, option_volatility, network)?;
+    let spot = spot_price(pool, oracle_prices)?;
+    let model = pool_option_model(
+        pool,
+        &option.strike_price,
+        option.maturity,
+        volatility,
+        spot,
+        network,
+        now,
+    )?;
+    let (delta, gamma, theta, vega) = model.greeks();
+
+    // premia of calls is in the base token
+    let premia_in_quote = match pool.type_ {
+        Type::Call => option.premia * spot,
+        Type::Put => option.premia,
+    };
+    let implied_volatility = match option.option_side {
+        0 => model.implied_volatility(premia_in_quote).map(|v| v * 100.0),
+        // short premia is what the seller gets after fees, not a model price
+        _ => None,
+    };
+
+    let sign = side_sign(option.option_side);
+
+    Some(Greeks {
+        delta: sign * delta,
+        gamma: sign * gamma,
+        theta: sign * theta,
+        vega: sign * vega,
+        volatility,
+        implied_volatility,
+        spot_price: spot,
+        days_to_maturity: (option.maturity - now) as f64 / SECONDS_IN_DAY,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn black_scholes_reference_values() {
+        let call = BlackScholes {
+            spot: 100.0,
+            strike: 100.0,
+            time_to_maturity: 1.0,
+            volatility: 0.2,
+            type_: Type::Call,
+        };
+        let put = BlackScholes {
+            type_: Type::Put,
+            ..call
+        };
+
+        assert_close(call.price(), 7.9656);
+        // put-call parity without interest
+        assert_close(put.price(), call.price());
+
+        let (delta, gamma, theta, vega) = call.greeks();
+        assert_close(delta, 0.5398);
+        assert_close(gamma, 0.019848);
+        assert_close(theta, -3.969525 / 365.0);
+        assert_close(vega, 0.396953);
+        assert_close(put.greeks().0, delta - 1.0);
+
+        assert_close(call.implied_volatility(7.9656).unwrap(), 0.2);
+        assert_eq!(call.implied_volatility(200.0), None);
+    }
+}
@@ -1,20 +1,57 @@
 use amm_state::AmmStateObserver;
 use carmine::Carmine;
 use carmine_api_core::{
+    metrics,
     network::{Network, Protocol},
     types::StarkScanEventSettled,
 };
 use carmine_api_db::create_batch_of_starkscan_events;
-use starkscan::get_protocol_events;
+use event_source::{event_source, get_protocol_events};
+use reorg::{handle_reorg, rollback_count, StoreGuard};
 use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
 
 pub mod amm_state;
 pub mod carmine;
+pub mod event_source;
+pub mod greeks;
+pub mod option_chain;
 pub mod oracle;
+pub mod pnl;
+pub mod pool_registry;
+pub mod portfolio;
+pub mod reorg;
+pub mod risk;
+pub mod rpc_events;
 pub mod starkscan;
 
+fn set_last_event_block(network: &Network, events: &[StarkScanEventSettled]) {
+    if let Some(block_number) = events.iter().map(|e| e.block_number).max() {
+        metrics::set_last_processed_block(network, "events", block_number);
+    }
+}
+
+/// Stores the events unless the chain was rolled back since they were fetched,
+/// dropped events are fetched again in the next run
+async fn store_events(network: &Network, events: Vec<StarkScanEventSettled>, fetched_at: u64) {
+    let guard = StoreGuard::lock().await;
+    if guard.rolled_back(network, fetched_at) {
+        warn!(
+            network = %network,
+            events = events.len(),
+            "Chain rolled back while fetching events, dropping them"
+        );
+        return;
+    }
+    match create_batch_of_starkscan_events(&events, network) {
+        Ok(_) => set_last_event_block(network, &events),
+        Err(e) => warn!(network = %network, error = %e, "Failed storing Starkscan events"),
+    }
+}
+
 pub async fn update_database_events() {
     let mut events: Vec<StarkScanEventSettled> = Vec::new();
+    let mainnet_rollbacks = rollback_count(&Network::Mainnet);
 
     let protocols = [
         Protocol::CarmineOptions,
@@ -81,8 +118,9 @@ pub async fn update_database_events() {
 
     for protocol in protocols {
         // Call the get_protocol_events function for each protocol
-        let current_events = get_protocol_events(&Network::Mainnet, &protocol).await;
-        println!("Fetched {} events for {}", current_events.len(), protocol);
+        let current_events =
+            get_protocol_events(&Network::Mainnet, &protocol, event_source(&protocol)).await;
+        info!(protocol = %protocol, events = current_events.len(), "Fetched protocol events");
         // Extend the combined_events vector with the events from the current protocol
         events.extend(current_events);
 
@@ -90,23 +128,37 @@ pub async fn update_database_events() {
         sleep(Duration::from_secs(2)).await;
     }
 
-    create_batch_of_starkscan_events(&events, &Network::Mainnet);
+    store_events(&Network::Mainnet, events, mainnet_rollbacks).await;
 
-    let testnet_carmine_events =
-        get_protocol_events(&Network::Testnet, &Protocol::CarmineOptions).await;
+    let testnet_rollbacks = rollback_count(&Network::Testnet);
+    let testnet_carmine_events = get_protocol_events(
+        &Network::Testnet,
+        &Protocol::CarmineOptions,
+        event_source(&Protocol::CarmineOptions),
+    )
+    .await;
 
-    create_batch_of_starkscan_events(&testnet_carmine_events, &Network::Testnet);
+    store_events(&Network::Testnet, testnet_carmine_events, testnet_rollbacks).await;
 }
 
 pub async fn update_database_amm_state(offset: i64) {
     let networks = vec![Network::Mainnet, Network::Testnet];
     for network in networks {
+        // orphaned data is removed before anything new is stored, errors are already reported
+        let _ = handle_reorg(&network).await;
         let carmine = Carmine::new(network);
         carmine.get_options_with_addresses().await;
     }
     AmmStateObserver::new().update_state(offset).await;
 }
 
+pub async fn discover_new_pools() {
+    let networks = vec![Network::Mainnet, Network::Testnet];
+    for network in networks {
+        pool_registry::discover_pools(&network).await;
+    }
+}
+
 pub async fn plug_holes_amm_state() {
     AmmStateObserver::new().plug_holes_in_state().await;
 }
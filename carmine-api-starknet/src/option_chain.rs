@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use carmine_api_core::{
+    network::Network,
+    pool::{get_all_pools, Pool, Type},
+    types::{
+        ChainMaturity, ChainOption, ChainStrike, OptionGreeks, OptionWithVolatility,
+        OraclePriceConcise, PoolOptionChain,
+    },
+    utils::same_felt,
+};
+use tracing::warn;
+
+use crate::{
+    greeks::option_greeks,
+    pnl::{decode_options_with_premia, strike_to_f64, OptionWithPremia},
+};
+
+fn belongs_to(option: &OptionWithPremia, pool: &Pool) -> bool {
+    option.option_type == pool.type_ as i16
+        && same_felt(&option.base_token_address, &pool.base.address)
+        && same_felt(&option.quote_token_address, &pool.quote.address)
+}
+
+/// rounds to the smallest unit of the token
+fn round_to_decimals(value: f64, decimals: u8) -> f64 {
+    let unit = 10f64.powi(decimals as i32);
+    (value * unit).round() / unit
+}
+
+fn side_name(option_side: i16) -> String {
+    match option_side {
+        0 => "long".to_string(),
+        _ => "short".to_string(),
+    }
+}
+
+/// Live options from "get_all_non_expired_options_with_premia" grouped by pool,
+/// maturity and strike, ordered by maturity and strike
+pub fn build_option_chain(
+    all_non_expired: &[String],
+    option_volatility: &[OptionWithVolatility],
+    oracle_prices: &HashMap<String, Vec<OraclePriceConcise>>,
+    network: &Network,
+) -> Result<Vec<PoolOptionChain>, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs() as i64;
+    let options = decode_options_with_premia(all_non_expired, network)?;
+    let pools = get_all_pools(network);
+
+    for option in &options {
+        if !pools.iter().any(|pool| belongs_to(option, pool)) {
+            warn!(
+                network = %network,
+                base = %option.base_token_address,
+                quote = %option.quote_token_address,
+                option_type = option.option_type,
+                "Option chain: no pool for option"
+            );
+        }
+    }
+
+    let chains = pools
+        .iter()
+        .map(|pool| {
+            let currency = match pool.type_ {
+                Type::Call => &pool.base,
+                Type::Put => &pool.quote,
+            };
+
+            let mut maturities: Vec<ChainMaturity> = vec![];
+            for option in options.iter().filter(|o| belongs_to(o, pool)) {
+                let strike_price = strike_to_f64(&option.strike_price, network).unwrap_or(0.0);
+
+                let maturity = match maturities
+                    .iter()
+                    .position(|m| m.maturity == option.maturity)
+                {
+                    Some(i) => &mut maturities[i],
+                    None => {
+                        maturities.push(ChainMaturity {
+                            maturity: option.maturity,
+                            strikes: vec![],
+                        });
+                        maturities.last_mut().unwrap()
+                    }
+                };
+                let strike = match maturity
+                    .strikes
+                    .iter()
+                    .position(|s| s.strike_price == strike_price)
+                {
+                    Some(i) => &mut maturity.strikes[i],
+                    None => {
+                        maturity.strikes.push(ChainStrike {
+                            strike_price,
+                            options: vec![],
+                        });
+                        maturity.strikes.last_mut().unwrap()
+                    }
+                };
+                strike.options.push(ChainOption {
+                    option_side: side_name(option.option_side),
+                    option_type: pool.type_.to_string().to_lowercase(),
+                    premia: round_to_decimals(option.premia, currency.decimals),
+                    greeks: option_greeks(
+                        option,
+                        pool,
+                        option_volatility,
+                        oracle_prices,
+                        network,
+                        now,
+                    ),
+                });
+            }
+
+            maturities.sort_by_key(|m| m.maturity);
+            for maturity in maturities.iter_mut() {
+                maturity
+                    .strikes
+                    .sort_by(|a, b| a.strike_price.total_cmp(&b.strike_price));
+                for strike in maturity.strikes.iter_mut() {
+                    strike
+                        .options
+                        .sort_by(|a, b| a.option_side.cmp(&b.option_side));
+                }
+            }
+
+            PoolOptionChain {
+                pool_id: pool.id.to_owned(),
+                base: pool.base.symbol.to_owned(),
+                quote: pool.quote.symbol.to_owned(),
+                currency: currency.symbol.to_owned(),
+                maturities,
+            }
+        })
+        .collect();
+
+    Ok(chains)
+}
+
+/// Live options with known Greeks, flattened from the option chain
+pub fn build_greeks(
+    all_non_expired: &[String],
+    option_volatility: &[OptionWithVolatility],
+    oracle_prices: &HashMap<String, Vec<OraclePriceConcise>>,
+    network: &Network,
+) -> Result<Vec<OptionGreeks>, String> {
+    let chains = build_option_chain(all_non_expired, option_volatility, oracle_prices, network)?;
+
+    let mut res = vec![];
+    for chain in chains {
+        for maturity in chain.maturities {
+            for strike in maturity.strikes {
+                for option in strike.options {
+                    if let Some(greeks) = option.greeks {
+                        res.push(OptionGreeks {
+                            pool_id: chain.pool_id.to_owned(),
+                            option_side: option.option_side,
+                            option_type: option.option_type,
+                            maturity: maturity.maturity,
+                            strike_price: strike.strike_price,
+                            premia: option.premia,
+                            greeks,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETH: &str = "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7";
+    const USDC: &str = "0x53c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8";
+    // 1500 and 1600 in Math64x61
+    const STRIKE_1500: &str = "0xbb8000000000000000";
+    const STRIKE_1600: &str = "0xc80000000000000000";
+    // 0.125 in Math64x61
+    const PREMIA: &str = "0x400000000000000";
+
+    fn option(side: &str, maturity: &str, strike: &str) -> Vec<String> {
+        vec![side, maturity, strike, USDC, ETH, "0x0", PREMIA]
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn groups_by_maturity_and_strike() {
+        let data: Vec<String> = [
+            option("0x1", "0x6553f100", STRIKE_1600),
+            option("0x0", "0x6553f100", STRIKE_1600),
+            option("0x0", "0x6553f100", STRIKE_1500),
+            option("0x0", "0x6500f100", STRIKE_1500),
+        ]
+        .concat();
+
+        let chains = build_option_chain(&data, &[], &HashMap::new(), &Network::Mainnet).unwrap();
+        let chain = chains
+            .iter()
+            .find(|c| c.pool_id == "eth-usdc-call")
+            .unwrap();
+        assert_eq!(chain.currency, "ETH");
+        assert_eq!(chain.maturities.len(), 2);
+        assert_eq!(chain.maturities[0].maturity, 0x6500f100);
+
+        let strikes = &chain.maturities[1].strikes;
+        assert_eq!(strikes[0].strike_price, 1500.0);
+        assert_eq!(strikes[1].strike_price, 1600.0);
+        assert_eq!(strikes[1].options[0].option_side, "long");
+        assert_eq!(strikes[1].options[1].option_side, "short");
+        assert_eq!(strikes[1].options[1].option_type, "call");
+        assert_eq!(strikes[1].options[1].premia, 0.125);
+        // no volatility history nor spot price
+        assert!(strikes[1].options[1].greeks.is_none());
+
+        let put = chains.iter().find(|c| c.pool_id == "eth-usdc-put").unwrap();
+        assert!(put.maturities.is_empty());
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+
+use carmine_api_core::{
+    network::Network,
+    pool::{get_all_pools, Pool, Type},
+    types::{IOption, OptionPnl, PnlTotal, TradeHistory, UserPnl},
+    utils::{felt_to_f64, same_felt},
+};
+use tracing::warn;
+
+use crate::portfolio::{chunks, is_cairo_1, parse_fixed, parse_number};
+
+// Cairo 0: option (6) + premia Math64x61 (1)
+const OPTION_WITH_PREMIA_LENGTH_CAIRO_0: usize = 7;
+// Cairo 1: option with Fixed strike (7) + premia Fixed (2)
+const OPTION_WITH_PREMIA_LENGTH_CAIRO_1: usize = 9;
+
+// remaining size below this is considered closed
+const DUST: f64 = 1e-12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionWithPremia {
+    pub option_side: i16,
+    pub maturity: i64,
+    pub strike_price: String,
+    pub quote_token_address: String,
+    pub base_token_address: String,
+    pub option_type: i16,
+    /// premia of a single option in the pool's token
+    pub premia: f64,
+}
+
+/// Decodes result of "get_all_non_expired_options_with_premia" without the length prefix
+pub fn decode_options_with_premia(
+    data: &[String],
+    network: &Network,
+) -> Result<Vec<OptionWithPremia>, String> {
+    let (size, strike_width) = match is_cairo_1(network) {
+        true => (OPTION_WITH_PREMIA_LENGTH_CAIRO_1, 2),
+        false => (OPTION_WITH_PREMIA_LENGTH_CAIRO_0, 1),
+    };
+
+    chunks(data, size, "options with premia")?
+        .into_iter()
+        .map(|v| {
+            let rest = &v[2 + strike_width..];
+            Ok(OptionWithPremia {
+                option_side: parse_number(&v[0], "option_side")?,
+                maturity: parse_number(&v[1], "maturity")?,
+                strike_price: v[2].to_owned(),
+                quote_token_address: rest[0].to_owned(),
+                base_token_address: rest[1].to_owned(),
+                option_type: parse_number(&rest[2], "option_type")?,
+                premia: parse_fixed(&rest[3..], network, "premia")?,
+            })
+        })
+        .collect()
+}
+
+/// u256 hex amount to float with given decimals
+pub(crate) fn token_amount(hex: &str, decimals: u8) -> Option<f64> {
+    hex.strip_prefix("0x")?;
+    Some(felt_to_f64(hex)? / 10f64.powi(decimals as i32))
+}
+
+/// strike price is stored as in the AMM, Cairo 1 only stores the magnitude
+pub(crate) fn strike_to_f64(strike: &str, network: &Network) -> Option<f64> {
+    let felts = match is_cairo_1(network) {
+        true => vec![strike.to_owned(), "0x0".to_owned()],
+        false => vec![strike.to_owned()],
+    };
+    parse_fixed(&felts, network, "strike_price").ok()
+}
+
+struct OptionState<'a> {
+    option: &'a IOption,
+    pool: &'a Pool,
+    size_opened: f64,
+    size_closed: f64,
+    // capital paid for the part of the position which is still open
+    open_cost: f64,
+    realized_pnl: f64,
+}
+
+impl<'a> OptionState<'a> {
+    fn remaining_size(&self) -> f64 {
+        let remaining = self.size_opened - self.size_closed;
+        match remaining > DUST {
+            true => remaining,
+            false => 0.0,
+        }
+    }
+
+    fn open(&mut self, size: f64, capital: f64) {
+        self.size_opened += size;
+        self.open_cost += capital;
+    }
+
+    /// closes with average cost of the open position
+    fn close(&mut self, size: f64, capital: f64) {
+        let remaining = self.remaining_size();
+        let cost = match remaining > 0.0 {
+            true => self.open_cost * (size / remaining).min(1.0),
+            false => 0.0,
+        };
+        self.open_cost -= cost;
+        self.size_closed += size;
+        self.realized_pnl += capital - cost;
+    }
+}
+
+/// Realized PnL is computed from TradeOpen, TradeClose and TradeSettle events of the user,
+/// remaining open positions are marked at current premia of the same option.
+/// All values are in the pool's token - base token for calls, quote token for puts.
+pub fn calculate_pnl(
+    address: &str,
+    trade_history: &[TradeHistory],
+    all_non_expired: &[String],
+    network: &Network,
+) -> Result<UserPnl, String> {
+    let pools = get_all_pools(network);
+    let options_with_premia = decode_options_with_premia(all_non_expired, network)?;
+
+    // keep order of the first trade
+    let mut order: Vec<String> = vec![];
+    let mut states: HashMap<String, OptionState> = HashMap::new();
+
+    for trade in trade_history {
+        if !same_felt(&trade.caller, address) {
+            continue;
+        }
+        let option = match &trade.option {
+            Some(option) => option,
+            None => continue,
+        };
+        let pool = match pools
+            .iter()
+            .find(|pool| same_felt(&pool.address, &option.lp_address))
+        {
+            Some(pool) => pool,
+            None => {
+                warn!(pool = %option.lp_address, "PnL: unknown pool");
+                continue;
+            }
+        };
+        let currency_decimals = match pool.type_ {
+            Type::Call => pool.base.decimals,
+            Type::Put => pool.quote.decimals,
+        };
+        // option tokens have decimals of the base token
+        let (size, capital) = match (
+            token_amount(&trade.tokens_minted, pool.base.decimals),
+            token_amount(&trade.capital_transfered, currency_decimals),
+        ) {
+            (Some(size), Some(capital)) => (size, capital),
+            _ => {
+                warn!(transaction = %trade.transaction_hash, "PnL: failed parsing trade");
+                continue;
+            }
+        };
+
+        let state = states
+            .entry(option.option_address.to_owned())
+            .or_insert_with(|| {
+                order.push(option.option_address.to_owned());
+                OptionState {
+                    option,
+                    pool,
+                    size_opened: 0.0,
+                    size_closed: 0.0,
+                    open_cost: 0.0,
+                    realized_pnl: 0.0,
+                }
+            });
+
+        match trade.action.as_str() {
+            "TradeOpen" => state.open(size, capital),
+            "TradeClose" | "TradeSettle" => state.close(size, capital),
+            _ => (),
+        }
+    }
+
+    let options: Vec<OptionPnl> = order
+        .iter()
+        .filter_map(|address| states.get(address))
+        .map(|state| {
+            let option = state.option;
+            let strike_price = strike_to_f64(&option.strike_price, network).unwrap_or(0.0);
+            let remaining_size = state.remaining_size();
+
+            let current_premia = options_with_premia
+                .iter()
+                .find(|o| {
+                    o.option_side == option.option_side
+                        && o.option_type == option.option_type
+                        && o.maturity == option.maturity
+                        && same_felt(&o.strike_price, &option.strike_price)
+                        && same_felt(&o.base_token_address, &option.base_token_address)
+                        && same_felt(&o.quote_token_address, &option.quote_token_address)
+                })
+                .map(|o| o.premia);
+
+            let unrealized_pnl = match (remaining_size > 0.0, current_premia) {
+                (true, Some(premia)) => {
+                    let value_per_option = match option.option_side {
+                        // long is worth the premia
+                        0 => premia,
+                        // short gets back locked capital minus premia
+                        _ => match state.pool.type_ {
+                            Type::Call => 1.0 - premia,
+                            Type::Put => strike_price - premia,
+                        },
+                    };
+                    Some(value_per_option * remaining_size - state.open_cost)
+                }
+                _ => None,
+            };
+
+            let currency = match state.pool.type_ {
+                Type::Call => &state.pool.base.symbol,
+                Type::Put => &state.pool.quote.symbol,
+            };
+
+            OptionPnl {
+                option_address: option.option_address.to_owned(),
+                pool_id: Some(state.pool.id.to_string()),
+                option_side: option.option_side,
+                option_type: option.option_type,
+                maturity: option.maturity,
+                strike_price,
+                currency: currency.to_string(),
+                size_opened: state.size_opened,
+                size_closed: state.size_closed,
+                remaining_size,
+                realized_pnl: state.realized_pnl,
+                unrealized_pnl,
+                current_premia,
+            }
+        })
+        .collect();
+
+    let mut totals: Vec<PnlTotal> = vec![];
+    for option in &options {
+        let total = match totals.iter().position(|t| t.currency == option.currency) {
+            Some(i) => &mut totals[i],
+            None => {
+                totals.push(PnlTotal {
+                    currency: option.currency.to_owned(),
+                    realized_pnl: 0.0,
+                    unrealized_pnl: 0.0,
+                });
+                totals.last_mut().unwrap()
+            }
+        };
+        total.realized_pnl += option.realized_pnl;
+        total.unrealized_pnl += option.unrealized_pnl.unwrap_or(0.0);
+    }
+
+    Ok(UserPnl {
+        address: address.to_owned(),
+        options,
+        totals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carmine_api_core::test_utils::TradeHistoryBuilder;
+
+    const CALL_POOL: &str = "0x7aba50fdb4e024c1ba63e2c60565d0fd32566ff4b18aa5818fc80c30e749024";
+    const ETH: &str = "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7";
+    const USDC: &str = "0x53c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8";
+    // 1500 in Math64x61
+    const STRIKE: &str = "0xbb8000000000000000";
+
+    fn trade(action: &str, size: &str, capital: &str) -> TradeHistory {
+        TradeHistoryBuilder::new(1, "0x1")
+            .action(action)
+            .caller("0xca11e7")
+            .capital_transfered(capital)
+            .tokens_minted(size)
+            .option(IOption {
+                option_side: 0,
+                maturity: 1700000000,
+                strike_price: STRIKE.to_string(),
+                quote_token_address: USDC.to_string(),
+                base_token_address: ETH.to_string(),
+                option_type: 0,
+                option_address: "0x0971".to_string(),
+                lp_address: CALL_POOL.to_string(),
+            })
+            .build()
+    }
+
+    #[test]
+    fn realized_and_unrealized() {
+        // open 1 ETH option for 0.1 ETH, close half of it for 0.08 ETH
+        let history = vec![
+            trade("TradeOpen", "0xde0b6b3a7640000", "0x16345785d8a0000"),
+            trade("TradeClose", "0x6f05b59d3b20000", "0x11c37937e080000"),
+        ];
+        // long call priced at 0.125 ETH in Math64x61
+        let non_expired: Vec<String> = vec![
+            "0x0",
+            "0x6553f100",
+            STRIKE,
+            USDC,
+            ETH,
+            "0x0",
+            "0x400000000000000",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        let pnl = calculate_pnl("0xca11e7", &history, &non_expired, &Network::Mainnet).unwrap();
+        let option = &pnl.options[0];
+        assert_eq!(option.strike_price, 1500.0);
+        assert!((option.remaining_size - 0.5).abs() < 1e-9);
+        assert!((option.realized_pnl - 0.03).abs() < 1e-9);
+        assert!((option.unrealized_pnl.unwrap() - 0.0125).abs() < 1e-9);
+        assert_eq!(pnl.totals[0].currency, "ETH");
+        assert!((pnl.totals[0].realized_pnl - 0.03).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ignores_other_users() {
+        let history = vec![trade("TradeOpen", "0x1", "0x1")];
+        let pnl = calculate_pnl("0xabc", &history, &[], &Network::Mainnet).unwrap();
+        assert!(pnl.options.is_empty());
+        assert!(pnl.totals.is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+use carmine_api_core::{
+    network::Network,
+    pool::{
+        get_all_pool_addresses, get_all_pools, load_pool_registry, pool_registry, Pool, Token, Type,
+    },
+    telegram_bot,
+    utils::same_felt,
+};
+use carmine_api_db::{create_pools, get_pools};
+use tokio::try_join;
+use tracing::{info, warn};
+
+use crate::carmine::Carmine;
+
+/// Registered addresses missing on the chain and chain addresses missing in the registry
+fn diff_addresses(registered: &[String], on_chain: &[String]) -> (Vec<String>, Vec<String>) {
+    let missing = |from: &[String], other: &[String]| -> Vec<String> {
+        from.iter()
+            .filter(|a| !other.iter().any(|b| same_felt(a, b)))
+            .cloned()
+            .collect()
+    };
+    (missing(registered, on_chain), missing(on_chain, registered))
+}
+
+/// Pool id as used in the API paths, eg. "eth-usdc-call"
+fn pool_id(base: &Token, quote: &Token, type_: &Type) -> String {
+    format!("{}-{}-{}", base.symbol, quote.symbol, type_).to_lowercase()
+}
+
+/// Appends end of the address to the id if another pool of the network
+/// already uses it (eg. pool of a redeployed AMM), routes are keyed by the id
+fn unique_pool_id(id: String, address: &str, pools: &[Pool]) -> String {
+    let taken = pools
+        .iter()
+        .any(|p| p.id == id && !same_felt(&p.address, address));
+    if !taken {
+        return id;
+    }
+    let hex = address.trim_start_matches("0x").to_lowercase();
+    format!("{}-{}", id, &hex[hex.len().saturating_sub(6)..])
+}
+
+/// Adds pools stored in the DB (eg. discovered by the fetcher) to the registry
+pub fn load_pools_from_db(network: &Network) {
+    match get_pools(network) {
+        Ok(rows) => {
+            for pool in rows.iter().filter_map(|row| Pool::from_db(row, *network)) {
+                if pool_registry().add(pool.clone()) {
+                    info!(network = %network, pool = %pool.id, "Registered pool from DB");
+                }
+            }
+        }
+        Err(e) => warn!(network = %network, error = %e, "Failed getting pools from DB"),
+    }
+}
+
+/// Loads the pools config, merges pools stored in the DB into the registry and stores
+/// the registry pools in the DB, then checks the registry against "GetAllLPTokenAddresses"
+/// of the AMM. Fails only if the pools config is invalid.
+pub async fn init_pool_registry(network: &Network) -> Result<(), String> {
+    load_pool_registry()?;
+    load_pools_from_db(network);
+
+    let rows = get_all_pools(network).iter().map(Pool::to_db).collect();
+    if let Err(e) = create_pools(rows, network) {
+        warn!(network = %network, error = %e, "Failed storing pools");
+    }
+
+    check_pools_on_chain(network).await;
+    Ok(())
+}
+
+/// Reports pools deployed on the chain that are not registered and the other way around
+pub async fn check_pools_on_chain(network: &Network) {
+    let on_chain = match Carmine::new(*network).get_all_lptoken_addresses().await {
+        Ok(v) => v,
+        Err(_) => {
+            warn!(network = %network, "Failed getting LP token addresses");
+            return;
+        }
+    };
+    let (not_on_chain, not_registered) =
+        diff_addresses(&get_all_pool_addresses(network), &on_chain);
+
+    if !not_registered.is_empty() {
+        let msg = format!(
+            "{} pools deployed but not registered: {}",
+            network,
+            not_registered.join(", ")
+        );
+        warn!(network = %network, "{}", msg);
+        telegram_bot::send_message(msg.as_str()).await;
+    }
+    if !not_on_chain.is_empty() {
+        let msg = format!(
+            "{} pools registered but not deployed: {}",
+            network,
+            not_on_chain.join(", ")
+        );
+        warn!(network = %network, "{}", msg);
+        telegram_bot::send_message(msg.as_str()).await;
+    }
+}
+
+/// Registers pools deployed on the chain that are not stored in the DB yet,
+/// state and options of registered pools are then fetched with the others
+pub async fn discover_pools(network: &Network) {
+    let carmine = Carmine::new(*network);
+    let on_chain = match carmine.get_all_lptoken_addresses().await {
+        Ok(v) => v,
+        Err(_) => {
+            warn!(network = %network, "Failed getting LP token addresses");
+            return;
+        }
+    };
+    let stored: Vec<String> = match get_pools(network) {
+        Ok(rows) => rows.into_iter().map(|row| row.lp_address).collect(),
+        Err(e) => {
+            warn!(network = %network, error = %e, "Failed getting pools from DB");
+            return;
+        }
+    };
+    let (_, new_pools) = diff_addresses(&stored, &on_chain);
+
+    for lp_address in new_pools {
+        let (base_address, quote_address, type_) =
+            match carmine.get_pool_definition(&lp_address).await {
+                Ok(v) => v,
+                Err(_) => {
+                    warn!(network = %network, pool = %lp_address, "Failed getting pool definition");
+                    continue;
+                }
+            };
+        let (base, quote) = match try_join!(
+            carmine.get_token(&base_address),
+            carmine.get_token(&quote_address)
+        ) {
+            Ok(v) => v,
+            Err(_) => {
+                warn!(network = %network, pool = %lp_address, "Failed getting pool tokens");
+                continue;
+            }
+        };
+        let id = unique_pool_id(
+            pool_id(&base, &quote, &type_),
+            &lp_address,
+            &get_all_pools(network),
+        );
+        let pool = Pool {
+            id,
+            address: lp_address,
+            network: *network,
+            type_,
+            base,
+            quote,
+        };
+
+        if let Err(e) = create_pools(vec![pool.to_db()], network) {
+            warn!(network = %network, pool = %pool.id, error = %e, "Failed storing pool");
+            continue;
+        }
+        let msg = format!(
+            "New {} pool {} registered: {}",
+            network, pool.id, pool.address
+        );
+        info!(network = %network, pool = %pool.id, "{}", msg);
+        pool_registry().add(pool);
+        telegram_bot::send_message(msg.as_str()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carmine_api_core::utils::felt_to_short_string;
+
+    #[test]
+    fn diffs_addresses() {
+        let registered = vec!["0x01".to_owned(), "0x2".to_owned()];
+        let on_chain = vec!["0x1".to_owned(), "0x3".to_owned()];
+
+        assert_eq!(
+            diff_addresses(&registered, &on_chain),
+            (vec!["0x2".to_owned()], vec!["0x3".to_owned()])
+        );
+    }
+
+    #[test]
+    fn builds_pool_id_from_token_metadata() {
+        let token = |felt: &str| Token {
+            address: "0x1".to_owned(),
+            symbol: felt_to_short_string(felt).unwrap(),
+            decimals: 18,
+        };
+        // "wBTC" and "USDC" short strings
+        let id = pool_id(&token("0x77425443"), &token("0x55534443"), &Type::Put);
+        assert_eq!(id, "wbtc-usdc-put");
+        assert_eq!(felt_to_short_string("0x0"), None);
+
+        let pools = vec![Pool {
+            id: id.clone(),
+            address: "0x0123".to_owned(),
+            network: Network::Mainnet,
+            type_: Type::Put,
+            base: token("0x77425443"),
+            quote: token("0x55534443"),
+        }];
+        assert_eq!(unique_pool_id(id.clone(), "0x123", &pools), "wbtc-usdc-put");
+        assert_eq!(
+            unique_pool_id(id, "0x0ABCDEF123", &pools),
+            "wbtc-usdc-put-def123"
+        );
+    }
+}
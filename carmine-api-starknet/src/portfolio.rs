@@ -0,0 +1,209 @@
+use carmine_api_core::{
+    amm_events::Uint256,
+    network::Network,
+    pool::Pool,
+    types::{UserOptionPosition, UserPoolInfo},
+    utils::{cubit_fixed_to_f64, felt_to_u128, math_64x61_to_f64, same_felt},
+};
+
+// Cairo 0: option (6) + position_size u256 (2) + value_of_position Math64x61 (1)
+const OPTION_WITH_POSITION_LENGTH_CAIRO_0: usize = 9;
+// Cairo 1: option with Fixed strike (7) + position_size u256 (2) + value_of_position Fixed (2)
+const OPTION_WITH_POSITION_LENGTH_CAIRO_1: usize = 11;
+// Cairo 0: value_of_user_stake u256 (2) + size_of_users_tokens u256 (2)
+// + pool (3) + lptoken_address (1) + staked_capital u256 (2) + unlocked_capital u256 (2)
+// + value_of_pool_position Math64x61 (1)
+const USER_POOL_INFO_LENGTH_CAIRO_0: usize = 13;
+// Cairo 1: same as Cairo 0, but value_of_pool_position is Fixed (2)
+const USER_POOL_INFO_LENGTH_CAIRO_1: usize = 14;
+
+// TODO: Testnet is already C1 - change when mainnet is also C1
+pub(crate) fn is_cairo_1(network: &Network) -> bool {
+    matches!(network, Network::Testnet)
+}
+
+pub(crate) fn parse_number<T: TryFrom<u128>>(felt: &str, field: &str) -> Result<T, String> {
+    match felt_to_u128(felt).and_then(|v| T::try_from(v).ok()) {
+        Some(v) => Ok(v),
+        None => Err(format!("Invalid {}: {}", field, felt)),
+    }
+}
+
+fn parse_u256(low: &str, high: &str, field: &str) -> Result<String, String> {
+    match Uint256::from_felts(low, high) {
+        Some(v) => Ok(v.to_hex()),
+        None => Err(format!("Invalid {}: {}, {}", field, low, high)),
+    }
+}
+
+pub(crate) fn parse_fixed(felts: &[String], network: &Network, field: &str) -> Result<f64, String> {
+    let parsed = match is_cairo_1(network) {
+        true => cubit_fixed_to_f64(&felts[0], &felts[1]),
+        false => math_64x61_to_f64(&felts[0]),
+    };
+    match parsed {
+        Some(v) => Ok(v),
+        None => Err(format!("Invalid {}: {:?}", field, felts)),
+    }
+}
+
+pub(crate) fn chunks<'a>(
+    data: &'a [String],
+    size: usize,
+    name: &str,
+) -> Result<Vec<&'a [String]>, String> {
+    if !data.len().is_multiple_of(size) {
+        return Err(format!(
+            "Got {} fields for {}, expected multiple of {}",
+            data.len(),
+            name,
+            size
+        ));
+    }
+    Ok(data.chunks(size).collect())
+}
+
+/// Decodes result of "get_option_with_position_of_user" without the length prefix
+pub fn decode_options_with_position(
+    data: &[String],
+    network: &Network,
+) -> Result<Vec<UserOptionPosition>, String> {
+    let (size, strike_width) = match is_cairo_1(network) {
+        true => (OPTION_WITH_POSITION_LENGTH_CAIRO_1, 2),
+        false => (OPTION_WITH_POSITION_LENGTH_CAIRO_0, 1),
+    };
+
+    chunks(data, size, "options with position")?
+        .into_iter()
+        .map(|v| {
+            // strike price is the only field of the option with different width
+            let rest = &v[2 + strike_width..];
+            Ok(UserOptionPosition {
+                option_side: parse_number(&v[0], "option_side")?,
+                maturity: parse_number(&v[1], "maturity")?,
+                strike_price: parse_fixed(&v[2..], network, "strike_price")?,
+                quote_token_address: rest[0].to_owned(),
+                base_token_address: rest[1].to_owned(),
+                option_type: parse_number(&rest[2], "option_type")?,
+                position_size: parse_u256(&rest[3], &rest[4], "position_size")?,
+                value_of_position: parse_fixed(&rest[5..], network, "value_of_position")?,
+            })
+        })
+        .collect()
+}
+
+/// Decodes result of "get_user_pool_infos" without the length prefix
+pub fn decode_user_pool_infos(
+    data: &[String],
+    network: &Network,
+    pools: &[Pool],
+) -> Result<Vec<UserPoolInfo>, String> {
+    let size = match is_cairo_1(network) {
+        true => USER_POOL_INFO_LENGTH_CAIRO_1,
+        false => USER_POOL_INFO_LENGTH_CAIRO_0,
+    };
+
+    chunks(data, size, "user pool infos")?
+        .into_iter()
+        .map(|v| {
+            let lp_address = v[7].to_owned();
+            let pool_id = pools
+                .iter()
+                .find(|pool| same_felt(&pool.address, &lp_address))
+                .map(|pool| pool.id.to_string());
+            Ok(UserPoolInfo {
+                value_of_user_stake: parse_u256(&v[0], &v[1], "value_of_user_stake")?,
+                size_of_users_tokens: parse_u256(&v[2], &v[3], "size_of_users_tokens")?,
+                quote_token_address: v[4].to_owned(),
+                base_token_address: v[5].to_owned(),
+                option_type: parse_number(&v[6], "option_type")?,
+                lp_address,
+                pool_id,
+                staked_capital: parse_u256(&v[8], &v[9], "staked_capital")?,
+                unlocked_capital: parse_u256(&v[10], &v[11], "unlocked_capital")?,
+                value_of_pool_position: parse_fixed(&v[12..], network, "value_of_pool_position")?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carmine_api_core::pool::get_all_pools;
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn options_with_position_cairo_0() {
+        // strike 1500 and value 0.5 in Math64x61
+        let data = strings(&[
+            "0x0",
+            "0x64ec5f7f",
+            "0xbb8000000000000000",
+            "0x53c",
+            "0x49d",
+            "0x0",
+            "0xde0b6b3a7640000",
+            "0x0",
+            "0x1000000000000000",
+        ]);
+        let options = decode_options_with_position(&data, &Network::Mainnet).unwrap();
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].maturity, 0x64ec5f7f);
+        assert_eq!(options[0].strike_price, 1500.0);
+        assert_eq!(options[0].position_size, "0xde0b6b3a7640000");
+        assert_eq!(options[0].value_of_position, 0.5);
+    }
+
+    #[test]
+    fn options_with_position_cairo_1() {
+        // strike 1500 and value 0.5 in Cubit Fixed
+        let data = strings(&[
+            "0x1",
+            "0x64ec5f7f",
+            "0x5dc0000000000000000",
+            "0x0",
+            "0x53c",
+            "0x49d",
+            "0x1",
+            "0x1",
+            "0x0",
+            "0x8000000000000000",
+            "0x0",
+        ]);
+        let options = decode_options_with_position(&data, &Network::Testnet).unwrap();
+        assert_eq!(options[0].option_side, 1);
+        assert_eq!(options[0].option_type, 1);
+        assert_eq!(options[0].strike_price, 1500.0);
+        assert_eq!(options[0].value_of_position, 0.5);
+        assert!(decode_options_with_position(&data[1..], &Network::Testnet).is_err());
+    }
+
+    #[test]
+    fn user_pool_infos_cairo_0() {
+        let pools = get_all_pools(&Network::Mainnet);
+        let data = strings(&[
+            "0x10",
+            "0x0",
+            "0x20",
+            "0x0",
+            "0x53c",
+            "0x49d",
+            "0x0",
+            "0x07aba50fdb4e024c1ba63e2c60565d0fd32566ff4b18aa5818fc80c30e749024",
+            "0x30",
+            "0x0",
+            "0x40",
+            "0x0",
+            "0x0",
+        ]);
+        let infos = decode_user_pool_infos(&data, &Network::Mainnet, &pools).unwrap();
+        assert_eq!(infos[0].pool_id, Some("eth-usdc-call".to_string()));
+        assert_eq!(infos[0].size_of_users_tokens, "0x20");
+        assert_eq!(infos[0].unlocked_capital, "0x40");
+        assert_eq!(infos[0].value_of_pool_position, 0.0);
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use carmine_api_core::{
+    network::{Network, REORG_WINDOW},
+    telegram_bot,
+    utils::same_felt,
+};
+use carmine_api_db::{delete_from_block, get_blocks_from, get_event_block_hashes_from};
+use carmine_api_rpc_gateway::{get_block_header, latest_block_number, BlockTag};
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::{error, instrument, warn};
+
+// rollback and storing of fetched data never run at the same time
+static STORE_LOCK: Mutex<()> = Mutex::const_new(());
+
+static MAINNET_ROLLBACKS: AtomicU64 = AtomicU64::new(0);
+static TESTNET_ROLLBACKS: AtomicU64 = AtomicU64::new(0);
+
+fn rollbacks(network: &Network) -> &'static AtomicU64 {
+    match network {
+        Network::Mainnet => &MAINNET_ROLLBACKS,
+        Network::Testnet => &TESTNET_ROLLBACKS,
+    }
+}
+
+/// Taken before fetching data which is stored later, see `StoreGuard::rolled_back`
+pub fn rollback_count(network: &Network) -> u64 {
+    rollbacks(network).load(Ordering::SeqCst)
+}
+
+/// Held while fetched data is being stored, rollback waits for it
+pub struct StoreGuard {
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl StoreGuard {
+    pub async fn lock() -> Self {
+        StoreGuard {
+            _guard: STORE_LOCK.lock().await,
+        }
+    }
+
+    /// Data fetched before the chain was rolled back may come
+    /// from orphaned blocks and must not be stored
+    pub fn rolled_back(&self, network: &Network, since: u64) -> bool {
+        rollback_count(network) != since
+    }
+}
+
+/// Lowest block whose stored hash differs from the chain
+fn find_fork_block(stored: &[(i64, String)], chain: &HashMap<i64, String>) -> Option<i64> {
+    stored
+        .iter()
+        .filter(|(block_number, hash)| match chain.get(block_number) {
+            Some(chain_hash) => !same_felt(hash, chain_hash),
+            None => false,
+        })
+        .map(|(block_number, _)| *block_number)
+        .min()
+}
+
+/// Compares hashes of recently stored blocks and events with the chain
+/// and deletes everything from the first orphaned block, regular updates
+/// then fetch the deleted state and events again.
+/// Returns the block rolled back to, if there was a reorg.
+#[instrument(skip_all, fields(network = %network))]
+pub async fn handle_reorg(network: &Network) -> Result<Option<i64>, ()> {
+    let latest = match latest_block_number(network).await {
+        Ok(n) => n,
+        Err(e) => {
+            warn!(error = ?e, "Failed getting latest block for reorg check");
+            return Err(());
+        }
+    };
+    let from_block = latest - REORG_WINDOW;
+
+    let blocks = get_blocks_from(from_block, network)
+        .map_err(|e| warn!(error = %e, "Reorg check failed"))?;
+    let event_blocks = get_event_block_hashes_from(from_block, network)
+        .map_err(|e| warn!(error = %e, "Reorg check failed"))?;
+
+    let mut stored: Vec<(i64, String)> = blocks
+        .into_iter()
+        .filter_map(|block| Some((block.block_number, block.block_hash?)))
+        .collect();
+    stored.extend(event_blocks);
+
+    let mut chain: HashMap<i64, String> = HashMap::new();
+    for (block_number, _) in stored.iter() {
+        if chain.contains_key(block_number) {
+            continue;
+        }
+        match get_block_header(BlockTag::Number(*block_number), network).await {
+            Ok(block) => {
+                if let Some(hash) = block.block_hash {
+                    chain.insert(*block_number, hash);
+                }
+            }
+            Err(e) => {
+                warn!(block = block_number, error = ?e, "Failed getting block");
+                return Err(());
+            }
+        }
+    }
+
+    let fork_block = match find_fork_block(&stored, &chain) {
+        Some(n) => n,
+        None => return Ok(None),
+    };
+
+    let msg = format!(
+        "Reorg detected on {}, rolling back from block #{}",
+        network, fork_block
+    );
+    warn!(block = fork_block, "{}", msg);
+    telegram_bot::send_message(msg.as_str()).await;
+
+    let _guard = StoreGuard::lock().await;
+    match delete_from_block(fork_block, network) {
+        Ok(_) => {
+            rollbacks(network).fetch_add(1, Ordering::SeqCst);
+            Ok(Some(fork_block))
+        }
+        Err(e) => {
+            error!(block = fork_block, error = %e, "Failed rolling back");
+            Err(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_lowest_orphaned_block() {
+        let stored = vec![
+            (10, "0x0a".to_owned()),
+            (12, "0x0c".to_owned()),
+            (12, "0x0bad".to_owned()),
+            (15, "0x0bad".to_owned()),
+            (20, "0x14".to_owned()),
+        ];
+        let chain: HashMap<i64, String> = [(10, "0xa"), (12, "0xc"), (15, "0xf"), (20, "0x15")]
+            .into_iter()
+            .map(|(n, h)| (n, h.to_owned()))
+            .collect();
+
+        assert_eq!(find_fork_block(&stored, &chain), Some(12));
+        assert_eq!(find_fork_block(&stored[..2], &chain), None);
+    }
+}
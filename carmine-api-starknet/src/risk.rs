@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use carmine_api_core::{
+    network::Network,
+    pool::{get_all_pools, Pool, Type},
+    types::{
+        OptionWithVolatility, OraclePriceConcise, PoolRisk, PoolStateWithTimestamp,
+        UtilizationPoint,
+    },
+    utils::same_felt,
+};
+
+use crate::{
+    greeks::{pool_option_model, side_sign, spot_price, volatility_to_f64},
+    pnl::token_amount,
+};
+
+// pool state is stored every few blocks, daily points are enough for a chart
+pub const DEFAULT_UTILIZATION_INTERVAL: i64 = 86400;
+
+/// Range of `utilization_history` (timestamps, inclusive),
+/// the last state of every `interval` seconds is kept
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryRange {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub interval: i64,
+}
+
+impl Default for HistoryRange {
+    fn default() -> Self {
+        HistoryRange {
+            from: None,
+            to: None,
+            interval: DEFAULT_UTILIZATION_INTERVAL,
+        }
+    }
+}
+
+fn utilization_point(state: &PoolStateWithTimestamp, pool: &Pool) -> Option<UtilizationPoint> {
+    let decimals = match pool.type_ {
+        Type::Call => pool.base.decimals,
+        Type::Put => pool.quote.decimals,
+    };
+    let locked_capital = token_amount(&state.locked_cap, decimals)?;
+    let unlocked_capital = token_amount(&state.unlocked_cap, decimals)?;
+    let total = locked_capital + unlocked_capital;
+    let utilization = match total > 0.0 {
+        true => locked_capital / total,
+        false => 0.0,
+    };
+    Some(UtilizationPoint {
+        block_number: state.block_number,
+        timestamp: state.timestamp,
+        locked_capital,
+        unlocked_capital,
+        utilization,
+    })
+}
+
+/// Utilization of the last state in each interval of the range, ordered by block
+fn utilization_history(
+    states: &[&PoolStateWithTimestamp],
+    pool: &Pool,
+    range: &HistoryRange,
+) -> Vec<UtilizationPoint> {
+    let from = range.from.unwrap_or(i64::MIN);
+    let to = range.to.unwrap_or(i64::MAX);
+
+    let mut history: Vec<UtilizationPoint> = vec![];
+    let mut last_bucket = None;
+    for state in states
+        .iter()
+        .filter(|s| s.timestamp >= from && s.timestamp <= to)
+    {
+        let point = match utilization_point(state, pool) {
+            Some(point) => point,
+            None => continue,
+        };
+        let bucket = state.timestamp.div_euclid(range.interval);
+        if last_bucket == Some(bucket) {
+            history.pop();
+        }
+        history.push(point);
+        last_bucket = Some(bucket);
+    }
+    history
+}
+
+/// Net Greeks and utilization of the pool. Option positions of the pool are
+/// the options held by traders, the pool has the opposite exposure.
+pub fn pool_risk(
+    pool: &Pool,
+    option_volatility: &[OptionWithVolatility],
+    oracle_prices: &HashMap<String, Vec<OraclePriceConcise>>,
+    state: &[PoolStateWithTimestamp],
+    network: &Network,
+    now: i64,
+    range: &HistoryRange,
+) -> PoolRisk {
+    let spot = spot_price(pool, oracle_prices);
+
+    let mut net = (0.0, 0.0, 0.0, 0.0);
+    let mut options_priced = 0;
+    let mut options_not_priced = 0;
+
+    let pool_options = option_volatility
+        .iter()
+        .filter(|o| same_felt(&o.lp_address, &pool.address) && o.maturity > now);
+
+    for option in pool_options {
+        // history is ordered from the latest block
+        let latest = match option.volatilities.first() {
+            Some(v) => v,
+            None => continue,
+        };
+        let position = match latest
+            .option_position
+            .as_ref()
+            .and_then(|p| token_amount(p, pool.base.decimals))
+        {
+            Some(p) if p > 0.0 => p,
+            _ => continue,
+        };
+        let model = match (latest.volatility.as_ref(), spot) {
+            (Some(volatility), Some(spot)) => {
+                volatility_to_f64(volatility, network).and_then(|volatility| {
+                    pool_option_model(
+                        pool,
+                        &option.strike_price,
+                        option.maturity,
+                        volatility,
+                        spot,
+                        network,
+                        now,
+                    )
+                })
+            }
+            _ => None,
+        };
+        let (delta, gamma, theta, vega) = match model {
+            Some(model) => model.greeks(),
+            None => {
+                options_not_priced += 1;
+                continue;
+            }
+        };
+
+        let size = -side_sign(option.option_side) * position;
+        net.0 += size * delta;
+        net.1 += size * gamma;
+        net.2 += size * theta;
+        net.3 += size * vega;
+        options_priced += 1;
+    }
+
+    let mut states: Vec<&PoolStateWithTimestamp> = state.iter().collect();
+    states.sort_by_key(|s| s.block_number);
+
+    PoolRisk {
+        pool_id: pool.id.to_owned(),
+        net_delta: net.0,
+        net_gamma: net.1,
+        net_theta: net.2,
+        net_vega: net.3,
+        options_priced,
+        options_not_priced,
+        utilization: states.last().and_then(|s| utilization_point(s, pool)),
+        utilization_history: utilization_history(&states, pool, range),
+    }
+}
+
+/// Risk metrics of all pools of the network, `state` is keyed by pool id
+pub fn pools_risk(
+    option_volatility: &[OptionWithVolatility],
+    oracle_prices: &HashMap<String, Vec<OraclePriceConcise>>,
+    state: &HashMap<String, Vec<PoolStateWithTimestamp>>,
+    network: &Network,
+    now: i64,
+    range: &HistoryRange,
+) -> Vec<PoolRisk> {
+    get_all_pools(network)
+        .iter()
+        .map(|pool| {
+            let pool_state = state.get(&pool.id).map(Vec::as_slice).unwrap_or(&[]);
+            pool_risk(
+                pool,
+                option_volatility,
+                oracle_prices,
+                pool_state,
+                network,
+                now,
+                range,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carmine_api_core::{test_utils::PoolStateBuilder, types::Volatility};
+
+    // 1500 and 80 in Math64x61
+    const STRIKE: &str = "0xbb8000000000000000";
+    const VOLATILITY: &str = "0xa0000000000000000";
+
+    fn pool() -> Pool {
+        get_all_pools(&Network::Mainnet)
+            .into_iter()
+            .find(|p| p.id == "eth-usdc-call")
+            .unwrap()
+    }
+
+    fn option(side: i16, position: &str) -> OptionWithVolatility {
+        OptionWithVolatility {
+            option_side: side,
+            maturity: 1_000_000 + 30 * 86400,
+            strike_price: STRIKE.to_string(),
+            quote_token_address: pool().quote.address,
+            base_token_address: pool().base.address,
+            option_type: 0,
+            option_address: format!("0x{}", side),
+            lp_address: pool().address,
+            volatilities: vec![Volatility {
+                block_number: 2,
+                timestamp: 1_000_000,
+                volatility: Some(VOLATILITY.to_string()),
+                option_position: Some(position.to_string()),
+            }],
+        }
+    }
+
+    fn state(locked: &str, unlocked: &str, block_number: i64) -> PoolStateWithTimestamp {
+        PoolStateBuilder::new(block_number)
+            .timestamp(block_number * 10)
+            .locked_cap(locked)
+            .unlocked_cap(unlocked)
+            .lp_address(&pool().address)
+            .build()
+    }
+
+    #[test]
+    fn nets_positions_and_utilization() {
+        let prices = HashMap::from([(
+            "eth-usdc".to_string(),
+            vec![OraclePriceConcise {
+                price: 150000000000,
+                decimals: 8,
+                last_updated_timestamp: 1_000_000,
+                block_number: 2,
+            }],
+        )]);
+        let state = vec![
+            // 1 ETH locked, 3 ETH unlocked
+            state("0xde0b6b3a7640000", "0x29a2241af62c0000", 2),
+            state("0x0", "0x29a2241af62c0000", 1),
+        ];
+
+        // traders hold 2 long and 1 short - pool is short 1 call
+        let options = vec![
+            option(0, "0x1bc16d674ec80000"),
+            option(1, "0xde0b6b3a7640000"),
+        ];
+        let risk = pool_risk(
+            &pool(),
+            &options,
+            &prices,
+            &state,
+            &Network::Mainnet,
+            1_000_000,
+            &HistoryRange {
+                interval: 10,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(risk.options_priced, 2);
+        // ATM call delta is slightly above 0.5
+        assert!(risk.net_delta < -0.5 && risk.net_delta > -0.6);
+        assert!(risk.net_vega < 0.0);
+        assert_eq!(risk.utilization_history.len(), 2);
+        assert_eq!(risk.utilization_history[0].utilization, 0.0);
+        assert_eq!(risk.utilization.unwrap().utilization, 0.25);
+
+        let without_price = pool_risk(
+            &pool(),
+            &options,
+            &HashMap::new(),
+            &state,
+            &Network::Mainnet,
+            1_000_000,
+            &HistoryRange::default(),
+        );
+        assert_eq!(without_price.options_not_priced, 2);
+        // both states are in the same day, only the last one is kept
+        assert_eq!(without_price.utilization_history.len(), 1);
+        assert_eq!(without_price.utilization_history[0].utilization, 0.25);
+        assert_eq!(without_price.net_delta, 0.0);
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use carmine_api_core::{
+    amm_events::CAIRO_1_EVENT_PREFIX,
+    network::{protocol_address, Network, Protocol},
+    telegram_bot,
+    types::StarkScanEventSettled,
+    utils::same_felt,
+};
+use carmine_api_rpc_gateway::{
+    get_block_header, get_events, get_transaction_events, latest_block_number, BlockTag,
+    EmittedEvent, EventFilter, ReceiptEvent, RpcError,
+};
+use starknet::core::utils::get_selector_from_name;
+use tracing::{error, warn};
+
+use crate::portfolio::is_cairo_1;
+
+const EVENTS_CHUNK_SIZE: u32 = 100;
+
+// blocks read with one filter, a run reads consecutive ranges until it gets
+// events past its start block, so deep history is caught up over several runs
+const EVENTS_BLOCK_RANGE: i64 = 10_000;
+
+const AMM_EVENT_NAMES: [&str; 6] = [
+    "TradeOpen",
+    "TradeClose",
+    "TradeSettle",
+    "DepositLiquidity",
+    "WithdrawLiquidity",
+    "ExpireOptionTokenForPool",
+];
+
+// RPC only gives the selector, these are the names we know,
+// events with other selectors are skipped like Starkscan events without "key_name"
+const COMMON_EVENT_NAMES: [&str; 11] = [
+    "Transfer",
+    "Approval",
+    "Upgraded",
+    "AdminChanged",
+    "OwnershipTransferred",
+    "Deposit",
+    "Withdraw",
+    "Withdrawal",
+    "Borrow",
+    "Repay",
+    "Liquidation",
+];
+
+// Starkscan returns hashes and addresses zero padded to 64 hex digits
+fn pad_felt(felt: &str) -> String {
+    let hex = felt.trim_start_matches("0x").to_lowercase();
+    format!("0x{:0>64}", hex)
+}
+
+fn same_felts(a: &[String], b: &[String]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_felt(a, b))
+}
+
+/// Padded selector -> event name as Starkscan names it
+fn event_names(network: &Network, protocol: &Protocol) -> HashMap<String, String> {
+    let mut names: Vec<String> = COMMON_EVENT_NAMES.iter().map(|n| n.to_string()).collect();
+
+    if let Protocol::CarmineOptions = protocol {
+        // TODO: C1 - remove when mainnet AMM is Cairo 1
+        let prefix = match is_cairo_1(network) {
+            true => CAIRO_1_EVENT_PREFIX,
+            false => "",
+        };
+        names.extend(AMM_EVENT_NAMES.iter().map(|n| format!("{}{}", prefix, n)));
+    }
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            // Cairo 1 selector is computed from the name without the path
+            let short_name = name.rsplit("::").next().unwrap_or(&name);
+            let selector = get_selector_from_name(short_name).ok()?;
+            Some((pad_felt(&format!("{:#x}", selector)), name))
+        })
+        .collect()
+}
+
+/// Position of the event in the receipt, searched from "cursor"
+/// so that identical events in single transaction get distinct indexes
+fn find_event_index(
+    receipt_events: &[ReceiptEvent],
+    cursor: usize,
+    event: &EmittedEvent,
+) -> Option<usize> {
+    receipt_events
+        .iter()
+        .skip(cursor)
+        .position(|e| {
+            same_felt(&e.from_address, &event.from_address)
+                && same_felts(&e.keys, &event.keys)
+                && same_felts(&e.data, &event.data)
+        })
+        .map(|i| cursor + i)
+}
+
+/// Settled events of the protocol between the blocks (inclusive)
+async fn _fetch_rpc_events(
+    network: &Network,
+    protocol: &Protocol,
+    from_block: i64,
+    to_block: i64,
+) -> Result<Vec<StarkScanEventSettled>, RpcError> {
+    let names = event_names(network, protocol);
+    let mut emitted: Vec<EmittedEvent> = vec![];
+    let mut continuation_token: Option<String> = None;
+
+    loop {
+        let filter = EventFilter {
+            from_block: BlockTag::Number(from_block),
+            to_block: BlockTag::Number(to_block),
+            address: protocol_address(network, protocol).to_owned(),
+            keys: vec![],
+            chunk_size: EVENTS_CHUNK_SIZE,
+            continuation_token,
+        };
+        let chunk = get_events(filter, network).await?;
+        emitted.extend(chunk.events);
+
+        match chunk.continuation_token {
+            Some(token) => continuation_token = Some(token),
+            None => break,
+        }
+    }
+
+    let mut timestamps: HashMap<i64, i64> = HashMap::new();
+    // receipt events of the transaction and position of the last matched event
+    let mut receipts: HashMap<String, (Vec<ReceiptEvent>, usize)> = HashMap::new();
+    let mut settled: Vec<StarkScanEventSettled> = vec![];
+
+    for event in emitted {
+        // pending events are not settled yet
+        let (block_hash, block_number) = match (&event.block_hash, event.block_number) {
+            (Some(hash), Some(number)) => (hash.to_owned(), number),
+            _ => continue,
+        };
+        let key_name = match event.keys.first().and_then(|k| names.get(&pad_felt(k))) {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+
+        let timestamp = match timestamps.get(&block_number) {
+            Some(timestamp) => *timestamp,
+            None => {
+                let block = get_block_header(BlockTag::Number(block_number), network).await?;
+                timestamps.insert(block_number, block.timestamp);
+                block.timestamp
+            }
+        };
+
+        if !receipts.contains_key(&event.transaction_hash) {
+            let receipt_events = get_transaction_events(&event.transaction_hash, network).await?;
+            receipts.insert(event.transaction_hash.to_owned(), (receipt_events, 0));
+        }
+        let (receipt_events, cursor) = match receipts.get_mut(&event.transaction_hash) {
+            Some(v) => v,
+            None => continue,
+        };
+        // failing the batch would fetch the same events again every cycle
+        let event_index = match find_event_index(receipt_events, *cursor, &event) {
+            Some(i) => i,
+            None => {
+                warn!(
+                    network = %network,
+                    protocol = %protocol,
+                    transaction = %event.transaction_hash,
+                    block = block_number,
+                    "Event not found in receipt, skipping it"
+                );
+                continue;
+            }
+        };
+        *cursor = event_index + 1;
+
+        let transaction_hash = pad_felt(&event.transaction_hash);
+        settled.push(StarkScanEventSettled {
+            id: format!("{}_{}", transaction_hash, event_index),
+            block_hash: pad_felt(&block_hash),
+            block_number,
+            transaction_hash,
+            event_index: event_index as i64,
+            from_address: pad_felt(&event.from_address),
+            keys: event.keys,
+            data: event.data,
+            timestamp,
+            key_name,
+        });
+    }
+
+    Ok(settled)
+}
+
+/// Settled events of the protocol from the block on, taken from our RPC nodes
+/// range by range, ranges read before a failed one are kept
+pub async fn fetch_rpc_events(
+    network: &Network,
+    protocol: &Protocol,
+    from_block: u32,
+) -> Vec<StarkScanEventSettled> {
+    let latest = match latest_block_number(network).await {
+        Ok(block) => block,
+        Err(e) => {
+            error!(network = %network, protocol = %protocol, error = ?e, "Failed getting latest block");
+            return vec![];
+        }
+    };
+    let from_block = i64::from(from_block);
+    let mut settled: Vec<StarkScanEventSettled> = vec![];
+    let mut start = from_block;
+
+    while start <= latest {
+        let end = (start + EVENTS_BLOCK_RANGE - 1).min(latest);
+        match _fetch_rpc_events(network, protocol, start, end).await {
+            Ok(events) => settled.extend(events),
+            Err(e) => {
+                // partly fetched range is not stored, that would create hole in the data
+                error!(
+                    network = %network,
+                    protocol = %protocol,
+                    from_block = start,
+                    to_block = end,
+                    error = ?e,
+                    "Failed fetching events from RPC"
+                );
+                telegram_bot::send_message("RPC events fetching failed").await;
+                break;
+            }
+        }
+        // next run starts from the last stored event
+        if settled.iter().any(|e| e.block_number > from_block) {
+            break;
+        }
+        start = end + 1;
+    }
+
+    settled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn felts(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn indexes_identical_events_in_order() {
+        let receipt_event = |from: &str, data: &[&str]| ReceiptEvent {
+            from_address: from.to_owned(),
+            keys: felts(&["0x99"]),
+            data: felts(data),
+        };
+        let receipt = vec![
+            receipt_event("0x1", &["0x5"]),
+            receipt_event("0x2", &["0x5"]),
+            receipt_event("0x2", &["0x5"]),
+        ];
+        let event = EmittedEvent {
+            from_address: "0x0002".to_owned(),
+            keys: felts(&["0x0099"]),
+            data: felts(&["0x05"]),
+            block_hash: Some("0xb".to_owned()),
+            block_number: Some(1),
+            transaction_hash: "0xa".to_owned(),
+        };
+
+        assert_eq!(find_event_index(&receipt, 0, &event), Some(1));
+        assert_eq!(find_event_index(&receipt, 2, &event), Some(2));
+        assert_eq!(find_event_index(&receipt, 3, &event), None);
+        assert_eq!(pad_felt("0xAB"), format!("0x{}ab", "0".repeat(62)));
+    }
+}
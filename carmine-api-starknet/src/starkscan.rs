@@ -1,17 +1,17 @@
 use std::{cmp::min, env, time::Duration};
 
-use async_recursion::async_recursion;
 use carmine_api_core::{
+    amm_events::{AmmEvent, EventDecodeError},
+    metrics,
     network::{protocol_address, starkscan_base_url, Network, Protocol},
     telegram_bot,
     types::{Event, StarkScanEvent, StarkScanEventResult, StarkScanEventSettled},
 };
-use carmine_api_db::{
-    create_batch_of_events, get_last_block_for_protocol_event, get_last_timestamp_carmine_event,
-};
+use carmine_api_db::{create_batch_of_events, get_last_timestamp_carmine_event};
 use reqwest::{Client, Error, Response};
 use serde::de::DeserializeOwned;
 use tokio::time::sleep;
+use tracing::{error, info, instrument, warn};
 
 pub struct StarkscanUrlBuilder<'a> {
     url: String,
@@ -75,15 +75,6 @@ impl<'a> StarkscanUrlBuilder<'a> {
 
 const STARKSCAN_REQUESTS_DELAY_IN_MS: u64 = 1000;
 
-// list of action names that will be stored
-const ALLOWED_ACTIONS: [&'static str; 5] = [
-    "TradeOpen",
-    "TradeClose",
-    "TradeSettle",
-    "DepositLiquidity",
-    "WithdrawLiquidity",
-];
-
 pub async fn api_call(url: &str) -> Result<Response, Error> {
     let api_key = env::var("STARKSCAN_API_KEY").expect("Failed to read API key");
     let mut headers = reqwest::header::HeaderMap::new();
@@ -111,62 +102,41 @@ pub async fn events_call(url: &str) -> Result<StarkScanEventResult, Error> {
 }
 
 pub fn parse_event(event: StarkScanEvent) -> Option<Event> {
-    // if "key_name" is null or not allowed action (eg "ExpireOptionTokenForPool")
-    // we can't handle the event so we don't store it
-    let action = match event.key_name {
-        Some(action) if ALLOWED_ACTIONS.iter().any(|&v| v == action) => action,
-        _ => {
-            return None;
-        }
-    };
-
-    // block_hash and block_number can sometimes be null, skip if that's the case
-    let block_hash = match event.block_hash {
-        Some(hash) => hash,
-        _ => {
-            return None;
-        }
-    };
-    // block_hash and block_number can sometimes be null, skip if that's the case
-    let block_number = match event.block_number {
-        Some(n) => n,
-        _ => {
-            return None;
-        }
-    };
-
-    // accessing data by index, make sure the length is correct
-    if event.data.len() != 6 {
-        return None;
-    }
-
-    Some(Event {
-        block_hash: block_hash,
-        block_number: block_number,
-        transaction_hash: event.transaction_hash,
-        event_index: event.event_index,
-        from_address: event.from_address,
-        timestamp: event.timestamp,
-        action: action,
-        caller: String::from(&event.data[0]),
-        token_address: String::from(&event.data[1]),
-        capital_transfered: String::from(&event.data[2]),
-        tokens_minted: String::from(&event.data[4]),
-    })
+    // block_hash, block_number and key_name can sometimes be null, skip if that's the case
+    get_settled_event(event).and_then(parse_settled_event)
 }
 
 // TODO: move Carmine events to Starkscan_events and then this will replace parse_event
 pub fn parse_settled_event(event: StarkScanEventSettled) -> Option<Event> {
-    // if "key_name" is null or not allowed action (eg "ExpireOptionTokenForPool")
-    // we can't handle the event so we don't store it
-    if !ALLOWED_ACTIONS.iter().any(|&v| v == event.key_name) {
-        return None;
+    let amm_event = match AmmEvent::decode(&event.key_name, &event.keys, &event.data) {
+        Ok(v) => v,
+        // not an AMM event we know (eg "Upgraded")
+        Err(EventDecodeError::UnknownEvent(_)) => return None,
+        Err(e) => {
+            warn!(event = %event.id, error = %e, "Failed decoding event");
+            return None;
+        }
     };
+    let action = amm_event.name().to_owned();
 
-    // accessing data by index, make sure the length is correct
-    if event.data.len() != 6 {
-        return None;
-    }
+    let (caller, token_address, capital_transfered, tokens_minted) = match amm_event {
+        AmmEvent::TradeOpen(trade) | AmmEvent::TradeClose(trade) | AmmEvent::TradeSettle(trade) => {
+            (
+                trade.caller,
+                trade.option_token,
+                trade.capital_transfered.to_hex(),
+                trade.option_tokens.to_hex(),
+            )
+        }
+        AmmEvent::DepositLiquidity(liquidity) | AmmEvent::WithdrawLiquidity(liquidity) => (
+            liquidity.caller,
+            liquidity.lp_token,
+            liquidity.capital_transfered.to_hex(),
+            liquidity.lp_tokens.to_hex(),
+        ),
+        // has no caller and capital, "events" table can't store it
+        AmmEvent::ExpireOptionTokenForPool(_) => return None,
+    };
 
     Some(Event {
         block_hash: event.block_hash,
@@ -175,11 +145,11 @@ pub fn parse_settled_event(event: StarkScanEventSettled) -> Option<Event> {
         event_index: event.event_index,
         from_address: event.from_address,
         timestamp: event.timestamp,
-        action: event.key_name,
-        caller: String::from(&event.data[0]),
-        token_address: String::from(&event.data[1]),
-        capital_transfered: String::from(&event.data[2]),
-        tokens_minted: String::from(&event.data[4]),
+        action,
+        caller,
+        token_address,
+        capital_transfered,
+        tokens_minted,
     })
 }
 
@@ -201,46 +171,55 @@ fn get_settled_event(event: StarkScanEvent) -> Option<StarkScanEventSettled> {
     return None;
 }
 
-#[async_recursion]
+#[instrument(skip_all, fields(url = %url, cutoff = cutoff_timestamp))]
 async fn _fetch_events(url: &str, data: &mut Vec<StarkScanEventSettled>, cutoff_timestamp: i64) {
-    let starkscan_response = match events_call(url).await {
-        Ok(v) => v,
-        Err(e) => {
-            // request failed, we cannot store partly fetched events, because that
-            // would create hole in the data -> throw away incomplete events
-            data.clear();
-            println!("Error from StarkScan: {:?}, URL: {}", e, url);
-            telegram_bot::send_message("Starkscan events fetching failed").await;
-            return;
-        }
-    };
-    let next_url_option = &starkscan_response.next_url;
+    let mut url = url.to_owned();
 
-    if let Some(message) = starkscan_response.message {
-        // if message something went wrong
-        // print and run same URL again
-        println!("Starkscan returned message: {}", message);
-        // prevent "limit exceeded"
-        sleep(Duration::from_millis(STARKSCAN_REQUESTS_DELAY_IN_MS)).await;
-        return _fetch_events(url, data, cutoff_timestamp).await;
-    }
+    loop {
+        let starkscan_response = match events_call(&url).await {
+            Ok(v) => v,
+            Err(e) => {
+                metrics::inc_starkscan_pages("error");
+                // request failed, we cannot store partly fetched events, because that
+                // would create hole in the data -> throw away incomplete events
+                data.clear();
+                error!(error = ?e, url = %url, "Error from Starkscan");
+                telegram_bot::send_message("Starkscan events fetching failed").await;
+                return;
+            }
+        };
+
+        if let Some(message) = starkscan_response.message {
+            // if message something went wrong
+            // log and run same URL again
+            warn!(message = %message, "Starkscan returned message");
+            metrics::inc_starkscan_pages("retry");
+            // prevent "limit exceeded"
+            sleep(Duration::from_millis(STARKSCAN_REQUESTS_DELAY_IN_MS)).await;
+            continue;
+        }
+        metrics::inc_starkscan_pages("ok");
 
-    if let Some(response_data) = starkscan_response.data {
-        for event in response_data {
-            if event.timestamp > cutoff_timestamp {
-                if let Some(settled) = get_settled_event(event) {
-                    data.push(settled);
+        if let Some(response_data) = starkscan_response.data {
+            for event in response_data {
+                if event.timestamp > cutoff_timestamp {
+                    if let Some(settled) = get_settled_event(event) {
+                        data.push(settled);
+                    }
+                } else {
+                    return;
                 }
-            } else {
-                return;
             }
         }
-    }
 
-    if let Some(next_url) = next_url_option {
-        // prevent "limit exceeded"
-        sleep(Duration::from_millis(STARKSCAN_REQUESTS_DELAY_IN_MS)).await;
-        return _fetch_events(next_url, data, cutoff_timestamp).await;
+        match starkscan_response.next_url {
+            Some(next_url) => {
+                // prevent "limit exceeded"
+                sleep(Duration::from_millis(STARKSCAN_REQUESTS_DELAY_IN_MS)).await;
+                url = next_url;
+            }
+            None => return,
+        }
     }
 }
 
@@ -250,7 +229,7 @@ pub async fn fetch_events(
 ) -> Vec<StarkScanEventSettled> {
     let mut data: Vec<StarkScanEventSettled> = vec![];
     _fetch_events(&initial_url, &mut data, cutoff_timestamp).await;
-    println!("Fetching event, URL: {}, data: {}", initial_url, data.len());
+    info!(url = %initial_url, events = data.len(), "Fetched Starkscan events");
     data
 }
 
@@ -258,8 +237,12 @@ pub async fn get_events_from_starkscan() {
     // no longer updating events for testnet
     let network = &Network::Mainnet;
     let last_timestamp = match get_last_timestamp_carmine_event(network) {
-        Some(t) => t,
-        None => return,
+        Ok(Some(t)) => t,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(error = %e, "Failed getting last Carmine event timestamp");
+            return;
+        }
     };
 
     let url = StarkscanUrlBuilder::new(network)
@@ -272,24 +255,10 @@ pub async fn get_events_from_starkscan() {
         .filter_map(|e| parse_settled_event(e))
         .collect();
     // update DB
-    create_batch_of_events(&parsed_events, network);
-    println!("Stored {} events from Starkscan", &parsed_events.len());
-}
-
-pub async fn get_protocol_events(
-    network: &Network,
-    protocol: &Protocol,
-) -> Vec<StarkScanEventSettled> {
-    let last_block_number: u32 = match get_last_block_for_protocol_event(network, protocol) {
-        Some(t) => t.try_into().expect("Failed parsing block_number -> u32"),
-        None => 0,
-    };
-    println!("Protocol: {}, last block: {}", protocol, last_block_number);
-    let url = StarkscanUrlBuilder::new(&network)
-        .protocol(protocol)
-        .from_block(last_block_number)
-        .get_url();
-    fetch_events(url, 0).await
+    match create_batch_of_events(&parsed_events, network) {
+        Ok(_) => info!(events = parsed_events.len(), "Stored events from Starkscan"),
+        Err(e) => warn!(error = %e, "Failed storing events from Starkscan"),
+    }
 }
 
 pub async fn get_block_range_events(
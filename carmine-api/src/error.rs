@@ -0,0 +1,140 @@
+use std::fmt;
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use carmine_api_rpc_gateway::RpcError;
+
+use crate::types::ErrorResponse;
+
+/// Errors returned by the handlers, `code` is stable and meant for clients,
+/// `message` is for humans and may change
+#[derive(Debug, PartialEq)]
+pub enum ApiError {
+    InvalidNetwork(String),
+    MissingAddress,
+    MissingPayload,
+    /// query parameters failed to parse
+    InvalidQuery(String),
+    InvalidCursor,
+    InvalidLimit(usize),
+    InvalidInterval,
+    /// pool in the query parameters
+    InvalidPool,
+    /// pool in the path
+    PoolNotFound,
+    /// token pair in the path
+    PairNotFound,
+    AddressNotEligible,
+    /// cache does not have the data (yet)
+    NoData,
+    /// none of the RPC nodes answered
+    RpcUnavailable,
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidNetwork(_) => "invalid_network",
+            ApiError::MissingAddress => "missing_address",
+            ApiError::MissingPayload => "missing_payload",
+            ApiError::InvalidQuery(_) => "invalid_query",
+            ApiError::InvalidCursor => "invalid_cursor",
+            ApiError::InvalidLimit(_) => "invalid_limit",
+            ApiError::InvalidInterval => "invalid_interval",
+            ApiError::InvalidPool => "invalid_pool",
+            ApiError::PoolNotFound => "pool_not_found",
+            ApiError::PairNotFound => "pair_not_found",
+            ApiError::AddressNotEligible => "address_not_eligible",
+            ApiError::NoData => "no_data",
+            ApiError::RpcUnavailable => "rpc_unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::InvalidNetwork(network) => write!(f, "Unknown network {}", network),
+            ApiError::MissingAddress => write!(f, "Did not receive address as a query parameter"),
+            ApiError::MissingPayload => write!(f, "No payload was provided"),
+            ApiError::InvalidQuery(e) => write!(f, "Invalid query parameters: {}", e),
+            ApiError::InvalidCursor => write!(f, "Invalid cursor"),
+            ApiError::InvalidLimit(max) => write!(f, "Limit must be between 1 and {}", max),
+            ApiError::InvalidInterval => write!(f, "Invalid interval, use eg. 15m, 1h, 1d or 1w"),
+            ApiError::InvalidPool | ApiError::PoolNotFound => write!(f, "Invalid pool"),
+            ApiError::PairNotFound => write!(f, "Unknown token pair"),
+            ApiError::AddressNotEligible => write!(f, "Address not on the list"),
+            ApiError::NoData => write!(f, "No data"),
+            ApiError::RpcUnavailable => write!(f, "Failed to get response from RPC Nodes"),
+            ApiError::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidNetwork(_)
+            | ApiError::MissingAddress
+            | ApiError::MissingPayload
+            | ApiError::InvalidQuery(_)
+            | ApiError::InvalidCursor
+            | ApiError::InvalidLimit(_)
+            | ApiError::InvalidInterval
+            | ApiError::InvalidPool => StatusCode::BAD_REQUEST,
+            ApiError::PoolNotFound | ApiError::PairNotFound | ApiError::AddressNotEligible => {
+                StatusCode::NOT_FOUND
+            }
+            ApiError::NoData | ApiError::RpcUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = match self.status_code() {
+            StatusCode::BAD_REQUEST => "bad_request",
+            StatusCode::NOT_FOUND => "not_found",
+            StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+            _ => "server_error",
+        };
+        HttpResponse::build(self.status_code()).json(ErrorResponse {
+            status: status.to_string(),
+            code: self.code().to_string(),
+            message: self.to_string(),
+        })
+    }
+}
+
+/// Failed RPC calls, only node failures are reported as unavailable
+impl From<RpcError> for ApiError {
+    fn from(e: RpcError) -> Self {
+        match e {
+            RpcError::Other(_) => ApiError::RpcUnavailable,
+            e => ApiError::Internal(format!("RPC call failed: {:?}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(
+            ApiError::InvalidNetwork("goerli".to_string()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(ApiError::PoolNotFound.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            ApiError::NoData.status_code(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            ApiError::from(RpcError::Other("timeout".to_string())),
+            ApiError::RpcUnavailable
+        );
+        assert_eq!(ApiError::InvalidLimit(1000).code(), "invalid_limit");
+    }
+}
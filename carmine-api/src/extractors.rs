@@ -0,0 +1,51 @@
+use std::{
+    future::{ready, Ready},
+    str::FromStr,
+};
+
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use carmine_api_core::network::Network;
+use utoipa::{
+    openapi::{
+        path::{Parameter, ParameterBuilder, ParameterIn},
+        schema::{ObjectBuilder, Type},
+        Required,
+    },
+    IntoParams,
+};
+
+use crate::error::ApiError;
+
+/// Network from the `{network}` path segment, requests with unknown network get 400
+pub struct NetworkPath(pub Network);
+
+impl FromRequest for NetworkPath {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let raw = req.match_info().get("network").unwrap_or_default();
+
+        ready(
+            Network::from_str(raw)
+                .map(NetworkPath)
+                .map_err(|_| ApiError::InvalidNetwork(raw.to_owned())),
+        )
+    }
+}
+
+// documents the `{network}` segment in the OpenAPI spec
+impl IntoParams for NetworkPath {
+    fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        let schema = ObjectBuilder::new()
+            .schema_type(Type::String)
+            .enum_values(Some(["mainnet", "testnet"]));
+
+        vec![ParameterBuilder::new()
+            .name("network")
+            .parameter_in(ParameterIn::Path)
+            .required(Required::True)
+            .schema(Some(schema))
+            .build()]
+    }
+}
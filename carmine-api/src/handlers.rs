@@ -1,6 +1,9 @@
 use actix_web::web;
 
+use crate::error::ApiError;
+
 mod common;
+mod openapi;
 mod v0;
 mod v1;
 
@@ -21,19 +24,34 @@ pub fn format_tx(tx: &String) -> String {
 
 pub fn config(conf: &mut web::ServiceConfig) {
     let scope = web::scope("")
+        // malformed query parameters get the same error body as the handlers
+        .app_data(
+            web::QueryConfig::default()
+                .error_handler(|err, _| ApiError::InvalidQuery(err.to_string()).into()),
+        )
         .service(common::liveness_probe_handler)
+        .service(common::metrics_handler)
         .service(
             web::scope("api")
+                .service(openapi::openapi_json)
+                .service(openapi::docs)
                 .service(v1::live_options)
+                .service(v1::option_chain)
+                .service(v1::greeks)
+                .service(v1::pool_risk)
                 .service(v1::transactions)
                 .service(v1::all_transactions)
+                .service(v1::portfolio)
+                .service(v1::pnl)
                 .service(v1::airdrop)
                 .service(v1::option_volatility)
-                .service(v1::pool_state)
-                .service(v1::pool_state_last)
-                .service(v1::pool_apy)
                 .service(v1::prices)
                 .service(v1::proxy_call)
+                .service(v1::stream)
+                // "/v1/{network}/{pool}" matches any other path, keep the pool routes last
+                .service(v1::pool_state_last)
+                .service(v1::pool_apy)
+                .service(v1::pool_state)
                 .service(v0::all_non_expired_handler)
                 .service(v0::trade_history_handler)
                 .service(v0::all_trade_history_handler),
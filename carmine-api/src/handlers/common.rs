@@ -1,4 +1,5 @@
 use actix_web::{get, http::header::ContentType, HttpResponse, Responder};
+use carmine_api_core::metrics;
 
 #[get("liveness")]
 pub async fn liveness_probe_handler() -> impl Responder {
@@ -6,3 +7,10 @@ pub async fn liveness_probe_handler() -> impl Responder {
         .content_type(ContentType::plaintext())
         .body("API is alive")
 }
+
+#[get("metrics")]
+pub async fn metrics_handler() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(metrics::content_type())
+        .body(metrics::render())
+}
@@ -0,0 +1,81 @@
+use actix_web::{get, http::header::ContentType, HttpResponse, Responder};
+use utoipa::OpenApi;
+
+use super::{v0, v1};
+
+// spec is rendered by Redoc loaded from CDN, nothing is bundled with the API,
+// version is pinned so that a new release does not change the page
+const DOCS_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>Carmine API</title>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+  </head>
+  <body>
+    <redoc spec-url="/api/openapi.json"></redoc>
+    <script src="https://cdn.jsdelivr.net/npm/redoc@2.1.5/bundles/redoc.standalone.js" crossorigin="anonymous"></script>
+  </body>
+</html>
+"#;
+
+/// Handlers are added to the spec here, new endpoint has to be listed
+/// in `paths` to show up in the docs and the generated clients
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Carmine API", description = "Carmine Options AMM data"),
+    servers((url = "/api")),
+    paths(
+        v1::live_options,
+        v1::option_chain,
+        v1::greeks,
+        v1::pool_risk,
+        v1::transactions,
+        v1::all_transactions,
+        v1::portfolio,
+        v1::pnl,
+        v1::airdrop,
+        v1::option_volatility,
+        v1::prices,
+        v1::proxy_call,
+        v1::stream,
+        v1::pool_state,
+        v1::pool_state_last,
+        v1::pool_apy,
+        v0::all_non_expired_handler,
+        v0::trade_history_handler,
+        v0::all_trade_history_handler,
+    )
+)]
+pub struct ApiDoc;
+
+#[get("openapi.json")]
+pub async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[get("docs")]
+pub async fn docs() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(DOCS_PAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_all_endpoints() {
+        let spec = ApiDoc::openapi();
+        let paths: Vec<&String> = spec.paths.paths.keys().collect();
+
+        assert_eq!(paths.len(), 19);
+        assert!(paths.contains(&&"/v1/{network}/{pool}/apy".to_string()));
+        assert!(paths.contains(&&"/all-non-expired".to_string()));
+        // response types are registered with the paths
+        let schemas = spec.components.unwrap().schemas;
+        assert!(schemas.contains_key("PoolOptionChain"));
+        assert!(schemas.contains_key("ErrorResponse"));
+    }
+}
@@ -1,93 +1,87 @@
 use crate::{
+    error::ApiError,
     handlers::format_tx,
     types::{
-        AllNonExpired, AllTradeHistoryResponse, GenericResponse, QueryOptions, TradeHistoryResponse,
+        AllNonExpired, AllTradeHistoryResponse, DataResponse, ErrorResponse, QueryOptions,
+        TradeHistoryResponse,
     },
 };
 use actix_web::{get, web, HttpResponse, Responder};
-use carmine_api_core::types::{AppState, TradeHistory};
-use std::sync::{Arc, Mutex};
+use carmine_api_core::{
+    network::Network,
+    types::{AppState, TradeHistory},
+};
 
+/// Testnet live options, use `/v1/{network}/live-options`
+#[utoipa::path(
+    tag = "v0",
+    path = "/all-non-expired",
+    responses((status = 200, body = DataResponse<Vec<String>>))
+)]
 #[get("all-non-expired")]
 pub async fn all_non_expired_handler(
     _opts: web::Query<QueryOptions>,
-    data: web::Data<Arc<Mutex<AppState>>>,
+    data: web::Data<AppState>,
 ) -> impl Responder {
-    let locked = &data.lock();
-    let response_data = match locked {
-        Ok(app_data) => &app_data.testnet.all_non_expired,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
-    };
+    let app_data = data.get_data(&Network::Testnet);
 
     HttpResponse::Ok().json(AllNonExpired {
         status: "success".to_string(),
-        data: response_data,
+        data: &app_data.all_non_expired,
     })
 }
 
+/// Testnet events triggered by the address, use `/v1/{network}/transactions`
+#[utoipa::path(
+    tag = "v0",
+    path = "/trade-history",
+    params(QueryOptions),
+    responses(
+        (status = 200, body = DataResponse<Vec<TradeHistory>>),
+        (status = 400, body = ErrorResponse)
+    )
+)]
 #[get("trade-history")]
 pub async fn trade_history_handler(
     opts: web::Query<QueryOptions>,
-    data: web::Data<Arc<Mutex<AppState>>>,
-) -> impl Responder {
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
     let address = match &opts.address {
         Some(address) => format_tx(address),
-        _ => {
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "Did not receive address as a query parameter".to_string(),
-            });
-        }
-    };
-    let locked = &data.lock();
-    let all_trade_history = match locked {
-        Ok(app_data) => &app_data.testnet.trade_history,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
+        None => return Err(ApiError::MissingAddress),
     };
+    let app_data = data.get_data(&Network::Testnet);
 
     let mut address_specific_trade_history: Vec<&TradeHistory> = vec![];
 
-    for history in all_trade_history {
+    for history in &app_data.trade_history {
         if history.caller == address {
             address_specific_trade_history.push(history);
         }
     }
 
-    HttpResponse::Ok().json(TradeHistoryResponse {
+    Ok(HttpResponse::Ok().json(TradeHistoryResponse {
         status: "success".to_string(),
         data: address_specific_trade_history,
-    })
+    }))
 }
 
+/// All testnet events, use `/v1/{network}/all-transactions`
+#[utoipa::path(
+    tag = "v0",
+    path = "/all-trade-history",
+    responses((status = 200, body = AllTradeHistoryResponse))
+)]
 #[get("all-trade-history")]
 pub async fn all_trade_history_handler(
     _opts: web::Query<QueryOptions>,
-    data: web::Data<Arc<Mutex<AppState>>>,
+    data: web::Data<AppState>,
 ) -> impl Responder {
-    let locked = &data.lock();
-    let res = match locked {
-        Ok(app_data) => &app_data.testnet.trade_history,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
-    };
+    let app_data = data.get_data(&Network::Testnet);
 
     let mut data: Vec<&TradeHistory> = vec![];
 
-    for history in res {
+    for history in &app_data.trade_history {
         data.push(history);
     }
 
@@ -1,433 +1,585 @@
 use crate::{
+    error::ApiError,
+    extractors::NetworkPath,
     handlers::format_tx,
+    pagination::{
+        paginate_trade_history, Cursor, TradeHistoryFilter, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+    },
+    stream::LiveStream,
     types::{
-        AllNonExpired, AllTradeHistoryResponse, DataResponse, GenericResponse, QueryOptions,
+        AllNonExpired, DataResponse, ErrorResponse, PaginatedTradeHistoryResponse, PairPath,
+        PoolPath, PoolStateHistory, PoolStateQuery, QueryOptions, StreamQuery, TradeHistoryQuery,
         TradeHistoryResponse,
     },
 };
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use actix_web::{
     get,
-    http::header::AcceptEncoding,
+    http::header::{AcceptEncoding, CacheControl, CacheDirective, ContentEncoding},
     post,
     web::{self},
-    HttpResponse, Responder,
+    HttpResponse,
 };
-use carmine_api_core::{network::Network, types::AppState};
-use lazy_static::lazy_static;
-use std::{
-    env,
-    sync::{Arc, Mutex},
+use carmine_api_cache::state_history::{
+    bucket_pool_state, bucket_precomputed, filter_pool_state, parse_interval,
 };
+use carmine_api_core::{
+    pool::get_all_pools,
+    types::{
+        AppState, ApyResult, OptionGreeks, OptionWithVolatility, OraclePriceConcise,
+        PoolOptionChain, PoolRisk, PoolStateWithTimestamp, Portfolio, TradeHistory, UserPnl,
+    },
+};
+use carmine_api_rpc_gateway::proxy_call as rpc_proxy_call;
+use carmine_api_starknet::{
+    carmine::Carmine,
+    option_chain::{build_greeks, build_option_chain},
+    pnl::calculate_pnl,
+    risk::{pools_risk, HistoryRange, DEFAULT_UTILIZATION_INTERVAL},
+};
+use tracing::warn;
 
-lazy_static! {
-    static ref BLAST_API_URL: String =
-        env::var("BLAST_API_URL").expect("missing env var BLAST_API_URL");
-    static ref INFURA_URL: String = env::var("INFURA_URL").expect("missing env var INFURA_URL");
-    static ref INFURA_TESTNET_URL: String =
-        env::var("INFURA_TESTNET_URL").expect("missing env var INFURA_TESTNET_URL");
-    static ref CARMINE_JUNO_NODE_URL: String =
-        env::var("CARMINE_JUNO_NODE_URL").expect("missing env var CARMINE_JUNO_NODE_URL");
-    static ref CARMINE_JUNO_TESTNET_NODE_URL: String = env::var("CARMINE_JUNO_TESTNET_NODE_URL")
-        .expect("missing env var CARMINE_JUNO_TESTNET_NODE_URL");
-}
+type ApiResult = Result<HttpResponse, ApiError>;
 
-const TESTNET: &'static str = "testnet";
-const MAINNET: &'static str = "mainnet";
+fn required_address(opts: &QueryOptions) -> Result<String, ApiError> {
+    match &opts.address {
+        Some(address) => Ok(format_tx(address)),
+        None => Err(ApiError::MissingAddress),
+    }
+}
 
+/// Options that can be currently traded, raw felts as returned by the AMM
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath),
+    responses(
+        (status = 200, body = DataResponse<Vec<String>>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse)
+    )
+)]
 #[get("/v1/{network}/live-options")]
 pub async fn live_options(
-    path: web::Path<String>,
-    data: web::Data<Arc<Mutex<AppState>>>,
-) -> impl Responder {
-    let network = match path.into_inner().as_str() {
-        TESTNET => Network::Testnet,
-        MAINNET => Network::Mainnet,
-        _ => {
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "Specify network in the path".to_string(),
-            });
-        }
-    };
-    let locked = &data.lock();
-    let app_state = match locked {
-        Ok(app_data) => app_data,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
-    };
-    let data = match network {
-        Network::Testnet => &app_state.testnet.all_non_expired,
-        Network::Mainnet => &app_state.mainnet.all_non_expired,
-    };
+    NetworkPath(network): NetworkPath,
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let app_data = data.get_data(&network);
+
+    Ok(HttpResponse::Ok().json(AllNonExpired {
+        status: "success".to_string(),
+        data: &app_data.all_non_expired,
+    }))
+}
+
+/// Live options decoded and grouped by pool, maturity and strike
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath),
+    responses(
+        (status = 200, body = DataResponse<Vec<PoolOptionChain>>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/option-chain")]
+pub async fn option_chain(
+    NetworkPath(network): NetworkPath,
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let app_data = data.get_data(&network);
+
+    let data = build_option_chain(
+        &app_data.all_non_expired,
+        &app_data.option_volatility,
+        &app_data.oracle_prices,
+        &network,
+    )
+    .map_err(|e| {
+        warn!(network = %network, error = %e, "Failed building option chain");
+        ApiError::Internal("Failed to decode live options".to_string())
+    })?;
+
+    Ok(HttpResponse::Ok().json(DataResponse {
+        status: "success".to_string(),
+        data,
+    }))
+}
 
-    HttpResponse::Ok().json(AllNonExpired {
+/// Live options with Black-Scholes Greeks
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath),
+    responses(
+        (status = 200, body = DataResponse<Vec<OptionGreeks>>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/greeks")]
+pub async fn greeks(NetworkPath(network): NetworkPath, data: web::Data<AppState>) -> ApiResult {
+    let app_data = data.get_data(&network);
+
+    let data = build_greeks(
+        &app_data.all_non_expired,
+        &app_data.option_volatility,
+        &app_data.oracle_prices,
+        &network,
+    )
+    .map_err(|e| {
+        warn!(network = %network, error = %e, "Failed calculating greeks");
+        ApiError::Internal("Failed to decode live options".to_string())
+    })?;
+
+    Ok(HttpResponse::Ok().json(DataResponse {
         status: "success".to_string(),
         data,
-    })
+    }))
+}
+
+/// Net Greeks exposure and capital utilization of each pool
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath, PoolStateQuery),
+    responses(
+        (status = 200, body = DataResponse<Vec<PoolRisk>>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/pool-risk")]
+pub async fn pool_risk(
+    NetworkPath(network): NetworkPath,
+    opts: web::Query<PoolStateQuery>,
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let range = HistoryRange {
+        from: opts.from,
+        to: opts.to,
+        interval: match &opts.interval {
+            Some(raw) => parse_interval(raw).ok_or(ApiError::InvalidInterval)?,
+            None => DEFAULT_UTILIZATION_INTERVAL,
+        },
+    };
+    let app_data = data.get_data(&network);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs() as i64;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(AcceptEncoding(vec!["gzip".parse().unwrap()]))
+        .json(DataResponse {
+            status: "success".to_string(),
+            data: pools_risk(
+                &app_data.option_volatility,
+                &app_data.oracle_prices,
+                &app_data.state,
+                &network,
+                now,
+                &range,
+            ),
+        }))
 }
 
+/// All events triggered by the address
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath, QueryOptions),
+    responses(
+        (status = 200, body = DataResponse<Vec<TradeHistory>>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse)
+    )
+)]
 #[get("/v1/{network}/transactions")]
 pub async fn transactions(
+    NetworkPath(network): NetworkPath,
     opts: web::Query<QueryOptions>,
-    path: web::Path<String>,
-    data: web::Data<Arc<Mutex<AppState>>>,
-) -> impl Responder {
-    let network = match path.into_inner().as_str() {
-        TESTNET => Network::Testnet,
-        MAINNET => Network::Mainnet,
-        _ => {
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "Specify network in the path".to_string(),
-            });
-        }
-    };
-    let address = match &opts.address {
-        Some(address) => format_tx(address),
-        _ => {
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "Did not receive address as a query parameter".to_string(),
-            });
-        }
-    };
-    let locked = &data.lock();
-    let app_state = match locked {
-        Ok(app_data) => app_data,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
-    };
-    let all_history = match network {
-        Network::Testnet => &app_state.testnet.trade_history,
-        Network::Mainnet => &app_state.mainnet.trade_history,
-    };
-
-    let data = all_history.iter().filter(|h| h.caller == address).collect();
-
-    HttpResponse::Ok().json(TradeHistoryResponse {
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let address = required_address(&opts)?;
+    let app_data = data.get_data(&network);
+
+    let data = app_data
+        .trade_history
+        .iter()
+        .filter(|h| h.caller == address)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(TradeHistoryResponse {
         status: "success".to_string(),
         data,
-    })
+    }))
 }
 
+/// Stored events, paginated and ordered by timestamp and transaction hash
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath, TradeHistoryQuery),
+    responses(
+        (status = 200, body = PaginatedTradeHistoryResponse),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse)
+    )
+)]
 #[get("/v1/{network}/all-transactions")]
 pub async fn all_transactions(
-    path: web::Path<String>,
-    data: web::Data<Arc<Mutex<AppState>>>,
-) -> impl Responder {
-    let network = match path.into_inner().as_str() {
-        TESTNET => Network::Testnet,
-        MAINNET => Network::Mainnet,
-        _ => {
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "Specify network in the path".to_string(),
-            });
-        }
+    NetworkPath(network): NetworkPath,
+    opts: web::Query<TradeHistoryQuery>,
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let cursor = match &opts.cursor {
+        Some(raw) => Some(Cursor::parse(raw).map_err(|_| ApiError::InvalidCursor)?),
+        None => None,
     };
-    let locked = &data.lock();
-    let app_state = match locked {
-        Ok(app_data) => app_data,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
+    let limit = match opts.limit {
+        Some(n) if n == 0 || n > MAX_PAGE_SIZE => {
+            return Err(ApiError::InvalidLimit(MAX_PAGE_SIZE));
         }
+        Some(n) => n,
+        None => DEFAULT_PAGE_SIZE,
     };
-
-    let data = match network {
-        Network::Testnet => &app_state.testnet.trade_history,
-        Network::Mainnet => &app_state.mainnet.trade_history,
+    let pools = get_all_pools(&network);
+    let pool = match &opts.pool {
+        Some(pool_id) => Some(
+            pools
+                .iter()
+                .find(|pool| pool.id == pool_id.as_str())
+                .ok_or(ApiError::InvalidPool)?,
+        ),
+        None => None,
+    };
+    let filter = TradeHistoryFilter {
+        action: opts.action.as_deref(),
+        pool,
+        option_address: opts.option.as_ref().map(format_tx),
+        from: opts.from,
+        to: opts.to,
     };
+    let app_data = data.get_data(&network);
 
-    let length = data.len();
+    let page = paginate_trade_history(&app_data.trade_history, &filter, cursor.as_ref(), limit);
 
-    HttpResponse::Ok().json(AllTradeHistoryResponse {
+    Ok(HttpResponse::Ok().json(PaginatedTradeHistoryResponse {
         status: "success".to_string(),
-        data: data.iter().collect(),
-        length,
-    })
+        data: page.data,
+        length: page.length,
+        next_cursor: page.next_cursor,
+    }))
 }
 
-#[get("/v1/mainnet/airdrop")]
-pub async fn airdrop(
+/// Options and liquidity of the address read from the AMM
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath, QueryOptions),
+    responses(
+        (status = 200, body = DataResponse<Portfolio>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 500, body = ErrorResponse),
+        (status = 503, body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/portfolio")]
+pub async fn portfolio(
+    NetworkPath(network): NetworkPath,
     opts: web::Query<QueryOptions>,
-    data: web::Data<Arc<Mutex<AppState>>>,
-) -> impl Responder {
-    let address = match &opts.address {
-        Some(address) => format_tx(address),
-        _ => {
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "Did not receive address as a query parameter".to_string(),
-            });
-        }
-    };
-    let locked = &data.lock();
-    let app_state = match locked {
-        Ok(app_data) => app_data,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
-    };
+) -> ApiResult {
+    let address = required_address(&opts)?;
+
+    let data = Carmine::new(network)
+        .get_portfolio(&address)
+        .await
+        .map_err(|e| {
+            warn!(address = %address, error = ?e, "Failed getting portfolio");
+            ApiError::from(e)
+        })?;
+
+    Ok(HttpResponse::Ok().json(DataResponse {
+        status: "success".to_string(),
+        data,
+    }))
+}
 
-    let data = match app_state.airdrop.address_calldata(&address) {
-        Ok(v) => v,
-        _ => {
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "Address not on the list".to_string(),
-            });
-        }
-    };
+/// Profit and loss of the address for each option it traded
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath, QueryOptions),
+    responses(
+        (status = 200, body = DataResponse<UserPnl>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/pnl")]
+pub async fn pnl(
+    NetworkPath(network): NetworkPath,
+    opts: web::Query<QueryOptions>,
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let address = required_address(&opts)?;
+    let app_data = data.get_data(&network);
+
+    let data = calculate_pnl(
+        &address,
+        &app_data.trade_history,
+        &app_data.all_non_expired,
+        &network,
+    )
+    .map_err(|e| {
+        warn!(address = %address, error = %e, "Failed calculating PnL");
+        ApiError::Internal("Failed to calculate PnL".to_string())
+    })?;
+
+    Ok(HttpResponse::Ok().json(DataResponse {
+        status: "success".to_string(),
+        data,
+    }))
+}
+
+/// Airdrop calldata of the address
+#[utoipa::path(
+    tag = "v1",
+    params(QueryOptions),
+    responses(
+        (status = 200, body = DataResponse<Vec<String>>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 404, description = "Address not eligible", body = ErrorResponse)
+    )
+)]
+#[get("/v1/mainnet/airdrop")]
+pub async fn airdrop(opts: web::Query<QueryOptions>, data: web::Data<AppState>) -> ApiResult {
+    let address = required_address(&opts)?;
 
-    HttpResponse::Ok().json(DataResponse {
+    let data = data
+        .airdrop
+        .address_calldata(&address)
+        .map_err(|_| ApiError::AddressNotEligible)?;
+
+    Ok(HttpResponse::Ok().json(DataResponse {
         status: "success".to_string(),
         data,
-    })
+    }))
 }
 
-#[get("/v1/mainnet/{pool}")]
+/// Pool state history, bucketed when `interval` is given
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath, PoolPath, PoolStateQuery),
+    responses(
+        (status = 200, body = DataResponse<PoolStateHistory>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 404, description = "Pool not found", body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/{pool}")]
 pub async fn pool_state(
-    path: web::Path<String>,
-    data: web::Data<Arc<Mutex<AppState>>>,
-) -> impl Responder {
-    let pool_id = path.into_inner();
-
-    let locked = &data.lock();
-    let app_state = match locked {
-        Ok(app_data) => app_data,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
+    NetworkPath(network): NetworkPath,
+    opts: web::Query<PoolStateQuery>,
+    path: web::Path<PoolPath>,
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let pool_id = path.into_inner().pool;
+    let app_data = data.get_data(&network);
+
+    let state = app_data.state.get(&pool_id).ok_or(ApiError::PoolNotFound)?;
+
+    let interval = match &opts.interval {
+        Some(raw) => Some(parse_interval(raw).ok_or(ApiError::InvalidInterval)?),
+        None => None,
     };
 
-    match app_state.mainnet.state.get(&pool_id) {
-        Some(state) => {
-            // found state
-            return HttpResponse::Ok()
-                .insert_header(AcceptEncoding(vec!["gzip".parse().unwrap()]))
-                .json(DataResponse {
-                    status: "success".to_string(),
-                    data: state,
-                });
-        }
-        None => {
-            // invalid pool
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "Invalid pool".to_string(),
-            });
-        }
-    }
+    let data = match interval {
+        // without range this is the whole history as before
+        None => PoolStateHistory::States(filter_pool_state(state, opts.from, opts.to)),
+        // common intervals are bucketed by the cache
+        Some(interval) => PoolStateHistory::Buckets(
+            match app_data
+                .state_buckets
+                .get(&pool_id)
+                .and_then(|buckets| buckets.get(&interval))
+            {
+                Some(precomputed) => {
+                    bucket_precomputed(state, precomputed, opts.from, opts.to, interval)
+                }
+                None => bucket_pool_state(state, opts.from, opts.to, interval),
+            },
+        ),
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header(AcceptEncoding(vec!["gzip".parse().unwrap()]))
+        .json(DataResponse {
+            status: "success".to_string(),
+            data,
+        }))
 }
 
-#[get("/v1/mainnet/{pool}/state")]
+/// Last pool state
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath, PoolPath),
+    responses(
+        (status = 200, body = DataResponse<PoolStateWithTimestamp>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 404, description = "Pool not found", body = ErrorResponse),
+        (status = 503, body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/{pool}/state")]
 pub async fn pool_state_last(
-    path: web::Path<String>,
-    data: web::Data<Arc<Mutex<AppState>>>,
-) -> impl Responder {
-    let pool_id = path.into_inner();
-
-    let locked = &data.lock();
-    let app_state = match locked {
-        Ok(app_data) => app_data,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
-    };
+    NetworkPath(network): NetworkPath,
+    path: web::Path<PoolPath>,
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let pool_id = path.into_inner().pool;
+    let app_data = data.get_data(&network);
 
-    let state = match app_state.mainnet.state.get(&pool_id) {
-        Some(state) => state,
-        None => {
-            // invalid pool
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "Invalid pool".to_string(),
-            });
-        }
-    };
+    let state = app_data.state.get(&pool_id).ok_or(ApiError::PoolNotFound)?;
 
-    let max_element = state.iter().max_by_key(|v| v.block_number);
+    let latest = state
+        .iter()
+        .max_by_key(|v| v.block_number)
+        .ok_or(ApiError::NoData)?;
 
-    match max_element {
-        Some(latest) => HttpResponse::Ok().json(DataResponse {
-            status: "success".to_string(),
-            data: latest,
-        }),
-        None => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "No data".to_string(),
-            });
-        }
-    }
+    Ok(HttpResponse::Ok().json(DataResponse {
+        status: "success".to_string(),
+        data: latest,
+    }))
 }
 
-#[get("/v1/mainnet/{pool}/apy")]
+/// APY of the pool over several windows
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath, PoolPath),
+    responses(
+        (status = 200, body = DataResponse<Vec<ApyResult>>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 404, description = "Pool not found", body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/{pool}/apy")]
 pub async fn pool_apy(
-    path: web::Path<String>,
-    data: web::Data<Arc<Mutex<AppState>>>,
-) -> impl Responder {
-    let pool_id = path.into_inner();
-
-    let locked = &data.lock();
-    let app_state = match locked {
-        Ok(app_data) => app_data,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
-    };
-
-    match app_state.mainnet.apy.get(&pool_id) {
-        Some(apy) => {
-            // found state
-            return HttpResponse::Ok()
-                .insert_header(AcceptEncoding(vec!["gzip".parse().unwrap()]))
-                .json(DataResponse {
-                    status: "success".to_string(),
-                    data: apy,
-                });
-        }
-        None => {
-            // invalid pool
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "Invalid pool".to_string(),
-            });
-        }
-    }
+    NetworkPath(network): NetworkPath,
+    path: web::Path<PoolPath>,
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let pool_id = path.into_inner().pool;
+    let app_data = data.get_data(&network);
+
+    let apy = app_data.apy.get(&pool_id).ok_or(ApiError::PoolNotFound)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(AcceptEncoding(vec!["gzip".parse().unwrap()]))
+        .json(DataResponse {
+            status: "success".to_string(),
+            data: apy,
+        }))
 }
 
-#[get("/v1/mainnet/option-volatility")]
-pub async fn option_volatility(data: web::Data<Arc<Mutex<AppState>>>) -> impl Responder {
-    let locked = &data.lock();
-    let app_state = match locked {
-        Ok(app_data) => app_data,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
-    };
-
-    HttpResponse::Ok().json(DataResponse {
+/// All options with volatility history
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath),
+    responses(
+        (status = 200, body = DataResponse<Vec<OptionWithVolatility>>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/option-volatility")]
+pub async fn option_volatility(
+    NetworkPath(network): NetworkPath,
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let app_data = data.get_data(&network);
+
+    Ok(HttpResponse::Ok().json(DataResponse {
         status: "success".to_string(),
-        data: &app_state.mainnet.option_volatility,
-    })
+        data: &app_data.option_volatility,
+    }))
 }
 
-#[get("/v1/mainnet/prices/{pair_id}")]
+/// Pragma prices of the pair
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath, PairPath),
+    responses(
+        (status = 200, body = DataResponse<Vec<OraclePriceConcise>>),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 404, description = "Pair not found", body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/prices/{pair_id}")]
 pub async fn prices(
-    path: web::Path<String>,
-    data: web::Data<Arc<Mutex<AppState>>>,
-) -> impl Responder {
-    let pair_id = path.into_inner();
-    let locked = &data.lock();
-    let app_state = match locked {
-        Ok(app_data) => app_data,
-        _ => {
-            return HttpResponse::InternalServerError().json(GenericResponse {
-                status: "server_error".to_string(),
-                message: "Failed to read AppState".to_string(),
-            });
-        }
-    };
-
-    HttpResponse::Ok().json(DataResponse {
+    NetworkPath(network): NetworkPath,
+    path: web::Path<PairPath>,
+    data: web::Data<AppState>,
+) -> ApiResult {
+    let pair_id = path.into_inner().pair_id;
+    let app_data = data.get_data(&network);
+
+    let prices = app_data
+        .oracle_prices
+        .get(&pair_id)
+        .ok_or(ApiError::PairNotFound)?;
+
+    Ok(HttpResponse::Ok().json(DataResponse {
         status: "success".to_string(),
-        data: &app_state.mainnet.oracle_prices.get(&pair_id),
-    })
+        data: prices,
+    }))
 }
 
-#[post("/v1/{network}/call")]
-async fn proxy_call(path: web::Path<String>, payload: Option<web::Bytes>) -> impl Responder {
-    let network = match path.into_inner().as_str() {
-        TESTNET => Network::Testnet,
-        MAINNET => Network::Mainnet,
-        unknown_network => {
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: format!("Unknown network {}", unknown_network),
-            });
-        }
-    };
-
-    let client = reqwest::Client::new();
-
-    let some_payload = match payload {
-        Some(data) => data,
-        None => {
-            return HttpResponse::BadRequest().json(GenericResponse {
-                status: "bad_request".to_string(),
-                message: "No payload was provided".to_string(),
-            })
-        }
-    };
-
-    let carmine_juno_url = match network {
-        Network::Mainnet => CARMINE_JUNO_NODE_URL.as_str(),
-        Network::Testnet => CARMINE_JUNO_TESTNET_NODE_URL.as_str(),
-    };
-
-    // proxy to Carmine Juno Node
-    let juno_res = client
-        .post(carmine_juno_url)
-        .body(some_payload.to_vec())
-        .send()
-        .await;
-
-    if let Ok(ok_response) = juno_res {
-        let parsed_response = ok_response.bytes().await;
-        if let Ok(bytes) = parsed_response {
-            return HttpResponse::Ok().body(bytes);
+/// Server-Sent Events with new trades (`trade`), pool states (`pool_state`) and prices (`price`)
+#[utoipa::path(
+    tag = "v1",
+    params(NetworkPath, StreamQuery),
+    responses(
+        (status = 200, description = "Event stream", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 404, description = "Pool not found", body = ErrorResponse)
+    )
+)]
+#[get("/v1/{network}/stream")]
+pub async fn stream(
+    NetworkPath(network): NetworkPath,
+    opts: web::Query<StreamQuery>,
+    live_stream: web::Data<LiveStream>,
+) -> ApiResult {
+    if let Some(pool_id) = &opts.pool {
+        if !get_all_pools(&network)
+            .iter()
+            .any(|pool| &pool.id == pool_id)
+        {
+            return Err(ApiError::PoolNotFound);
         }
     }
 
-    let infura_node_url = match network {
-        Network::Mainnet => INFURA_URL.as_str(),
-        Network::Testnet => INFURA_TESTNET_URL.as_str(),
-    };
-
-    // if Carmine Juno Node did not succeed proxy to Infura Node
-    let infura_res = client
-        .post(infura_node_url)
-        .body(some_payload.to_vec())
-        .send()
-        .await;
-
-    if let Ok(ok_response) = infura_res {
-        let parsed_response = ok_response.bytes().await;
-        if let Ok(bytes) = parsed_response {
-            return HttpResponse::Ok().body(bytes);
-        }
-    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        // compression would buffer the events
+        .insert_header(ContentEncoding::Identity)
+        .streaming(live_stream.subscribe(network, opts.into_inner().pool)))
+}
 
-    // if neither succeeded return internal server error
-    HttpResponse::InternalServerError().json(GenericResponse {
-        status: "error".to_string(),
-        message: "Failed to get response from RPC Nodes".to_string(),
-    })
+/// Proxies JSON-RPC call to the healthiest RPC node
+#[utoipa::path(
+    tag = "v1",
+    request_body(content = Object, description = "JSON-RPC request"),
+    params(NetworkPath),
+    responses(
+        (status = 200, body = Object),
+        (status = 400, description = "Invalid network or query", body = ErrorResponse),
+        (status = 503, body = ErrorResponse)
+    )
+)]
+#[post("/v1/{network}/call")]
+async fn proxy_call(NetworkPath(network): NetworkPath, payload: Option<web::Bytes>) -> ApiResult {
+    let payload = payload.ok_or(ApiError::MissingPayload)?;
+
+    // proxy to the healthiest RPC node, others are tried if it fails
+    let bytes = rpc_proxy_call(payload.to_vec(), &network)
+        .await
+        .map_err(|e| {
+            warn!(error = ?e, "Proxy call failed");
+            ApiError::RpcUnavailable
+        })?;
+
+    Ok(HttpResponse::Ok().body(bytes))
 }
@@ -1,20 +1,36 @@
+mod error;
+mod extractors;
 mod handlers;
+mod pagination;
+mod request_metrics;
+mod stream;
 mod types;
 
 use actix_cors::Cors;
-use actix_web::middleware::{self, Logger};
+use actix_web::middleware::{self, from_fn, Logger};
 use actix_web::web::Data;
 use actix_web::{http::header, App, HttpServer};
 use carmine_api_airdrop::merkle_tree::MerkleTree;
 use carmine_api_cache::Cache;
+use carmine_api_core::metrics;
 use carmine_api_core::network::Network;
+use carmine_api_core::telemetry;
 use carmine_api_core::types::AppState;
+use carmine_api_db::{listen_for_changes, DataChange};
+use carmine_api_starknet::pool_registry::init_pool_registry;
 use dotenvy::dotenv;
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::sync::{Arc, Mutex};
-use tokio::time::{sleep, Duration};
+use std::thread;
+use stream::LiveStream;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tracing::{error, info};
 
+// every section is updated this often even without DB notifications
 const UPDATE_APP_STATE_INTERVAL: u64 = 300;
+// fetcher writes several tables for a block, notifications within this time are handled together
+const CHANGES_DEBOUNCE_MILLIS: u64 = 500;
 
 const LOCAL_IP: &str = "127.0.0.1";
 const DOCKER_IP: &str = "0.0.0.0";
@@ -36,6 +52,45 @@ fn ip_address() -> &'static str {
     }
 }
 
+/// Waits for changes committed to the DB by the fetcher, adds update of every
+/// section of both networks every UPDATE_APP_STATE_INTERVAL. The sections are
+/// updated incrementally, only `DataChange::All` notification reads everything again.
+async fn next_changes(
+    receiver: &mut UnboundedReceiver<(Network, DataChange)>,
+    last_full_update: &mut Option<Instant>,
+) -> HashMap<Network, HashSet<DataChange>> {
+    let full_update_interval = Duration::from_secs(UPDATE_APP_STATE_INTERVAL);
+    let mut changes: HashMap<Network, HashSet<DataChange>> = HashMap::new();
+
+    let wait = match last_full_update {
+        Some(t) => full_update_interval.saturating_sub(t.elapsed()),
+        None => Duration::ZERO,
+    };
+    if let Ok(Some((network, change))) = timeout(wait, receiver.recv()).await {
+        changes.entry(network).or_default().insert(change);
+        sleep(Duration::from_millis(CHANGES_DEBOUNCE_MILLIS)).await;
+        while let Ok((network, change)) = receiver.try_recv() {
+            changes.entry(network).or_default().insert(change);
+        }
+    }
+
+    let full_update = match last_full_update {
+        Some(t) => t.elapsed() >= full_update_interval,
+        None => true,
+    };
+    if full_update {
+        *last_full_update = Some(Instant::now());
+        for network in [Network::Mainnet, Network::Testnet] {
+            changes
+                .entry(network)
+                .or_default()
+                .extend(DataChange::SECTIONS);
+        }
+    }
+
+    changes
+}
+
 /// Checks necessary ENV variables and panics if any is missing
 fn startup_check() {
     let environment = env::var("ENVIRONMENT").expect("ENV \"ENVIRONMENT\" is not set");
@@ -51,72 +106,88 @@ fn startup_check() {
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
+    let _telemetry = telemetry::init("carmine-api");
     startup_check();
 
-    println!("👷 Starting server");
+    info!("Starting server");
+
+    info!("Loading pools");
 
-    if env::var_os("RUST_LOG").is_none() {
-        env::set_var("RUST_LOG", "info");
+    for network in [Network::Mainnet, Network::Testnet] {
+        if let Err(e) = init_pool_registry(&network).await {
+            error!(network = %network, error = %e, "Failed loading pools");
+            return Err(std::io::Error::other(e));
+        }
     }
-    env_logger::init();
 
-    println!("🛠️  Creating cache instances...");
+    info!("Creating cache instances");
 
     let mut mainnet_cache = Cache::new(Network::Mainnet).await;
     let mut testnet_cache = Cache::new(Network::Testnet).await;
 
-    println!("🛠️  Getting data from DB...");
+    info!("Getting data from DB");
 
     let mainnet = mainnet_cache.get_app_data();
 
-    println!("✨ Got Mainnet data");
+    info!("Got Mainnet data");
 
     let testnet = testnet_cache.get_app_data();
 
-    println!("✨ Got Testnet data");
+    info!("Got Testnet data");
 
     let airdrop: MerkleTree = MerkleTree::new();
 
-    println!("✨ Got Airdrop data");
+    info!("Got Airdrop data");
 
-    println!("🛠️  Creating app state...");
+    info!("Creating app state");
 
-    let app_state = Data::new(Arc::new(Mutex::new(AppState {
-        mainnet,
-        testnet,
-        airdrop,
-    })));
+    let app_state = Data::new(AppState::new(mainnet, testnet, airdrop));
 
-    println!("🛠️  Cloning app state...");
+    info!("Cloning app state");
 
     let app_state_clone = app_state.clone();
 
-    println!("🛠️  Spawning app state updating thread...");
+    let live_stream = Data::new(LiveStream::new());
+    let live_stream_clone = live_stream.clone();
+
+    info!("Listening for DB changes");
+
+    let (changes_sender, mut changes_receiver) = mpsc::unbounded_channel();
+    for network in [Network::Mainnet, Network::Testnet] {
+        let sender = changes_sender.clone();
+        // blocking DB connection, gets its own thread
+        thread::spawn(move || {
+            listen_for_changes(&network, |change| {
+                let _ = sender.send((network, change));
+            })
+        });
+    }
+
+    info!("Spawning app state updating thread");
 
     // updates app state
     actix_web::rt::spawn(async move {
-        let mut startup = true;
+        let mut last_full_update = None;
         loop {
-            if startup {
-                startup = false;
-            } else {
-                sleep(Duration::from_secs(UPDATE_APP_STATE_INTERVAL)).await;
+            let changes = next_changes(&mut changes_receiver, &mut last_full_update).await;
+            for (network, network_changes) in changes {
+                info!(network = %network, changes = ?network_changes, "Updating AppState");
+                let cache = match network {
+                    Network::Mainnet => &mut mainnet_cache,
+                    Network::Testnet => &mut testnet_cache,
+                };
+                cache.update_sections(&network_changes).await;
+                let data = cache.get_app_data();
+                let previous = app_state_clone.get_data(&network);
+                live_stream_clone.publish(&network, &previous, &data);
+                app_state_clone.update_data(&network, data);
+                metrics::set_cache_updated(&network);
+                info!(network = %network, "AppState updated");
             }
-            println!("Updating AppState");
-            mainnet_cache.update().await;
-            testnet_cache.update().await;
-            let mainnet = mainnet_cache.get_app_data();
-            let testnet = testnet_cache.get_app_data();
-
-            let mut app_state_lock = app_state_clone.lock().unwrap();
-            app_state_lock.mainnet = mainnet;
-            app_state_lock.testnet = testnet;
-            drop(app_state_lock);
-            println!("AppState updated");
         }
     });
 
-    println!("🚀 Server started successfully");
+    info!("Server started successfully");
 
     HttpServer::new(move || {
         let cors = Cors::default()
@@ -132,8 +203,10 @@ async fn main() -> std::io::Result<()> {
             .max_age(3600);
         App::new()
             .app_data(app_state.clone())
+            .app_data(live_stream.clone())
             .configure(handlers::config)
             .wrap(cors)
+            .wrap(from_fn(request_metrics::record_request))
             .wrap(Logger::default())
             .wrap(middleware::Compress::default())
     })
@@ -0,0 +1,242 @@
+use carmine_api_core::{pool::Pool, types::TradeHistory, utils::same_felt};
+
+use crate::handlers::format_tx;
+
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Points to the last entry of the previous page,
+/// serialized as "{timestamp}_{transaction_hash}"
+#[derive(Debug, PartialEq)]
+pub struct Cursor {
+    pub timestamp: i64,
+    pub transaction_hash: String,
+}
+
+impl Cursor {
+    pub fn parse(raw: &str) -> Result<Self, ()> {
+        let (timestamp, transaction_hash) = match raw.split_once('_') {
+            Some(v) => v,
+            None => return Err(()),
+        };
+        let timestamp = match timestamp.parse::<i64>() {
+            Ok(v) => v,
+            Err(_) => return Err(()),
+        };
+        if transaction_hash.is_empty() {
+            return Err(());
+        }
+        Ok(Cursor {
+            timestamp,
+            transaction_hash: transaction_hash.to_owned(),
+        })
+    }
+
+    fn from_trade(trade: &TradeHistory) -> Self {
+        Cursor {
+            timestamp: trade.timestamp,
+            transaction_hash: trade.transaction_hash.to_owned(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}_{}", self.timestamp, self.transaction_hash)
+    }
+}
+
+#[derive(Default)]
+pub struct TradeHistoryFilter<'a> {
+    pub action: Option<&'a str>,
+    pub pool: Option<&'a Pool>,
+    pub option_address: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+impl<'a> TradeHistoryFilter<'a> {
+    fn matches(&self, trade: &TradeHistory) -> bool {
+        if let Some(action) = self.action {
+            if trade.action != action {
+                return false;
+            }
+        }
+        if let Some(from) = self.from {
+            if trade.timestamp < from {
+                return false;
+            }
+        }
+        if let Some(to) = self.to {
+            if trade.timestamp > to {
+                return false;
+            }
+        }
+        if let Some(option_address) = &self.option_address {
+            match &trade.option {
+                Some(option) if &format_tx(&option.option_address) == option_address => (),
+                _ => return false,
+            }
+        }
+        if let Some(pool) = self.pool {
+            // trades reference pool through the option,
+            // liquidity events only carry the pool description
+            let option_in_pool = match &trade.option {
+                Some(option) => same_felt(&option.lp_address, &pool.address),
+                None => false,
+            };
+            let liquidity_in_pool = match &trade.liquidity_pool {
+                Some(description) => description == &pool.description(),
+                None => false,
+            };
+            if !option_in_pool && !liquidity_in_pool {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+pub struct TradeHistoryPage<'a> {
+    pub data: Vec<&'a TradeHistory>,
+    pub length: usize,
+    pub next_cursor: Option<String>,
+}
+
+fn cursor_key(trade: &TradeHistory) -> (i64, &str) {
+    (trade.timestamp, trade.transaction_hash.as_str())
+}
+
+/// Expects `history` sorted by timestamp and transaction hash.
+/// Events from the same transaction are never split between pages,
+/// so the page can be slightly longer than `limit`.
+pub fn paginate_trade_history<'a>(
+    history: &'a [TradeHistory],
+    filter: &TradeHistoryFilter,
+    cursor: Option<&Cursor>,
+    limit: usize,
+) -> TradeHistoryPage<'a> {
+    let start = match cursor {
+        Some(c) => history.partition_point(|trade| {
+            cursor_key(trade) <= (c.timestamp, c.transaction_hash.as_str())
+        }),
+        None => 0,
+    };
+
+    let length = history.iter().filter(|trade| filter.matches(trade)).count();
+
+    let mut matching = history[start..]
+        .iter()
+        .filter(|trade| filter.matches(trade));
+    let mut data: Vec<&TradeHistory> = vec![];
+    let mut next_cursor = None;
+
+    for trade in matching.by_ref() {
+        let same_transaction = match data.last() {
+            Some(last) => cursor_key(last) == cursor_key(trade),
+            None => false,
+        };
+        if data.len() >= limit && !same_transaction {
+            // there is at least one more entry, point to the last returned
+            next_cursor = data.last().map(|last| Cursor::from_trade(last).encode());
+            break;
+        }
+        data.push(trade);
+    }
+
+    TradeHistoryPage {
+        data,
+        length,
+        next_cursor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carmine_api_core::{
+        network::Network, pool::get_all_pools, test_utils::TradeHistoryBuilder,
+    };
+
+    fn trade(timestamp: i64, transaction_hash: &str, action: &str) -> TradeHistory {
+        TradeHistoryBuilder::new(timestamp, transaction_hash)
+            .action(action)
+            .build()
+    }
+
+    #[test]
+    fn cursor_roundtrip() {
+        let cursor = Cursor::parse("1690000000_0xabc").unwrap();
+        assert_eq!(cursor.timestamp, 1690000000);
+        assert_eq!(cursor.transaction_hash, "0xabc");
+        assert_eq!(cursor.encode(), "1690000000_0xabc");
+        assert!(Cursor::parse("0xabc").is_err());
+        assert!(Cursor::parse("abc_0xabc").is_err());
+        assert!(Cursor::parse("1690000000_").is_err());
+    }
+
+    #[test]
+    fn pages_through_history() {
+        let history = vec![
+            trade(1, "0x1", "TradeOpen"),
+            trade(2, "0x2", "TradeClose"),
+            trade(3, "0x3", "TradeOpen"),
+            trade(4, "0x4", "TradeOpen"),
+        ];
+        let filter = TradeHistoryFilter::default();
+
+        let first = paginate_trade_history(&history, &filter, None, 2);
+        assert_eq!(first.length, 4);
+        assert_eq!(first.data.len(), 2);
+        assert_eq!(first.next_cursor, Some("2_0x2".to_string()));
+
+        let cursor = Cursor::parse(&first.next_cursor.unwrap()).unwrap();
+        let second = paginate_trade_history(&history, &filter, Some(&cursor), 2);
+        assert_eq!(second.data[0].transaction_hash, "0x3");
+        assert_eq!(second.data.len(), 2);
+        assert_eq!(second.next_cursor, None);
+    }
+
+    #[test]
+    fn does_not_split_transaction() {
+        let history = vec![
+            trade(1, "0x1", "TradeOpen"),
+            trade(1, "0x1", "TradeClose"),
+            trade(2, "0x2", "TradeOpen"),
+        ];
+        let page = paginate_trade_history(&history, &TradeHistoryFilter::default(), None, 1);
+        assert_eq!(page.data.len(), 2);
+        assert_eq!(page.next_cursor, Some("1_0x1".to_string()));
+    }
+
+    #[test]
+    fn filters_action_time_and_pool() {
+        let pools = get_all_pools(&Network::Mainnet);
+        let call_pool = &pools[0];
+        let deposit = TradeHistoryBuilder::new(5, "0x5")
+            .action("DepositLiquidity")
+            .liquidity_pool(&call_pool.description())
+            .build();
+        let history = vec![
+            trade(1, "0x1", "TradeOpen"),
+            trade(2, "0x2", "TradeClose"),
+            trade(3, "0x3", "TradeOpen"),
+            deposit,
+        ];
+
+        let filter = TradeHistoryFilter {
+            action: Some("TradeOpen"),
+            from: Some(2),
+            ..Default::default()
+        };
+        let page = paginate_trade_history(&history, &filter, None, 10);
+        assert_eq!(page.length, 1);
+        assert_eq!(page.data[0].transaction_hash, "0x3");
+
+        let filter = TradeHistoryFilter {
+            pool: Some(call_pool),
+            ..Default::default()
+        };
+        let page = paginate_trade_history(&history, &filter, None, 10);
+        assert_eq!(page.length, 1);
+        assert_eq!(page.data[0].action, "DepositLiquidity");
+    }
+}
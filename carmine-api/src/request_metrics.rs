@@ -0,0 +1,26 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    Error,
+};
+use carmine_api_core::metrics;
+use tokio::time::Instant;
+
+/// Records count and latency of every request under its route pattern,
+/// streamed responses are timed until the headers are sent
+pub async fn record_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    // pattern is only known once the request was routed
+    let res = next.call(req).await?;
+    let route = res
+        .request()
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    metrics::observe_http_request(&route, &method, res.status().as_u16(), start.elapsed());
+    Ok(res)
+}
@@ -0,0 +1,277 @@
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use carmine_api_core::{
+    network::Network,
+    pool::{get_all_pools, Pool},
+    types::{AppData, TradeHistory},
+    utils::same_felt,
+};
+use futures_util::{stream, Stream};
+use serde::Serialize;
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::timeout,
+};
+use tracing::warn;
+
+// messages buffered for slow subscribers, older ones are dropped for them
+const CHANNEL_CAPACITY: usize = 1024;
+// comment sent on idle connection so that proxies do not close it
+const KEEP_ALIVE_SECS: u64 = 15;
+
+/// Update pushed to the subscribers, data is serialized once for all of them
+#[derive(Debug, Clone)]
+pub struct StreamMessage {
+    pub network: Network,
+    /// pools the update belongs to, price belongs to every pool of its pair
+    pub pools: Vec<String>,
+    /// "trade", "pool_state" or "price"
+    pub event: &'static str,
+    pub data: String,
+}
+
+impl StreamMessage {
+    fn new<T: Serialize>(
+        network: &Network,
+        pools: Vec<String>,
+        event: &'static str,
+        data: &T,
+    ) -> Option<Self> {
+        let data = serde_json::to_string(data)
+            .map_err(|e| warn!(event, error = %e, "Failed serializing stream update"))
+            .ok()?;
+        Some(StreamMessage {
+            network: *network,
+            pools,
+            event,
+            data,
+        })
+    }
+
+    fn matches(&self, network: &Network, pool: Option<&str>) -> bool {
+        if &self.network != network {
+            return false;
+        }
+        match pool {
+            Some(pool) => self.pools.iter().any(|p| p == pool),
+            None => true,
+        }
+    }
+
+    fn to_sse(&self) -> Bytes {
+        Bytes::from(format!("event: {}\ndata: {}\n\n", self.event, self.data))
+    }
+}
+
+fn trade_pools(pools: &[Pool], trade: &TradeHistory) -> Vec<String> {
+    pools
+        .iter()
+        .filter(|pool| match (&trade.option, &trade.liquidity_pool) {
+            (Some(option), _) => same_felt(&pool.address, &option.lp_address),
+            (None, Some(description)) => &pool.description() == description,
+            (None, None) => false,
+        })
+        .map(|pool| pool.id.to_owned())
+        .collect()
+}
+
+/// What is in `new` snapshot and was not in `old`. Series missing in `old`
+/// are skipped, there is nothing to compare them with.
+pub fn updates(
+    network: &Network,
+    pools: &[Pool],
+    old: &AppData,
+    new: &AppData,
+) -> Vec<StreamMessage> {
+    let mut messages = vec![];
+
+    // trade history is sorted by timestamp and transaction hash
+    if let Some(last) = old.trade_history.last() {
+        let last_key = (last.timestamp, &last.transaction_hash);
+        let trades = new
+            .trade_history
+            .iter()
+            .filter(|t| (t.timestamp, &t.transaction_hash) > last_key);
+        for trade in trades {
+            let pools = trade_pools(pools, trade);
+            messages.extend(StreamMessage::new(network, pools, "trade", trade));
+        }
+    }
+
+    for (pool_id, state) in &new.state {
+        let last_block = match old
+            .state
+            .get(pool_id)
+            .and_then(|s| s.iter().map(|v| v.block_number).max())
+        {
+            Some(v) => v,
+            None => continue,
+        };
+        let mut rows: Vec<_> = state
+            .iter()
+            .filter(|v| v.block_number > last_block)
+            .collect();
+        rows.sort_by_key(|v| v.block_number);
+        for row in rows {
+            let pools = vec![pool_id.to_owned()];
+            messages.extend(StreamMessage::new(network, pools, "pool_state", row));
+        }
+    }
+
+    for (pair_id, prices) in &new.oracle_prices {
+        let last_block = match old
+            .oracle_prices
+            .get(pair_id)
+            .and_then(|p| p.iter().map(|v| v.block_number).max())
+        {
+            Some(v) => v,
+            None => continue,
+        };
+        let mut rows: Vec<_> = prices
+            .iter()
+            .filter(|v| v.block_number > last_block)
+            .collect();
+        rows.sort_by_key(|v| v.block_number);
+        let pair_pools: Vec<String> = pools
+            .iter()
+            .filter(|pool| &pool.pair_id() == pair_id)
+            .map(|pool| pool.id.to_owned())
+            .collect();
+        for row in rows {
+            messages.extend(StreamMessage::new(
+                network,
+                pair_pools.clone(),
+                "price",
+                row,
+            ));
+        }
+    }
+
+    messages
+}
+
+/// Fans out new trades, pool states and prices to the stream subscribers
+pub struct LiveStream {
+    sender: broadcast::Sender<StreamMessage>,
+}
+
+impl Default for LiveStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveStream {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        LiveStream { sender }
+    }
+
+    /// Publishes what is new in the network's `new` snapshot compared to `old`
+    pub fn publish(&self, network: &Network, old: &AppData, new: &AppData) {
+        let pools = get_all_pools(network);
+        for message in updates(network, &pools, old, new) {
+            // fails only when nobody is subscribed
+            let _ = self.sender.send(message);
+        }
+    }
+
+    /// Server-Sent Events of the network, only of the pool if given
+    pub fn subscribe(
+        &self,
+        network: Network,
+        pool: Option<String>,
+    ) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+        let receiver = self.sender.subscribe();
+
+        stream::unfold((receiver, pool), move |(mut receiver, pool)| async move {
+            loop {
+                let chunk =
+                    match timeout(Duration::from_secs(KEEP_ALIVE_SECS), receiver.recv()).await {
+                        Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+                        Ok(Ok(message)) if message.matches(&network, pool.as_deref()) => {
+                            message.to_sse()
+                        }
+                        Ok(Ok(_)) => continue,
+                        // client was too slow and missed updates, it should fetch the full endpoints
+                        Ok(Err(RecvError::Lagged(skipped))) => {
+                            Bytes::from(format!("event: lagged\ndata: {}\n\n", skipped))
+                        }
+                        Ok(Err(RecvError::Closed)) => return None,
+                    };
+                return Some((Ok(chunk), (receiver, pool)));
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carmine_api_core::{
+        test_utils::{PoolStateBuilder, TradeHistoryBuilder},
+        types::PoolStateWithTimestamp,
+    };
+    use std::collections::HashMap;
+
+    fn trade(timestamp: i64, transaction_hash: &str) -> TradeHistory {
+        TradeHistoryBuilder::new(timestamp, transaction_hash).build()
+    }
+
+    fn state(block_number: i64) -> PoolStateWithTimestamp {
+        PoolStateBuilder::new(block_number).build()
+    }
+
+    fn app_data(
+        trade_history: Vec<TradeHistory>,
+        state: HashMap<String, Vec<PoolStateWithTimestamp>>,
+    ) -> AppData {
+        AppData {
+            all_non_expired: vec![],
+            trade_history,
+            option_volatility: vec![],
+            state,
+            oracle_prices: HashMap::new(),
+            apy: HashMap::new(),
+            state_buckets: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn publishes_only_new_rows() {
+        let old = app_data(
+            vec![trade(1, "0xa"), trade(2, "0xb")],
+            HashMap::from([("eth-usdc-call".to_string(), vec![state(10)])]),
+        );
+        let new = app_data(
+            vec![trade(1, "0xa"), trade(2, "0xb"), trade(2, "0xc")],
+            HashMap::from([
+                (
+                    "eth-usdc-call".to_string(),
+                    vec![state(12), state(10), state(11)],
+                ),
+                // not in the old snapshot
+                ("eth-usdc-put".to_string(), vec![state(12)]),
+            ]),
+        );
+
+        let messages = updates(&Network::Mainnet, &[], &old, &new);
+        let events: Vec<&str> = messages.iter().map(|m| m.event).collect();
+        assert_eq!(events, vec!["trade", "pool_state", "pool_state"]);
+        assert!(messages[0].data.contains("0xc"));
+        assert!(messages[1].data.contains("\"block_number\":11"));
+        assert!(messages[1].matches(&Network::Mainnet, Some("eth-usdc-call")));
+        assert!(!messages[1].matches(&Network::Testnet, None));
+        assert!(!messages[0].matches(&Network::Mainnet, Some("eth-usdc-call")));
+
+        // nothing to compare with
+        assert!(updates(
+            &Network::Mainnet,
+            &[],
+            &app_data(vec![], HashMap::new()),
+            &new
+        )
+        .is_empty());
+    }
+}
@@ -1,13 +1,16 @@
-use carmine_api_core::types::{Event, TradeHistory};
+use carmine_api_core::types::{Event, PoolStateBucket, PoolStateWithTimestamp, TradeHistory};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Serialize)]
-pub struct GenericResponse {
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
     pub status: String,
+    /// machine readable, see `ApiError::code`
+    pub code: String,
     pub message: String,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, ToSchema)]
 pub struct DataResponse<T> {
     pub status: String,
     pub data: T,
@@ -25,20 +28,83 @@ pub struct TradeHistoryResponse<'a> {
     pub data: Vec<&'a TradeHistory>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, ToSchema)]
 pub struct AllTradeHistoryResponse<'a> {
     pub status: String,
     pub data: Vec<&'a TradeHistory>,
     pub length: usize,
 }
 
+#[derive(Serialize, Debug, ToSchema)]
+pub struct PaginatedTradeHistoryResponse<'a> {
+    pub status: String,
+    pub data: Vec<&'a TradeHistory>,
+    pub length: usize,
+    pub next_cursor: Option<String>,
+}
+
+/// Pool state history, bucketed when `interval` is given
+#[derive(Serialize, Debug, ToSchema)]
+#[serde(untagged)]
+pub enum PoolStateHistory<'a> {
+    States(Vec<&'a PoolStateWithTimestamp>),
+    Buckets(Vec<PoolStateBucket>),
+}
+
 #[derive(Serialize)]
 pub struct EventsResponse {
     pub status: String,
     pub events: Vec<Event>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct QueryOptions {
+    /// user address
     pub address: Option<String>,
 }
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TradeHistoryQuery {
+    /// `next_cursor` from the previous page
+    pub cursor: Option<String>,
+    /// page size, defaults to 100, max 1000
+    pub limit: Option<usize>,
+    /// eg. `TradeOpen`, `TradeClose`, `TradeSettle`, `DepositLiquidity`, `WithdrawLiquidity`
+    pub action: Option<String>,
+    /// pool id, eg. `eth-usdc-call`
+    pub pool: Option<String>,
+    /// option address
+    pub option: Option<String>,
+    /// timestamp range (inclusive)
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct PoolPath {
+    /// pool id, eg. `eth-usdc-call`
+    pub pool: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct PairPath {
+    /// token pair id, eg. `eth-usdc`
+    pub pair_id: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PoolStateQuery {
+    /// timestamp range (inclusive)
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    /// bucket size, eg. `15m`, `1h`, `1d`, `1w`
+    pub interval: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct StreamQuery {
+    /// pool id, eg. `eth-usdc-call`, only updates of this pool and prices of its pair
+    pub pool: Option<String>,
+}